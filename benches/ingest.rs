@@ -0,0 +1,92 @@
+//! # Ingest Benchmark
+//!
+//! `ingest` is a `criterion` benchmark of ingesting a synthetic deposit-heavy feed of a million
+//! rows, run with `cargo bench`.
+//!
+//! The feed is generated deterministically, so runs are comparable across changes. The submission
+//! of its transactions once parsed, with and without capacity reserved up front, and the ingestion
+//! of its CSV are measured.
+
+use std::{fmt::Write, hint::black_box, time::Duration};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use coding_test::{AccountsHandler, Amount, ClientId, Transaction, TransactionType, Txid};
+use rust_decimal::Decimal;
+
+/// Number of rows of the feed
+const ROWS: u64 = 1_000_000;
+
+/// Number of distinct clients of the feed
+const CLIENTS: u64 = 1_000;
+
+/// Generate the feed, where every tenth transaction is a withdrawal and the rest are deposits
+fn feed() -> Vec<Transaction> {
+    (1..=ROWS)
+        .map(|txid| {
+            let amount =
+                Amount::new(Decimal::new(i64::try_from(txid % 10_000).unwrap() + 1, 2)).unwrap();
+            Transaction {
+                tx_type: if txid % 10 == 0 {
+                    TransactionType::Withdrawal(amount)
+                } else {
+                    TransactionType::Deposit(amount)
+                },
+                client_id: ClientId(u16::try_from(txid % CLIENTS).unwrap()),
+                txid: Txid(txid),
+                timestamp: None,
+            }
+        })
+        .collect()
+}
+
+/// Format the feed as CSV with a header row
+fn feed_csv(feed: &[Transaction]) -> String {
+    let mut csv = String::from("type,client,tx,amount\n");
+    for tx in feed {
+        let (keyword, amount) = match tx.tx_type {
+            TransactionType::Deposit(amount) => ("deposit", amount),
+            TransactionType::Withdrawal(amount) => ("withdrawal", amount),
+            _ => unreachable!("the feed only has deposits and withdrawals"),
+        };
+        writeln!(csv, "{},{},{},{}", keyword, tx.client_id, tx.txid, amount).unwrap();
+    }
+    csv
+}
+
+/// Measure the ingestion of the feed
+fn ingest(c: &mut Criterion) {
+    let feed = feed();
+    let csv = feed_csv(&feed);
+
+    let mut group = c.benchmark_group("ingest");
+    // every sample ingests the whole feed, so few samples suffice
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(20))
+        .throughput(Throughput::Elements(ROWS));
+    group.bench_function("submit", |b| {
+        b.iter(|| {
+            let mut handler = AccountsHandler::new();
+            handler.extend(feed.iter().copied());
+            black_box(handler)
+        })
+    });
+    group.bench_function("reserved", |b| {
+        b.iter(|| {
+            let mut handler = AccountsHandler::with_capacity(CLIENTS as usize, ROWS as usize);
+            handler.extend(feed.iter().copied());
+            black_box(handler)
+        })
+    });
+    group.bench_function("csv", |b| {
+        b.iter(|| {
+            let mut handler = AccountsHandler::new();
+            black_box(handler.ingest_csv(csv.as_bytes()).unwrap())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, ingest);
+criterion_main!(benches);
@@ -2,23 +2,102 @@
 //!
 //! `account` is a module providing functionality for operating on a single account.
 
+use log::{debug, warn};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{ClientId, Transaction, TransactionType, Txid};
+use crate::{
+    Amount, ClientId, DisputePolicy, HandlerConfig, IgnoreReason, InvariantError, LockedPolicy,
+    ProcessOutcome, RepeatDepositPolicy, ScalePolicy, Transaction, TransactionError,
+    TransactionType, Txid, WithdrawalMode,
+};
 
-/// Deposit record
-pub(crate) struct DepositRecord {
-    /// Deposit amount
+/// Minimum number of decimal places balances are reported with by default, which is raised to the
+/// maximum scale of amounts if larger
+pub const DEFAULT_OUTPUT_SCALE: u32 = 4;
+
+/// Kind of a recorded transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum TxKind {
+    /// Funds credited to the account
+    Deposit,
+    /// Funds debited from the account
+    Withdrawal,
+}
+
+/// Record of a disputable transaction
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct TxRecord {
+    /// Kind of transaction
+    kind: TxKind,
+    /// Transaction amount
     amount: Decimal,
     /// Disputed
     disputed: bool,
+    /// Amount held by the current dispute, zero if not disputed
+    held_amount: Decimal,
+    /// Charged back, after which the transaction can no longer be disputed
+    charged_back: bool,
+    /// Reversed by an operator, after which the transaction can no longer be disputed
+    reversed: bool,
+    /// Sequence number of the submission that opened the current dispute
+    disputed_at: u64,
+}
+
+impl TxRecord {
+    /// Create a record of an undisputed transaction
+    fn new(kind: TxKind, amount: Decimal) -> Self {
+        Self {
+            kind,
+            amount,
+            disputed: false,
+            held_amount: Decimal::ZERO,
+            charged_back: false,
+            reversed: false,
+            disputed_at: 0,
+        }
+    }
+}
+
+/// Status of an account, telling apart the reasons it may be locked
+///
+/// Statuses are ordered from least to most restrictive. [`AccountSnapshot::locked`] is set for
+/// every status but [`AccountStatus::Active`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Deserialize, Serialize,
+)]
+pub enum AccountStatus {
+    /// Account takes every transaction
+    #[default]
+    Active,
+    /// Account was frozen by an operator, and is unlocked by unfreezing or unlocking it
+    Frozen,
+    /// Account was locked by a chargeback, or seeded locked, and is only unlocked by an operator
+    /// unlocking it
+    ChargebackLocked,
+}
+
+/// Transaction applied to an account, along with the balances it resulted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AppliedEvent {
+    /// Transaction's ID
+    pub txid: Txid,
+    /// Type of the transaction, where a transfer appears in the histories of both the sender and
+    /// the recipient
+    pub kind: TransactionType,
+    /// Available balance after the transaction was applied
+    pub available: Decimal,
+    /// Held balance after the transaction was applied
+    pub held: Decimal,
 }
 
 /// Serializable snapshot of the client's account
-#[derive(Debug, PartialEq, Eq, Hash, Serialize)]
+///
+/// Snapshots compare and hash balances by numeric value, so balances of different scales, e.g.
+/// `2` and `2.00`, are equal, although they serialize differently.
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct AccountSnapshot {
     /// Client's ID
     #[serde(rename = "client")]
@@ -31,148 +110,1215 @@ pub struct AccountSnapshot {
     pub total: Decimal,
     /// Locked status
     pub locked: bool,
+    /// Number of transactions currently under dispute
+    pub disputed_count: usize,
+}
+
+impl AccountSnapshot {
+    /// Create a snapshot, computing the total from the available and held balances
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total overflows.
+    ///
+    /// ```
+    /// use coding_test::{
+    ///     AccountSnapshot, AccountsHandler, Amount, ClientId, Transaction, TransactionType, Txid,
+    /// };
+    /// use rust_decimal::Decimal;
+    ///
+    /// let mut accounts = AccountsHandler::new();
+    /// accounts
+    ///     .submit_transaction(Transaction {
+    ///         tx_type: TransactionType::Deposit(Amount::new(Decimal::new(15, 1)).unwrap()),
+    ///         client_id: ClientId(1),
+    ///         txid: Txid(1),
+    ///         timestamp: None,
+    ///     })
+    ///     .unwrap();
+    ///
+    /// let snapshot = accounts.snapshot(ClientId(1)).unwrap();
+    /// assert_eq!(snapshot.available, Decimal::new(15, 1));
+    /// assert_eq!(snapshot.total, Decimal::new(15, 1));
+    /// assert!(!snapshot.locked);
+    /// assert_eq!(
+    ///     snapshot,
+    ///     AccountSnapshot::new(ClientId(1), Decimal::new(15, 1), Decimal::ZERO, false, 0)
+    /// );
+    /// ```
+    pub fn new(
+        id: ClientId,
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+        disputed_count: usize,
+    ) -> Self {
+        Self {
+            id,
+            available,
+            held,
+            total: available + held,
+            locked,
+            disputed_count,
+        }
+    }
+
+    /// Check that the snapshot's total is its available plus held balance, e.g. after deserializing
+    /// it from external input
+    pub fn validate(&self) -> Result<(), TransactionError> {
+        if self.available.checked_add(self.held) != Some(self.total) {
+            return Err(TransactionError::InconsistentTotal(self.id));
+        }
+        Ok(())
+    }
+
+    /// Reconstruct an account with the snapshot's balances and locked status
+    ///
+    /// The snapshot carries no transaction records, so the account is seeded as with
+    /// [`crate::AccountsHandler::seed_account`], and disputes in flight cannot be settled.
+    pub(crate) fn try_into_account(
+        &self,
+        config: &HandlerConfig,
+    ) -> Result<Account, TransactionError> {
+        if self.available < Decimal::ZERO || self.held < Decimal::ZERO {
+            return Err(TransactionError::NegativeSeed(self.id));
+        }
+        self.validate()?;
+        Ok(Account::seeded(
+            self.id,
+            config,
+            self.available,
+            self.held,
+            self.locked,
+        ))
+    }
+
+    /// Convert into a snapshot with its balances formatted to exactly `scale` decimal places
+    ///
+    /// Balances are rounded half to even, as when outputting accounts.
+    pub fn to_string_snapshot(&self, scale: u32) -> StringSnapshot {
+        let format = |balance| round_output(balance, scale).to_string();
+        StringSnapshot {
+            id: self.id,
+            available: format(self.available),
+            held: format(self.held),
+            total: format(self.total),
+            locked: self.locked,
+            disputed_count: self.disputed_count,
+        }
+    }
+}
+
+/// Serializable snapshot of the client's account with balances as strings at a fixed scale, for
+/// consumers that cannot handle decimals
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct StringSnapshot {
+    /// Client's ID
+    #[serde(rename = "client")]
+    pub id: ClientId,
+    /// Available balance
+    pub available: String,
+    /// Held balance
+    pub held: String,
+    /// Total balance
+    pub total: String,
+    /// Locked status
+    pub locked: bool,
+    /// Number of transactions currently under dispute
+    pub disputed_count: usize,
 }
 
 /// Client's account
+#[derive(Clone, Deserialize, Serialize)]
 pub(crate) struct Account {
     /// Client's ID
     id: ClientId,
-    /// Record of deposits as a map of transaction IDs to deposit records
-    deposits: HashMap<Txid, DepositRecord>,
+    /// Record of deposits and withdrawals as a map of transaction IDs to transaction records
+    records: HashMap<Txid, TxRecord>,
     /// Available balance
     available: Decimal,
-    /// Held balance
+    /// Held balance, holding disputed deposits out of available and disputed withdrawals on top
+    /// of it as potential clawbacks
     held: Decimal,
-    /// Locked status
-    locked: bool,
+    /// Status, locked unless active
+    status: AccountStatus,
+    /// History of applied transactions, if enabled
+    history: Option<Vec<AppliedEvent>>,
+    /// Policy for disputes exceeding the available balance
+    dispute_policy: DisputePolicy,
+    /// Policy for transactions submitted while locked
+    locked_policy: LockedPolicy,
+    /// Maximum number of decimal places accepted in amounts
+    max_scale: u32,
+    /// Held balance the account was seeded with, which is not tied to any dispute
+    seeded_held: Decimal,
+    /// Policy for rescaling balances after each applied transaction, if any
+    scale_policy: Option<ScalePolicy>,
+    /// Fee debited on top of the amount of each withdrawal
+    withdrawal_fee: Decimal,
+    /// Sum of the withdrawal fees collected from the account
+    collected_fees: Decimal,
+    /// Sum of the funds ever credited to the account by deposits, transfers, seeding and interest,
+    /// which the held balance may never exceed
+    lifetime_deposited: Decimal,
+    /// Maximum available balance deposits and transfers may credit up to, if capped
+    max_available: Option<Decimal>,
+    /// Minimum available balance withdrawals and transfers may debit down to
+    min_available: Decimal,
+    /// Mode of withdrawals exceeding the available balance
+    withdrawal_mode: WithdrawalMode,
+    /// Maximum number of records retained, if bounded
+    max_records: Option<usize>,
+    /// Txids of the records in insertion order, tracked only if the number of records is bounded
+    record_order: VecDeque<Txid>,
 }
 
 impl Account {
-    /// Create a new empty account
+    /// Create a new empty account, accepting amounts of any precision
     pub(crate) fn new(id: ClientId) -> Self {
         Self {
             id,
-            deposits: HashMap::new(),
+            records: HashMap::new(),
             available: Decimal::ZERO,
             held: Decimal::ZERO,
-            locked: false,
+            status: AccountStatus::Active,
+            history: None,
+            dispute_policy: DisputePolicy::default(),
+            locked_policy: LockedPolicy::default(),
+            max_scale: u32::MAX,
+            seeded_held: Decimal::ZERO,
+            scale_policy: None,
+            withdrawal_fee: Decimal::ZERO,
+            collected_fees: Decimal::ZERO,
+            lifetime_deposited: Decimal::ZERO,
+            max_available: None,
+            min_available: Decimal::ZERO,
+            withdrawal_mode: WithdrawalMode::default(),
+            max_records: None,
+            record_order: VecDeque::new(),
+        }
+    }
+
+    /// Create a new empty account following the given policy
+    pub(crate) fn with_config(id: ClientId, config: &HandlerConfig) -> Self {
+        Self {
+            history: config.history.then(Vec::new),
+            dispute_policy: config.dispute_policy,
+            locked_policy: config.locked_policy,
+            max_scale: config.max_scale,
+            scale_policy: config.scale_policy,
+            withdrawal_fee: config.withdrawal_fee,
+            max_available: config.max_available,
+            min_available: config.min_available,
+            withdrawal_mode: config.withdrawal_mode,
+            max_records: config.max_records_per_account,
+            ..Self::new(id)
+        }
+    }
+
+    /// Create an account seeded with opening balances, without any transaction records
+    pub(crate) fn seeded(
+        id: ClientId,
+        config: &HandlerConfig,
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+    ) -> Self {
+        let account = Self::with_config(id, config);
+        let held = account.rescale(held);
+        let available = account.rescale(available);
+        Self {
+            available,
+            held,
+            status: if locked {
+                AccountStatus::ChargebackLocked
+            } else {
+                AccountStatus::Active
+            },
+            seeded_held: held,
+            lifetime_deposited: available.saturating_add(held),
+            ..account
         }
     }
 
-    /// Process a transaction
-    pub(crate) fn process(&mut self, tx: Transaction) {
+    /// Process a transaction submitted with sequence number `sequence`
+    ///
+    /// A locked account ignores every transaction except resolves and chargebacks, so that
+    /// disputes already in flight when the account was locked can still settle.
+    pub(crate) fn process(
+        &mut self,
+        tx: Transaction,
+        sequence: u64,
+    ) -> Result<ProcessOutcome, TransactionError> {
         // reject if client id does not match
         if tx.client_id != self.id {
-            return;
+            return Err(TransactionError::ClientMismatch {
+                expected: self.id,
+                got: tx.client_id,
+            });
         }
         use TransactionType::*;
-        match tx.tx_type {
-            Deposit(amount) => self.deposit(tx.txid, amount),
-            Withdrawal(amount) => self.withdraw(amount),
-            Dispute => self.dispute(tx.txid),
-            Resolve => self.resolve(tx.txid),
+        // reject a deposit or withdrawal reusing the txid of one still recorded, e.g. once the
+        // handler forgot the txid, as it would overwrite the record
+        if matches!(tx.tx_type, Deposit(_) | Withdrawal(_)) && self.has_record(tx.txid) {
+            return Err(TransactionError::DuplicateTxid(tx.txid));
+        }
+        // once locked, only settling disputes already in flight is permitted
+        if self.is_locked() && !matches!(tx.tx_type, Resolve | PartialResolve(_) | Chargeback) {
+            return self.locked_outcome();
+        }
+        let outcome = match tx.tx_type {
+            Deposit(amount) => self.deposit(tx.txid, amount.value()),
+            Withdrawal(amount) => self.withdraw(tx.txid, amount.value()),
+            Transfer { amount, .. } => self.debit(amount, Decimal::ZERO),
+            Dispute => self.dispute(tx.txid, None),
+            PartialDispute(amount) => self.dispute(tx.txid, Some(amount)),
+            Resolve => self.resolve(tx.txid, None),
+            PartialResolve(amount) => self.resolve(tx.txid, Some(amount)),
             Chargeback => self.chargeback(tx.txid),
+        }?;
+        if outcome.is_applied() {
+            self.apply_scale_policy();
+            if matches!(tx.tx_type, Dispute | PartialDispute(_)) {
+                if let Some(record) = self.records.get_mut(&tx.txid) {
+                    record.disputed_at = sequence;
+                }
+            }
+            // a partial withdrawal is recorded for the amount actually withdrawn, which is
+            // positive
+            match outcome {
+                ProcessOutcome::PartiallyApplied(amount) => self.record(Transaction {
+                    tx_type: Withdrawal(Amount(amount)),
+                    ..tx
+                }),
+                _ => self.record(tx),
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Resolve every dispute opened more than `max_age` submissions before sequence number
+    /// `sequence`, returning the number of disputes resolved
+    pub(crate) fn resolve_stale(
+        &mut self,
+        sequence: u64,
+        max_age: u64,
+    ) -> Result<usize, TransactionError> {
+        let mut stale: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, r)| r.disputed && sequence.saturating_sub(r.disputed_at) > max_age)
+            .map(|(txid, r)| (r.disputed_at, *txid))
+            .collect();
+        // resolve in the order the disputes were opened
+        stale.sort_unstable();
+        let mut resolved = 0;
+        for (_, txid) in stale {
+            let tx = Transaction {
+                tx_type: TransactionType::Resolve,
+                client_id: self.id,
+                txid,
+                timestamp: None,
+            };
+            if self.process(tx, sequence)? == ProcessOutcome::Applied {
+                resolved += 1;
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Resolve every open dispute in the order they were opened, with sequence number `sequence`,
+    /// returning the number of disputes resolved
+    ///
+    /// Disputes that cannot be resolved, e.g. as their funds would overflow, stay open.
+    pub(crate) fn resolve_all(&mut self, sequence: u64) -> usize {
+        let mut disputed: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, r)| r.disputed)
+            .map(|(txid, r)| (r.disputed_at, *txid))
+            .collect();
+        disputed.sort_unstable();
+        let txids = disputed.into_iter().map(|(_, txid)| txid).collect();
+        self.process_each(TransactionType::Resolve, txids, sequence)
+    }
+
+    /// Dispute every deposit not already under dispute in order of txid, with sequence number
+    /// `sequence`, returning the number of deposits disputed
+    ///
+    /// Deposits that cannot be disputed, e.g. for insufficient available funds, are skipped.
+    pub(crate) fn dispute_all_deposits(&mut self, sequence: u64) -> usize {
+        let mut txids: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, r)| r.kind == TxKind::Deposit && !r.disputed)
+            .map(|(txid, _)| *txid)
+            .collect();
+        txids.sort_unstable();
+        self.process_each(TransactionType::Dispute, txids, sequence)
+    }
+
+    /// Process a transaction of type `tx_type` for each of `txids` with sequence number
+    /// `sequence`, returning the number applied
+    fn process_each(&mut self, tx_type: TransactionType, txids: Vec<Txid>, sequence: u64) -> usize {
+        txids
+            .into_iter()
+            .filter(|&txid| {
+                let tx = Transaction {
+                    tx_type,
+                    client_id: self.id,
+                    txid,
+                    timestamp: None,
+                };
+                self.process(tx, sequence) == Ok(ProcessOutcome::Applied)
+            })
+            .count()
+    }
+
+    /// Record an applied transaction in the history, if enabled
+    fn record(&mut self, tx: Transaction) {
+        if let Some(history) = &mut self.history {
+            history.push(AppliedEvent {
+                txid: tx.txid,
+                kind: tx.tx_type,
+                available: self.available,
+                held: self.held,
+            });
+        }
+    }
+
+    /// Fold the balances, records and history of `other`, another account of the same client, into
+    /// this one, which is locked if either account was
+    ///
+    /// Fails without modifying the account if both accounts record the same txid or a combined
+    /// balance cannot be represented.
+    pub(crate) fn merge(&mut self, other: Account) -> Result<(), TransactionError> {
+        if let Some(txid) = other
+            .records
+            .keys()
+            .find(|txid| self.records.contains_key(txid))
+        {
+            return Err(TransactionError::DuplicateTxid(*txid));
+        }
+        let available = checked_add(self.id, self.available, other.available)?;
+        let held = checked_add(self.id, self.held, other.held)?;
+        let seeded_held = checked_add(self.id, self.seeded_held, other.seeded_held)?;
+        let collected_fees = checked_add(self.id, self.collected_fees, other.collected_fees)?;
+
+        self.available = available;
+        self.held = held;
+        self.seeded_held = seeded_held;
+        self.collected_fees = collected_fees;
+        self.lifetime_deposited = self
+            .lifetime_deposited
+            .saturating_add(other.lifetime_deposited);
+        self.status = self.status.max(other.status);
+        self.records.extend(other.records);
+        if self.max_records.is_some() {
+            self.record_order.extend(other.record_order);
+            self.evict_records();
+        }
+        if let (Some(history), Some(other_history)) = (&mut self.history, other.history) {
+            history.extend(other_history);
         }
+        Ok(())
+    }
+
+    /// Get the txids of the disputed deposits and withdrawals in ascending order, along with the
+    /// amount each dispute holds
+    pub(crate) fn disputed_breakdown(&self) -> Vec<(Txid, Decimal)> {
+        let mut breakdown: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.disputed)
+            .map(|(txid, record)| (*txid, record.held_amount))
+            .collect();
+        breakdown.sort_unstable_by_key(|(txid, _)| *txid);
+        breakdown
+    }
+
+    /// Get the kind of the transaction with `txid` recorded on the account, if any
+    pub(crate) fn record_kind(&self, txid: Txid) -> Option<TxKind> {
+        self.records.get(&txid).map(|record| record.kind)
+    }
+
+    /// Check whether the deposit or withdrawal with `txid` is recorded on the account
+    pub(crate) fn has_record(&self, txid: Txid) -> bool {
+        self.records.contains_key(&txid)
+    }
+
+    /// Get the history of applied transactions, if enabled
+    pub(crate) fn history(&self) -> Option<&[AppliedEvent]> {
+        self.history.as_deref()
     }
 
     /// Deposit funds into the account
-    fn deposit(&mut self, txid: Txid, amount: Decimal) {
-        // if negative amount, ignore
-        if amount.is_sign_negative() {
-            return;
+    fn deposit(&mut self, txid: Txid, amount: Decimal) -> Result<ProcessOutcome, TransactionError> {
+        // if non-positive amount, ignore
+        if amount <= Decimal::ZERO {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount));
+        }
+        // if too precise amount, ignore
+        if amount.scale() > self.max_scale {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision));
         }
+        let available = self.checked_credit(amount)?;
         // record deposit
-        self.deposits.insert(
-            txid,
-            DepositRecord {
-                amount,
-                disputed: false,
-            },
-        );
-        self.available += amount;
+        self.insert_record(txid, TxRecord::new(TxKind::Deposit, amount));
+        self.available = available;
+        self.lifetime_deposited = self.lifetime_deposited.saturating_add(amount);
+        Ok(ProcessOutcome::Applied)
     }
 
-    /// Withdraw funds from the account
-    fn withdraw(&mut self, amount: Decimal) {
-        // if locked, disallow withdrawals
-        if self.locked {
-            return;
+    /// Withdraw funds from the account, collecting the withdrawal fee on top
+    ///
+    /// Only the withdrawn amount is recorded, so disputing the withdrawal leaves the fee collected.
+    /// Under [`WithdrawalMode::Saturating`], an amount exceeding the available balance is reduced
+    /// to what is available.
+    fn withdraw(
+        &mut self,
+        txid: Txid,
+        requested: Decimal,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        let amount = match self.withdrawal_mode {
+            WithdrawalMode::Strict => requested,
+            WithdrawalMode::Saturating => self.fillable(requested)?,
+        };
+        // fail before debiting if the fee cannot be collected
+        let collected_fees = checked_add(self.id, self.collected_fees, self.withdrawal_fee)?;
+        let outcome = self.debit(amount, self.withdrawal_fee)?;
+        if outcome == ProcessOutcome::Applied {
+            self.collected_fees = collected_fees;
+            // record withdrawal
+            self.insert_record(txid, TxRecord::new(TxKind::Withdrawal, amount));
+            if amount < requested {
+                return Ok(ProcessOutcome::PartiallyApplied(amount));
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Compute the part of a withdrawal of `requested` that the available balance can fill after
+    /// the fee, down to the minimum available balance if positive and truncated to the maximum
+    /// scale
+    ///
+    /// Invalid amounts are left for [`Account::debit`] to ignore, as are those leaving nothing
+    /// to withdraw.
+    fn fillable(&self, requested: Decimal) -> Result<Decimal, TransactionError> {
+        if requested <= Decimal::ZERO || requested.scale() > self.max_scale {
+            return Ok(requested);
+        }
+        let floor = checked_add(
+            self.id,
+            self.withdrawal_fee,
+            self.min_available.max(Decimal::ZERO),
+        )?;
+        let fillable = checked_sub(self.id, self.available, floor)?
+            .round_dp_with_strategy(self.max_scale, rust_decimal::RoundingStrategy::ToZero);
+        if fillable <= Decimal::ZERO || fillable >= requested {
+            return Ok(requested);
+        }
+        Ok(fillable)
+    }
+
+    /// Record a deposit or withdrawal, evicting the oldest undisputed record if the number of
+    /// records is bounded and exceeded
+    fn insert_record(&mut self, txid: Txid, record: TxRecord) {
+        self.records.insert(txid, record);
+        if self.max_records.is_some() {
+            self.record_order.push_back(txid);
+            self.evict_records();
         }
-        // if negative amount, ignore
-        if amount.is_sign_negative() {
+    }
+
+    /// Evict the oldest undisputed records beyond the maximum number of records, if bounded
+    fn evict_records(&mut self) {
+        let Some(max_records) = self.max_records else {
             return;
+        };
+        while self.records.len() > max_records {
+            // disputed records must be kept so that their disputes can settle
+            let oldest = self.record_order.iter().position(|txid| {
+                self.records
+                    .get(txid)
+                    .is_some_and(|record| !record.disputed)
+            });
+            let Some(txid) = oldest.and_then(|i| self.record_order.remove(i)) else {
+                return;
+            };
+            self.records.remove(&txid);
+        }
+    }
+
+    /// Debit funds along with `fee` from the available balance, following the rules for
+    /// withdrawals
+    fn debit(&mut self, amount: Decimal, fee: Decimal) -> Result<ProcessOutcome, TransactionError> {
+        // if non-positive amount, ignore
+        if amount <= Decimal::ZERO {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount));
         }
-        let new_balance = self.available - amount;
+        // if too precise amount, ignore
+        if amount.scale() > self.max_scale {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision));
+        }
+        let debited = checked_add(self.id, amount, fee)?;
+        let new_balance = checked_sub(self.id, self.available, debited)?;
         // if insufficient funds, ignore
         if new_balance.is_sign_negative() {
-            return;
+            warn!(
+                "client {}: debit of {} exceeds available balance {}",
+                self.id, debited, self.available
+            );
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds));
+        }
+        // if below the minimum balance, reject
+        if new_balance < self.min_available {
+            return Err(TransactionError::MinimumBalanceBreach { client_id: self.id });
         }
         self.available = new_balance;
+        Ok(ProcessOutcome::Applied)
+    }
+
+    /// Check that `amount` can be credited to the available balance without overflowing or
+    /// exceeding the cap
+    pub(crate) fn check_credit(&self, amount: Decimal) -> Result<(), TransactionError> {
+        self.checked_credit(amount).map(|_| ())
+    }
+
+    /// Compute the available balance after crediting `amount`, failing if it would overflow or
+    /// exceed the cap
+    fn checked_credit(&self, amount: Decimal) -> Result<Decimal, TransactionError> {
+        let available = checked_add(self.id, self.available, amount)?;
+        if self.max_available.is_some_and(|cap| available > cap) {
+            return Err(TransactionError::BalanceCapExceeded { client_id: self.id });
+        }
+        Ok(available)
+    }
+
+    /// Credit funds of transfer `tx` from another account to the available balance
+    ///
+    /// Unlike deposits, transferred funds are not recorded and thus cannot be disputed.
+    pub(crate) fn credit(
+        &mut self,
+        tx: Transaction,
+        amount: Decimal,
+    ) -> Result<(), TransactionError> {
+        self.available = self.checked_credit(amount)?;
+        self.lifetime_deposited = self.lifetime_deposited.saturating_add(amount);
+        self.apply_scale_policy();
+        self.record(tx);
+        Ok(())
+    }
+
+    /// Compute the interest accrued on the available balance at `rate`, failing if crediting it
+    /// would overflow or exceed the cap
+    ///
+    /// The interest is rounded following the scale policy if any, or otherwise half to even to the
+    /// maximum scale of amounts, so sub-cent interest on small balances may round to zero. Locked
+    /// accounts and non-positive balances accrue none.
+    pub(crate) fn interest(&self, rate: Decimal) -> Result<Decimal, TransactionError> {
+        if self.is_locked() || self.available <= Decimal::ZERO {
+            return Ok(Decimal::ZERO);
+        }
+        let interest = self
+            .available
+            .checked_mul(rate)
+            .ok_or(TransactionError::BalanceOverflow { client_id: self.id })?;
+        let interest = match self.scale_policy {
+            Some(policy) => policy.apply(interest),
+            None => interest.round_dp(self.max_scale),
+        };
+        self.check_credit(interest)?;
+        Ok(interest)
+    }
+
+    /// Credit `interest` computed by [`Account::interest`] to the available balance
+    ///
+    /// Interest has no txid and thus is neither recorded nor part of the history.
+    pub(crate) fn credit_interest(&mut self, interest: Decimal) -> Result<(), TransactionError> {
+        self.available = self.checked_credit(interest)?;
+        self.lifetime_deposited = self.lifetime_deposited.saturating_add(interest);
+        self.apply_scale_policy();
+        Ok(())
+    }
+
+    /// Round `value` following the scale policy, if any
+    fn rescale(&self, value: Decimal) -> Decimal {
+        match self.scale_policy {
+            Some(policy) => policy.apply(value),
+            None => value,
+        }
+    }
+
+    /// Rescale the balances following the scale policy, if any
+    fn apply_scale_policy(&mut self) {
+        self.available = self.rescale(self.available);
+        self.held = self.rescale(self.held);
     }
 
     /// Dispute a transaction
-    fn dispute(&mut self, txid: Txid) {
-        // only deposits can be disputed
-        if let Some(DepositRecord { amount, disputed }) = self.deposits.get_mut(&txid) {
-            // if already disputed or if available balance cannot take a dispute, ignore
-            if !*disputed && self.available >= *amount {
-                // hold funds
-                *disputed = true;
-                self.available -= *amount;
-                self.held += *amount;
+    ///
+    /// Disputing a deposit moves the deposited amount from available to held, which under
+    /// [`DisputePolicy::AllowNegative`] may drive available, and after a chargeback the total,
+    /// negative. Disputing a withdrawal holds the withdrawn amount on top of the available
+    /// balance, which is left untouched until the dispute is settled.
+    ///
+    /// A partial dispute only holds `portion` of the transaction's amount, and the subsequent
+    /// resolve or chargeback settles only that portion.
+    fn dispute(
+        &mut self,
+        txid: Txid,
+        portion: Option<Decimal>,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        if let Some(portion) = portion {
+            // if non-positive portion, ignore
+            if portion <= Decimal::ZERO {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount));
+            }
+            // if too precise portion, ignore
+            if portion.scale() > self.max_scale {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision));
+            }
+        }
+        // only deposits and withdrawals can be disputed
+        let Some(record) = self.records.get_mut(&txid) else {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit));
+        };
+        // if already disputed, ignore
+        if record.disputed {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::AlreadyDisputed));
+        }
+        // if charged back, ignore as the transaction is settled for good
+        if record.charged_back {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::ChargedBack));
+        }
+        // if reversed, ignore as the transaction no longer stands
+        if record.reversed {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::Reversed));
+        }
+        // if portion exceeds the transaction, ignore
+        let amount = match portion {
+            Some(portion) if portion > record.amount => {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::ExceedsTransaction))
             }
+            Some(portion) => portion,
+            None => record.amount,
+        };
+        // hold a rounded amount, so that the held balance stays the sum of the held amounts
+        let amount = match self.scale_policy {
+            Some(policy) => policy.apply(amount),
+            None => amount,
+        };
+        let mut available = self.available;
+        if record.kind == TxKind::Deposit {
+            // if available balance cannot take a dispute, ignore unless it may go negative
+            if available < amount && self.dispute_policy == DisputePolicy::RequireAvailable {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds));
+            }
+            available = checked_sub(self.id, available, amount)?;
+        }
+        let held = checked_add(self.id, self.held, amount)?;
+        // if holding more than was ever deposited, ignore as the stream is malformed
+        if held > self.lifetime_deposited {
+            return Ok(ProcessOutcome::Ignored(
+                IgnoreReason::HeldWouldExceedDeposits,
+            ));
         }
+        // hold funds
+        record.disputed = true;
+        record.held_amount = amount;
+        self.available = available;
+        self.held = held;
+        debug!("client {}: dispute of {} holds {}", self.id, txid, amount);
+        Ok(ProcessOutcome::Applied)
     }
 
     /// Resolve a transaction
-    fn resolve(&mut self, txid: Txid) {
-        // only disputed deposits can be resolved
-        if let Some(DepositRecord { amount, disputed }) = self.deposits.get_mut(&txid) {
-            // if not disputed, ignore
-            if *disputed {
-                // release funds
-                *disputed = false;
-                self.available += *amount;
-                self.held -= *amount;
+    ///
+    /// Resolving a deposit releases the held amount back to available. Resolving a withdrawal
+    /// releases the hold without crediting available, as the withdrawal stands.
+    ///
+    /// A partial resolve only releases `portion` of the held amount, leaving the transaction
+    /// disputed until the rest is released or charged back.
+    fn resolve(
+        &mut self,
+        txid: Txid,
+        portion: Option<Decimal>,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        if let Some(portion) = portion {
+            // if non-positive portion, ignore
+            if portion <= Decimal::ZERO {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount));
+            }
+            // if too precise portion, ignore
+            if portion.scale() > self.max_scale {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision));
+            }
+        }
+        // release a rounded amount, so that the held balance stays the sum of the held amounts
+        let portion = portion.map(|portion| self.rescale(portion));
+        // only disputed deposits and withdrawals can be resolved
+        let Some(record) = self.records.get_mut(&txid) else {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit));
+        };
+        // if not disputed, ignore
+        if !record.disputed {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed));
+        }
+        // if portion exceeds the held amount, ignore
+        let amount = match portion {
+            Some(portion) if portion > record.held_amount => {
+                return Ok(ProcessOutcome::Ignored(IgnoreReason::ExceedsHeld))
             }
+            Some(portion) => portion,
+            None => record.held_amount,
+        };
+        // if held balance no longer covers the held amount, ignore
+        if self.held < amount {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::HeldInsufficient));
         }
+        let mut available = self.available;
+        if record.kind == TxKind::Deposit {
+            available = checked_add(self.id, available, amount)?;
+        }
+        let held = checked_sub(self.id, self.held, amount)?;
+        // release funds, settling the dispute once nothing is held
+        record.held_amount -= amount;
+        record.disputed = !record.held_amount.is_zero();
+        self.available = available;
+        self.held = held;
+        debug!(
+            "client {}: resolve of {} releases {}",
+            self.id, txid, amount
+        );
+        Ok(ProcessOutcome::Applied)
     }
 
     /// Chargeback a transaction
-    fn chargeback(&mut self, txid: Txid) {
-        // only disputed deposits can be chargebacked
-        if let Some(DepositRecord { amount, disputed }) = self.deposits.get_mut(&txid) {
-            // if not disputed, ignore
-            if *disputed {
-                // reverse transaction and lock account
-                *disputed = false;
-                self.held -= *amount;
-                self.locked = true;
-            }
+    ///
+    /// Charging back a deposit removes the held amount from the account. Charging back a
+    /// withdrawal moves the held amount to available, restoring the withdrawn funds. Either way,
+    /// the account is locked and the transaction can no longer be disputed.
+    ///
+    /// Only the amount still held is reversed, so after a partial resolve the released portion
+    /// stays with the account.
+    fn chargeback(&mut self, txid: Txid) -> Result<ProcessOutcome, TransactionError> {
+        // only disputed deposits and withdrawals can be chargebacked
+        let Some(record) = self.records.get_mut(&txid) else {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit));
+        };
+        // if not disputed, ignore
+        if !record.disputed {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed));
+        }
+        // if held balance no longer covers the held amount, ignore
+        let amount = record.held_amount;
+        if self.held < amount {
+            return Ok(ProcessOutcome::Ignored(IgnoreReason::HeldInsufficient));
         }
+        let mut available = self.available;
+        if record.kind == TxKind::Withdrawal {
+            available = checked_add(self.id, available, amount)?;
+        }
+        let held = checked_sub(self.id, self.held, amount)?;
+        // reverse transaction and lock account
+        record.disputed = false;
+        record.held_amount = Decimal::ZERO;
+        record.charged_back = true;
+        self.available = available;
+        self.held = held;
+        self.status = AccountStatus::ChargebackLocked;
+        warn!(
+            "client {}: chargeback of {} reverses {} and locks the account",
+            self.id, txid, amount
+        );
+        Ok(ProcessOutcome::Applied)
     }
 
-    /// Get a snapshot of the account
+    /// Reverse a deposit administratively, e.g. an erroneous credit, debiting its amount from the
+    /// available balance without locking the account
+    ///
+    /// Fails if the deposit is disputed, charged back or already reversed, or if the available
+    /// balance cannot cover it. The deposit can no longer be disputed afterwards.
+    pub(crate) fn reverse_deposit(&mut self, txid: Txid) -> Result<(), TransactionError> {
+        let rejected = |reason| Err(TransactionError::ReversalRejected { txid, reason });
+        let record = match self.records.get_mut(&txid) {
+            Some(record) if record.kind == TxKind::Deposit => record,
+            _ => return rejected(IgnoreReason::NotADeposit),
+        };
+        if record.disputed {
+            return rejected(IgnoreReason::AlreadyDisputed);
+        }
+        if record.charged_back {
+            return rejected(IgnoreReason::ChargedBack);
+        }
+        if record.reversed {
+            return rejected(IgnoreReason::Reversed);
+        }
+        if self.available < record.amount {
+            return rejected(IgnoreReason::InsufficientFunds);
+        }
+        let available = checked_sub(self.id, self.available, record.amount)?;
+        record.reversed = true;
+        warn!(
+            "client {}: deposit {} of {} reversed",
+            self.id, txid, record.amount
+        );
+        self.available = available;
+        self.apply_scale_policy();
+        Ok(())
+    }
+
+    /// Amend the deposit with the txid of repeated deposit `tx` of `amount` following `policy`,
+    /// returning `None` if the account records no deposit with the txid
+    ///
+    /// A deposit under dispute, charged back or reversed is left as is, ignoring `tx`, as is one
+    /// whose amendment would credit the available balance beyond its cap or debit it below zero.
+    pub(crate) fn amend_deposit(
+        &mut self,
+        tx: Transaction,
+        amount: Decimal,
+        policy: RepeatDepositPolicy,
+    ) -> Option<Result<ProcessOutcome, TransactionError>> {
+        let record = self.records.get(&tx.txid)?;
+        if record.kind != TxKind::Deposit || policy == RepeatDepositPolicy::Reject {
+            return None;
+        }
+        let ignored = |reason| Some(Ok(ProcessOutcome::Ignored(reason)));
+        if self.is_locked() {
+            return Some(self.locked_outcome());
+        }
+        if amount <= Decimal::ZERO {
+            return ignored(IgnoreReason::NonPositiveAmount);
+        }
+        if amount.scale() > self.max_scale {
+            return ignored(IgnoreReason::ExcessPrecision);
+        }
+        if record.disputed {
+            return ignored(IgnoreReason::AlreadyDisputed);
+        }
+        if record.charged_back {
+            return ignored(IgnoreReason::ChargedBack);
+        }
+        if record.reversed {
+            return ignored(IgnoreReason::Reversed);
+        }
+        let (amended, delta, available) = match self.amendment(record.amount, amount, policy) {
+            Ok(amendment) => amendment,
+            Err(e) => return Some(Err(e)),
+        };
+        if available.is_sign_negative() {
+            return ignored(IgnoreReason::InsufficientFunds);
+        }
+        if let Some(record) = self.records.get_mut(&tx.txid) {
+            record.amount = amended;
+        }
+        self.available = available;
+        self.lifetime_deposited = self.lifetime_deposited.saturating_add(delta);
+        self.apply_scale_policy();
+        self.record(tx);
+        Some(Ok(ProcessOutcome::Applied))
+    }
+
+    /// Compute the amount of a deposit of `recorded` amended with `amount` following `policy`,
+    /// along with the difference and the resulting available balance
+    fn amendment(
+        &self,
+        recorded: Decimal,
+        amount: Decimal,
+        policy: RepeatDepositPolicy,
+    ) -> Result<(Decimal, Decimal, Decimal), TransactionError> {
+        let amended = match policy {
+            RepeatDepositPolicy::Sum => checked_add(self.id, recorded, amount)?,
+            RepeatDepositPolicy::Reject | RepeatDepositPolicy::Overwrite => amount,
+        };
+        let delta = checked_sub(self.id, amended, recorded)?;
+        let available = if delta.is_sign_negative() {
+            checked_add(self.id, self.available, delta)?
+        } else {
+            self.checked_credit(delta)?
+        };
+        Ok((amended, delta, available))
+    }
+
+    /// Get the outcome of a transaction rejected as the account is locked, following the locked
+    /// policy
+    pub(crate) fn locked_outcome(&self) -> Result<ProcessOutcome, TransactionError> {
+        match self.locked_policy {
+            LockedPolicy::Ignore => Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount)),
+            LockedPolicy::Reject => Err(TransactionError::AccountLocked(self.id)),
+        }
+    }
+
+    /// Check whether the account is locked, whatever the reason
+    pub(crate) fn is_locked(&self) -> bool {
+        self.status != AccountStatus::Active
+    }
+
+    /// Get the status of the account
+    pub(crate) fn status(&self) -> AccountStatus {
+        self.status
+    }
+
+    /// Freeze the account, blocking withdrawals as after a chargeback, unless already locked by one
+    pub(crate) fn freeze(&mut self) {
+        self.status = self.status.max(AccountStatus::Frozen);
+    }
+
+    /// Unfreeze the account, failing if it is locked by a chargeback rather than frozen
+    pub(crate) fn unfreeze(&mut self) -> Result<(), TransactionError> {
+        if self.status == AccountStatus::ChargebackLocked {
+            return Err(TransactionError::ChargebackLocked(self.id));
+        }
+        self.status = AccountStatus::Active;
+        Ok(())
+    }
+
+    /// Unlock the account, whatever the reason it is locked, allowing withdrawals again
+    pub(crate) fn unlock(&mut self) {
+        self.status = AccountStatus::Active;
+    }
+
+    /// Lock the account again with `status`, unless it was locked more strictly since
+    pub(crate) fn relock(&mut self, status: AccountStatus) {
+        self.status = self.status.max(status);
+    }
+
+    /// Get the client's ID
+    pub(crate) fn id(&self) -> ClientId {
+        self.id
+    }
+
+    /// Get the available balance
+    pub(crate) fn available(&self) -> Decimal {
+        self.available
+    }
+
+    /// Get the sum of the withdrawal fees collected from the account
+    pub(crate) fn collected_fees(&self) -> Decimal {
+        self.collected_fees
+    }
+
+    /// Get the held balance
+    pub(crate) fn held(&self) -> Decimal {
+        self.held
+    }
+
+    /// Check whether both the available and held balances are zero
+    pub(crate) fn is_zeroed(&self) -> bool {
+        self.available.is_zero() && self.held.is_zero()
+    }
+
+    /// Get the number of decimal places balances are reported with by default, the larger of
+    /// `DEFAULT_OUTPUT_SCALE` and the maximum scale of amounts
+    pub(crate) fn output_scale(&self) -> u32 {
+        self.max_scale.max(DEFAULT_OUTPUT_SCALE)
+    }
+
+    /// Get a snapshot of the account with balances rounded to [`Account::output_scale`] decimal
+    /// places
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total overflows, see [`Account::try_snapshot`].
     pub(crate) fn snapshot(&self) -> AccountSnapshot {
-        AccountSnapshot {
+        self.snapshot_with_scale(self.output_scale())
+    }
+
+    /// Get a snapshot of the account with balances rounded to [`Account::output_scale`] decimal
+    /// places, failing if the total overflows
+    pub(crate) fn try_snapshot(&self) -> Result<AccountSnapshot, InvariantError> {
+        self.try_snapshot_with_scale(self.output_scale())
+    }
+
+    /// Get a snapshot of the account
+    ///
+    /// Balances are rounded half to even (banker's rounding) to exactly `scale` decimal places,
+    /// with the total recomputed from the rounded components so that each snapshot is internally
+    /// consistent. A balance too large to have `scale` decimal places keeps fewer, to which the
+    /// other balances are then rounded too, so that every column of a row shares a scale. The
+    /// account itself keeps full precision.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total overflows, which only holding and depositing amounts near
+    /// `Decimal::MAX` can cause.
+    pub(crate) fn snapshot_with_scale(&self, scale: u32) -> AccountSnapshot {
+        match self.try_snapshot_with_scale(scale) {
+            Ok(snapshot) => snapshot,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Get a snapshot of the account like [`Account::snapshot_with_scale`], failing with
+    /// [`InvariantError::TotalOverflow`] instead of panicking if the total overflows
+    ///
+    /// No other invariant is checked, so that a state loaded without validation can be inspected
+    /// before being repaired. [`Account::validate_invariants`] checks them.
+    pub(crate) fn try_snapshot_with_scale(
+        &self,
+        scale: u32,
+    ) -> Result<AccountSnapshot, InvariantError> {
+        let (available, held, total) = unify_scales(
+            round_output(self.available, scale),
+            round_output(self.held, scale),
+        )
+        .ok_or(InvariantError::TotalOverflow { client_id: self.id })?;
+        Ok(AccountSnapshot {
             id: self.id,
-            available: self.available,
-            held: self.held,
-            total: self.available + self.held,
-            locked: self.locked,
+            available,
+            held,
+            total,
+            locked: self.is_locked(),
+            disputed_count: self.records.values().filter(|r| r.disputed).count(),
+        })
+    }
+
+    /// Check that the held balance equals the sum of the seeded held balance and the amounts held
+    /// by disputes and, unless
+    /// disputes may drive available negative, that the total balance is non-negative
+    pub(crate) fn validate_invariants(&self) -> Result<(), InvariantError> {
+        let total = self
+            .available
+            .checked_add(self.held)
+            .ok_or(InvariantError::TotalOverflow { client_id: self.id })?;
+        if total.is_sign_negative()
+            && !total.is_zero()
+            && self.dispute_policy == DisputePolicy::RequireAvailable
+        {
+            return Err(InvariantError::NegativeTotal {
+                client_id: self.id,
+                total,
+            });
+        }
+        let disputed = self.disputed_amount()?;
+        if self.held != disputed {
+            return Err(InvariantError::HeldMismatch {
+                client_id: self.id,
+                held: self.held,
+                disputed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Get the amount the held balance should be, i.e. the seeded held balance plus the amounts
+    /// held by the open disputes, failing if it overflows, e.g. in a hand-edited state
+    fn disputed_amount(&self) -> Result<Decimal, InvariantError> {
+        self.records
+            .values()
+            .try_fold(self.seeded_held, |disputed, record| {
+                disputed.checked_add(record.held_amount)
+            })
+            .ok_or(InvariantError::TotalOverflow { client_id: self.id })
+    }
+
+    /// Recompute the held balance from the open disputes, returning whether it had to be corrected
+    pub(crate) fn repair_held(&mut self) -> Result<bool, InvariantError> {
+        let disputed = self.disputed_amount()?;
+        if self.held == disputed {
+            return Ok(false);
+        }
+        warn!(
+            "client {}: held balance {} corrected to {}",
+            self.id, self.held, disputed
+        );
+        self.held = disputed;
+        Ok(true)
+    }
+}
+
+/// Add `amount` to a balance of the client's account, failing on overflow or loss of precision
+fn checked_add(
+    client_id: ClientId,
+    balance: Decimal,
+    amount: Decimal,
+) -> Result<Decimal, TransactionError> {
+    let sum = balance
+        .checked_add(amount)
+        .ok_or(TransactionError::BalanceOverflow { client_id })?;
+    check_exact(client_id, balance, amount, sum, |a, b| a + b)
+}
+
+/// Subtract `amount` from a balance of the client's account, failing on overflow or loss of
+/// precision
+fn checked_sub(
+    client_id: ClientId,
+    balance: Decimal,
+    amount: Decimal,
+) -> Result<Decimal, TransactionError> {
+    let difference = balance
+        .checked_sub(amount)
+        .ok_or(TransactionError::BalanceOverflow { client_id })?;
+    check_exact(client_id, balance, amount, difference, |a, b| a - b)
+}
+
+/// Check that `result` of combining `a` and `b` with `op` kept every significant digit
+///
+/// `rust_decimal` silently rounds a result to fewer decimal places when it would otherwise not fit
+/// its 96-bit mantissa. Combining the digits beyond the result's scale tells whether any of them
+/// were lost.
+fn check_exact(
+    client_id: ClientId,
+    a: Decimal,
+    b: Decimal,
+    result: Decimal,
+    op: fn(Decimal, Decimal) -> Decimal,
+) -> Result<Decimal, TransactionError> {
+    let scale = result.scale();
+    if a.scale() <= scale && b.scale() <= scale {
+        return Ok(result);
+    }
+    let tail = |value: Decimal| value - value.trunc_with_scale(scale);
+    if op(tail(a), tail(b)).normalize().scale() > scale {
+        return Err(TransactionError::PrecisionLoss { client_id });
+    }
+    Ok(result)
+}
+
+/// Round a balance half to even to exactly `scale` decimal places, normalizing any zero, e.g. a
+/// negative zero, to the canonical zero
+fn round_output(balance: Decimal, scale: u32) -> Decimal {
+    let mut balance = balance.round_dp(scale);
+    if balance.is_zero() {
+        balance = Decimal::ZERO;
+    }
+    balance.rescale(scale);
+    balance
+}
+
+/// Round `available` and `held` to the lowest scale among them and their total, returning them
+/// along with the total, or `None` if the total overflows
+///
+/// Rescaling keeps fewer decimal places than asked for if a balance cannot be represented with as
+/// many, which would otherwise leave the balances of a row at different scales.
+fn unify_scales(mut available: Decimal, mut held: Decimal) -> Option<(Decimal, Decimal, Decimal)> {
+    loop {
+        let total = available.checked_add(held)?;
+        let scale = available.scale().min(held.scale()).min(total.scale());
+        if [available, held, total].iter().all(|b| b.scale() == scale) {
+            return Some((available, held, total));
         }
+        available = round_output(available, scale);
+        held = round_output(held, scale);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::amount;
+    use crate::RoundingStrategy;
     use rust_decimal_macros::dec;
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
 
     #[test]
     fn deposit_positive_works() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -180,16 +1326,50 @@ mod tests {
     #[test]
     fn deposit_negative_ignored() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(-1.00));
+        assert_eq!(
+            account.deposit(Txid(1), dec!(-1.00)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
         assert_eq!(account.available, dec!(0));
         assert_eq!(account.held, dec!(0));
     }
 
+    #[test]
+    fn deposit_zero_ignored() {
+        let mut account = Account::new(ClientId(1));
+        assert_eq!(
+            account.deposit(Txid(1), dec!(0)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert!(account.records.is_empty());
+    }
+
+    #[test]
+    fn deposit_excess_precision_ignored() {
+        let mut account = Account::with_config(ClientId(1), &HandlerConfig::default());
+        assert_eq!(
+            account.deposit(Txid(1), dec!(1.23456)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert!(account.records.is_empty());
+        assert_eq!(
+            account.deposit(Txid(2), dec!(1.2345)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            account.withdraw(Txid(3), dec!(0.00001)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision))
+        );
+        assert_eq!(account.available, dec!(1.2345));
+    }
+
     #[test]
     fn withdraw_positive_works() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
-        account.withdraw(dec!(0.50));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(Txid(2), dec!(0.50)).unwrap();
         assert_eq!(account.available, dec!(0.50));
         assert_eq!(account.held, dec!(0));
     }
@@ -197,18 +1377,42 @@ mod tests {
     #[test]
     fn withdraw_negative_ignored() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
-        account.withdraw(dec!(-0.50));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(-0.50)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
 
+    #[test]
+    fn withdraw_zero_ignored() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(0)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
+        assert_eq!(account.available, dec!(1.00));
+        assert!(!account.records.contains_key(&Txid(2)));
+    }
+
     #[test]
     fn withdraw_from_locked_ignored() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
-        account.locked = true;
-        account.withdraw(dec!(0.50));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.status = AccountStatus::ChargebackLocked;
+        let tx = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(dec!(0.50))),
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process(tx, 0),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -216,8 +1420,11 @@ mod tests {
     #[test]
     fn withdraw_insufficient_funds_ignored() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
-        account.withdraw(dec!(1.50));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(1.50)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -226,8 +1433,8 @@ mod tests {
     fn dispute_deposit_works() {
         let mut account = Account::new(ClientId(1));
         let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.dispute(txid);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
         assert_eq!(account.available, dec!(0));
         assert_eq!(account.held, dec!(1.00));
     }
@@ -235,8 +1442,11 @@ mod tests {
     #[test]
     fn dispute_nondeposit_ignores() {
         let mut account = Account::new(ClientId(1));
-        account.deposit(Txid(1), dec!(1.00));
-        account.dispute(Txid(2));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        assert_eq!(
+            account.dispute(Txid(2), None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -245,9 +1455,9 @@ mod tests {
     fn resolve_deposit_works() {
         let mut account = Account::new(ClientId(1));
         let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.dispute(txid);
-        account.resolve(txid);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
+        account.resolve(txid, None).unwrap();
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -256,8 +1466,11 @@ mod tests {
     fn resolve_undisputed_ignores() {
         let mut account = Account::new(ClientId(1));
         let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.resolve(txid);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        assert_eq!(
+            account.resolve(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
     }
@@ -266,33 +1479,959 @@ mod tests {
     fn chargeback_deposit_works() {
         let mut account = Account::new(ClientId(1));
         let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.dispute(txid);
-        account.chargeback(txid);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
+        account.chargeback(txid).unwrap();
         assert_eq!(account.available, dec!(0));
         assert_eq!(account.held, dec!(0));
-        assert_eq!(account.locked, true);
+        assert!(account.is_locked());
     }
 
     #[test]
     fn chargeback_undisputed_ignores() {
         let mut account = Account::new(ClientId(1));
         let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.chargeback(txid);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        assert_eq!(
+            account.chargeback(txid),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed))
+        );
         assert_eq!(account.available, dec!(1.00));
         assert_eq!(account.held, dec!(0));
-        assert_eq!(account.locked, false);
+        assert!(!account.is_locked());
     }
 
     #[test]
-    fn dispute_more_than_balance_ignores() {
+    fn dispute_withdrawal_works() {
         let mut account = Account::new(ClientId(1));
-        let txid = Txid(1);
-        account.deposit(txid, dec!(1.00));
-        account.withdraw(dec!(1.00));
-        account.dispute(txid);
-        assert_eq!(account.available, dec!(0));
-        assert_eq!(account.held, dec!(0));
+        let txid = Txid(2);
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(txid, dec!(0.40)).unwrap();
+        assert_eq!(account.dispute(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(0.60));
+        assert_eq!(account.held, dec!(0.40));
+    }
+
+    #[test]
+    fn resolve_withdrawal_works() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(2);
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(txid, dec!(0.40)).unwrap();
+        account.dispute(txid, None).unwrap();
+        assert_eq!(account.resolve(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(0.60));
+        assert_eq!(account.held, dec!(0));
+        assert!(!account.is_locked());
+    }
+
+    #[test]
+    fn chargeback_withdrawal_works() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(2);
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(txid, dec!(0.40)).unwrap();
+        account.dispute(txid, None).unwrap();
+        assert_eq!(account.chargeback(txid), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(1.00));
+        assert_eq!(account.held, dec!(0));
+        assert!(account.is_locked());
+    }
+
+    #[test]
+    fn dispute_rejected_withdrawal_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(2);
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(txid, dec!(1.50)).unwrap();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit))
+        );
+        assert_eq!(account.held, dec!(0));
+    }
+
+    #[test]
+    fn dispute_already_disputed_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.deposit(Txid(2), dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::AlreadyDisputed))
+        );
+        assert_eq!(account.available, dec!(1.00));
+        assert_eq!(account.held, dec!(1.00));
+    }
+
+    #[test]
+    fn process_reports_applied() {
+        let mut account = Account::new(ClientId(1));
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(dec!(1.00))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(account.process(tx, 0), Ok(ProcessOutcome::Applied));
+    }
+
+    #[test]
+    fn process_client_mismatch_fails() {
+        let mut account = Account::new(ClientId(1));
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(dec!(1.00))),
+            client_id: ClientId(2),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process(tx, 0),
+            Err(TransactionError::ClientMismatch {
+                expected: ClientId(1),
+                got: ClientId(2),
+            })
+        );
+        assert_eq!(account.available, dec!(0));
+    }
+
+    #[test]
+    fn dispute_more_than_balance_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.withdraw(Txid(2), dec!(1.00)).unwrap();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert_eq!(account.held, dec!(0));
+    }
+
+    #[test]
+    fn dispute_more_than_balance_allowed_negative() {
+        let config = HandlerConfig {
+            dispute_policy: DisputePolicy::AllowNegative,
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.withdraw(Txid(2), dec!(0.75)).unwrap();
+        assert_eq!(account.dispute(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(-0.75));
+        assert_eq!(account.held, dec!(1.00));
+        account.chargeback(txid).unwrap();
+        let snapshot = account.snapshot();
+        assert_eq!(snapshot.available, dec!(-0.7500));
+        assert_eq!(snapshot.held, dec!(0.0000));
+        assert_eq!(snapshot.total, dec!(-0.7500));
+        assert!(snapshot.locked);
+    }
+
+    #[test]
+    fn snapshot_rounds_to_four_places() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1)).unwrap();
+        account.deposit(Txid(2), dec!(0.33335)).unwrap();
+        let snapshot = account.snapshot_with_scale(DEFAULT_OUTPUT_SCALE);
+        assert_eq!(snapshot.available.to_string(), "1.3334");
+        assert_eq!(snapshot.held.to_string(), "0.0000");
+        assert_eq!(snapshot.total.to_string(), "1.3334");
+    }
+
+    #[test]
+    fn try_snapshot_total_overflow_fails() {
+        let mut account = Account::new(ClientId(1));
+        // twice this is one more than `Decimal::MAX`
+        let half = dec!(39614081257132168796771975168);
+        account.deposit(Txid(1), half).unwrap();
+        account.dispute(Txid(1), None).unwrap();
+        account.deposit(Txid(2), half).unwrap();
+        assert_eq!(
+            account.try_snapshot(),
+            Err(InvariantError::TotalOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(
+            account.validate_invariants(),
+            Err(InvariantError::TotalOverflow {
+                client_id: ClientId(1)
+            })
+        );
+
+        account.withdraw(Txid(3), Decimal::ONE).unwrap();
+        let snapshot = account.try_snapshot().unwrap();
+        assert_eq!(snapshot.total, Decimal::MAX);
+    }
+
+    #[test]
+    fn unlock_allows_withdrawal() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.status = AccountStatus::ChargebackLocked;
+        account.unlock();
+        assert!(!account.is_locked());
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(0.50)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(account.available, dec!(0.50));
+    }
+
+    #[test]
+    fn debit_is_not_disputable() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        let tx = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: dec!(0.40),
+            },
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(account.process(tx, 0), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(0.60));
+        assert_eq!(
+            account.dispute(Txid(2), None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit))
+        );
+    }
+
+    #[test]
+    fn deposit_to_locked_ignored() {
+        let mut account = Account::new(ClientId(1));
+        account.status = AccountStatus::ChargebackLocked;
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(dec!(1.00))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process(tx, 0),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert!(account.records.is_empty());
+    }
+
+    #[test]
+    fn dispute_on_locked_ignored() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.status = AccountStatus::ChargebackLocked;
+        let tx = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            account.process(tx, 0),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+        assert_eq!(account.available, dec!(1.00));
+        assert_eq!(account.held, dec!(0));
+    }
+
+    #[test]
+    fn resolve_on_locked_works() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.dispute(Txid(1), None).unwrap();
+        account.status = AccountStatus::ChargebackLocked;
+        let tx = Transaction {
+            tx_type: TransactionType::Resolve,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(account.process(tx, 0), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(1.00));
+        assert_eq!(account.held, dec!(0));
+        assert!(account.is_locked());
+    }
+
+    #[test]
+    fn deposit_overflow_fails() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), Decimal::MAX).unwrap();
+        assert_eq!(
+            account.deposit(Txid(2), Decimal::MAX),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(account.available, Decimal::MAX);
+        assert!(!account.records.contains_key(&Txid(2)));
+    }
+
+    #[test]
+    fn dispute_overflow_fails() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), Decimal::MAX).unwrap();
+        account.withdraw(Txid(2), Decimal::MAX).unwrap();
+        account.dispute(Txid(2), None).unwrap();
+        account.deposit(Txid(3), Decimal::ONE).unwrap();
+        account.withdraw(Txid(4), Decimal::ONE).unwrap();
+        assert_eq!(
+            account.dispute(Txid(4), None),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(account.held, Decimal::MAX);
+        assert!(!account.records[&Txid(4)].disputed);
+    }
+
+    #[test]
+    fn resolve_releases_held_amount() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(10)).unwrap();
+        account.dispute(Txid(1), None).unwrap();
+        assert_eq!(account.records[&Txid(1)].held_amount, dec!(10));
+        account.deposit(Txid(2), dec!(5)).unwrap();
+        account.withdraw(Txid(3), dec!(3)).unwrap();
+        account.dispute(Txid(3), None).unwrap();
+        account.resolve(Txid(1), None).unwrap();
+        assert_eq!(account.records[&Txid(1)].held_amount, dec!(0));
+        assert_eq!(account.available, dec!(12));
+        assert_eq!(account.held, dec!(3));
+        account.chargeback(Txid(3)).unwrap();
+        assert_eq!(account.records[&Txid(3)].held_amount, dec!(0));
+        assert_eq!(account.available, dec!(15));
+        assert_eq!(account.held, dec!(0));
+    }
+
+    #[test]
+    fn redispute_after_resolve_lifecycle() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(10)).unwrap();
+        account.deposit(Txid(2), dec!(5)).unwrap();
+        assert_eq!(account.dispute(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!((account.available, account.held), (dec!(5), dec!(10)));
+        assert_eq!(account.resolve(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!((account.available, account.held), (dec!(15), dec!(0)));
+        // the second dispute is checked against the changed available balance
+        account.withdraw(Txid(3), dec!(8)).unwrap();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        account.deposit(Txid(4), dec!(3)).unwrap();
+        assert_eq!(account.dispute(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!((account.available, account.held), (dec!(0), dec!(10)));
+        assert_eq!(account.chargeback(txid), Ok(ProcessOutcome::Applied));
+        assert_eq!((account.available, account.held), (dec!(0), dec!(0)));
+        assert!(account.is_locked());
+        // a charged back deposit is terminal, even once the account is unlocked
+        account.unlock();
+        account.deposit(Txid(5), dec!(20)).unwrap();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ChargedBack))
+        );
+        assert_eq!((account.available, account.held), (dec!(20), dec!(0)));
+    }
+
+    #[test]
+    fn resolve_stale_works() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(10)).unwrap();
+        account.deposit(Txid(2), dec!(5)).unwrap();
+        let dispute = |txid| Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        account.process(dispute(1), 3).unwrap();
+        account.process(dispute(2), 6).unwrap();
+        assert_eq!(account.resolve_stale(8, 5), Ok(0));
+        assert_eq!(account.resolve_stale(9, 5), Ok(1));
+        assert_eq!((account.available, account.held), (dec!(10), dec!(5)));
+        assert!(account.records[&Txid(2)].disputed);
+    }
+
+    #[test]
+    fn consistent_account_validates() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.deposit(Txid(2), dec!(2.00)).unwrap();
+        account.withdraw(Txid(3), dec!(0.50)).unwrap();
+        account.dispute(Txid(2), None).unwrap();
+        account.dispute(Txid(3), None).unwrap();
+        assert_eq!(account.validate_invariants(), Ok(()));
+        account.resolve(Txid(3), None).unwrap();
+        account.chargeback(Txid(2)).unwrap();
+        assert_eq!(account.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn inconsistent_account_fails_validation() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.held = dec!(0.50);
+        assert_eq!(
+            account.validate_invariants(),
+            Err(InvariantError::HeldMismatch {
+                client_id: ClientId(1),
+                held: dec!(0.50),
+                disputed: dec!(0)
+            })
+        );
+        account.held = dec!(0);
+        account.available = dec!(-1.00);
+        assert_eq!(
+            account.validate_invariants(),
+            Err(InvariantError::NegativeTotal {
+                client_id: ClientId(1),
+                total: dec!(-1.00)
+            })
+        );
+    }
+
+    #[test]
+    fn snapshot_counts_disputes() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.deposit(Txid(2), dec!(2.00)).unwrap();
+        account.dispute(Txid(1), None).unwrap();
+        account.dispute(Txid(2), None).unwrap();
+        assert_eq!(account.snapshot().disputed_count, 2);
+        account.resolve(Txid(1), None).unwrap();
+        assert_eq!(account.snapshot().disputed_count, 1);
+    }
+
+    #[test]
+    fn chargeback_more_than_held_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
+        account.held = dec!(0.50);
+        assert_eq!(
+            account.chargeback(txid),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::HeldInsufficient))
+        );
+        assert_eq!(account.held, dec!(0.50));
+        assert!(!account.is_locked());
+        assert!(account.records[&txid].disputed);
+    }
+
+    #[test]
+    fn resolve_more_than_held_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        account.dispute(txid, None).unwrap();
+        account.held = dec!(0.50);
+        assert_eq!(
+            account.resolve(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::HeldInsufficient))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert_eq!(account.held, dec!(0.50));
+    }
+
+    #[test]
+    fn partial_dispute_then_resolve_works() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(10.00)).unwrap();
+        assert_eq!(
+            account.dispute(txid, Some(dec!(5.00))),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(account.available, dec!(5.00));
+        assert_eq!(account.held, dec!(5.00));
+        assert_eq!(account.resolve(txid, None), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(10.00));
+        assert_eq!(account.held, dec!(0));
+        assert_eq!(account.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn partial_dispute_then_chargeback_works() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(10.00)).unwrap();
+        account.dispute(txid, Some(dec!(2.50))).unwrap();
+        assert_eq!(account.chargeback(txid), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.available, dec!(7.50));
+        assert_eq!(account.held, dec!(0));
+        assert!(account.is_locked());
+    }
+
+    #[test]
+    fn partial_dispute_exceeding_transaction_ignored() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(1.00)).unwrap();
+        assert_eq!(
+            account.dispute(txid, Some(dec!(1.01))),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ExceedsTransaction))
+        );
+        assert_eq!(
+            account.dispute(txid, Some(dec!(0))),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
+        assert_eq!(account.available, dec!(1.00));
+        assert_eq!(account.held, dec!(0));
+        assert!(!account.records[&txid].disputed);
+        assert_eq!(
+            account.dispute(txid, Some(dec!(1.00))),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(account.held, dec!(1.00));
+    }
+
+    #[test]
+    fn scale_policy_rescales_balances() {
+        let config = HandlerConfig {
+            max_scale: 8,
+            scale_policy: Some(ScalePolicy::default()),
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        for (txid, value) in [(1, dec!(0.33335)), (2, dec!(0.33335)), (3, dec!(0.33335))] {
+            let tx = Transaction {
+                tx_type: TransactionType::Deposit(amount(value)),
+                client_id: ClientId(1),
+                txid: Txid(txid),
+                timestamp: None,
+            };
+            assert_eq!(account.process(tx, txid), Ok(ProcessOutcome::Applied));
+        }
+        // exact sum is 1.00005, but each deposit rounds half to even
+        assert_eq!(account.available, dec!(1.0002));
+        assert_eq!(account.available.scale(), 4);
+        account.dispute(Txid(1), None).unwrap();
+        assert_eq!(account.held, dec!(0.3334));
+        assert_eq!(account.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn scale_policy_rounding_strategy_configurable() {
+        let config = HandlerConfig {
+            max_scale: 8,
+            scale_policy: Some(ScalePolicy {
+                scale: 2,
+                rounding: RoundingStrategy::MidpointAwayFromZero,
+            }),
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(dec!(0.125))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        account.process(tx, 1).unwrap();
+        assert_eq!(account.available, dec!(0.13));
+    }
+
+    #[test]
+    fn max_records_evicts_oldest_undisputed() {
+        let config = HandlerConfig {
+            max_records_per_account: Some(2),
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.deposit(Txid(2), dec!(1.00)).unwrap();
+        account.dispute(Txid(1), None).unwrap();
+        account.deposit(Txid(3), dec!(1.00)).unwrap();
+        // the disputed txid 1 survives, so txid 2 is the oldest evictable record
+        assert!(account.has_record(Txid(1)));
+        assert!(!account.has_record(Txid(2)));
+        assert!(account.has_record(Txid(3)));
+        assert_eq!(
+            account.dispute(Txid(2), None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit))
+        );
+        assert_eq!(account.resolve(Txid(1), None), Ok(ProcessOutcome::Applied));
+        account.deposit(Txid(4), dec!(1.00)).unwrap();
+        assert!(!account.has_record(Txid(1)));
+        assert_eq!(account.records.len(), 2);
+        assert_eq!(account.available, dec!(4.00));
+    }
+
+    #[test]
+    fn snapshot_normalizes_zero() {
+        let fresh = Account::new(ClientId(1)).snapshot_with_scale(DEFAULT_OUTPUT_SCALE);
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.25)).unwrap();
+        account.withdraw(Txid(2), dec!(1.25)).unwrap();
+        let computed = account.snapshot_with_scale(DEFAULT_OUTPUT_SCALE);
+        assert_eq!(computed.available.to_string(), fresh.available.to_string());
+        assert_eq!(computed.total.to_string(), fresh.total.to_string());
+        // negative zero, e.g. from external arithmetic
+        account.available = Decimal::from_parts(0, 0, 0, true, 2);
+        let snapshot = account.snapshot_with_scale(DEFAULT_OUTPUT_SCALE);
+        assert_eq!(snapshot.available.to_string(), "0.0000");
+        assert!(!snapshot.available.is_sign_negative());
+        assert_eq!(snapshot.total.to_string(), "0.0000");
+    }
+
+    #[test]
+    fn deposit_losing_precision_fails() {
+        let mut account = Account::new(ClientId(1));
+        let large = Decimal::from(10u128.pow(28));
+        account.deposit(Txid(1), large).unwrap();
+        assert_eq!(
+            account.deposit(Txid(2), dec!(0.5)),
+            Err(TransactionError::PrecisionLoss {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(account.available, large);
+        assert!(!account.records.contains_key(&Txid(2)));
+        // dropping only trailing zeros loses nothing
+        assert_eq!(
+            account.deposit(Txid(3), dec!(1.0)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(account.available, large + Decimal::ONE);
+        assert_eq!(
+            account.withdraw(Txid(4), dec!(0.25)),
+            Err(TransactionError::PrecisionLoss {
+                client_id: ClientId(1)
+            })
+        );
+    }
+
+    /// Logger capturing the records logged on each thread, so that tests running in parallel do
+    /// not see each other's records
+    struct CapturingLogger;
+
+    thread_local! {
+        static CAPTURED: std::cell::RefCell<Vec<(log::Level, String)>> =
+            const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            CAPTURED.with(|captured| {
+                captured
+                    .borrow_mut()
+                    .push((record.level(), record.args().to_string()))
+            });
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn withdraw_insufficient_funds_warns() {
+        static LOGGER: CapturingLogger = CapturingLogger;
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(1.00)).unwrap();
+        account.withdraw(Txid(2), dec!(1.50)).unwrap();
+        let captured = CAPTURED.with(|captured| captured.take());
+        assert_eq!(
+            captured,
+            vec![(
+                log::Level::Warn,
+                "client 1: debit of 1.50 exceeds available balance 1.00".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn chargeback_after_partial_resolve_reverses_remaining_portion() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(100)).unwrap();
+        account.dispute(txid, Some(dec!(60))).unwrap();
+        assert_eq!(
+            account.resolve(txid, Some(dec!(20))),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!((account.available, account.held), (dec!(60), dec!(40)));
+        assert!(account.records[&txid].disputed);
+        assert_eq!(account.validate_invariants(), Ok(()));
+
+        assert_eq!(account.chargeback(txid), Ok(ProcessOutcome::Applied));
+        assert_eq!((account.available, account.held), (dec!(60), dec!(0)));
+        assert!(account.is_locked());
+        // the deposit is settled for good
+        account.unlock();
+        assert_eq!(
+            account.dispute(txid, None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ChargedBack))
+        );
+        assert_eq!(account.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn partial_resolve_exceeding_held_ignored() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(100)).unwrap();
+        account.dispute(txid, Some(dec!(60))).unwrap();
+        assert_eq!(
+            account.resolve(txid, Some(dec!(60.01))),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::ExceedsHeld))
+        );
+        // releasing the whole held amount in parts settles the dispute
+        account.resolve(txid, Some(dec!(20))).unwrap();
+        account.resolve(txid, Some(dec!(40))).unwrap();
+        assert_eq!((account.available, account.held), (dec!(100), dec!(0)));
+        assert!(!account.records[&txid].disputed);
+        assert_eq!(
+            account.chargeback(txid),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed))
+        );
+    }
+
+    #[test]
+    fn reverse_deposit_works() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(10)).unwrap();
+        account.deposit(Txid(2), dec!(5)).unwrap();
+        assert_eq!(account.reverse_deposit(Txid(1)), Ok(()));
+        assert_eq!((account.available, account.held), (dec!(5), dec!(0)));
+        assert!(!account.is_locked());
+        assert_eq!(
+            account.reverse_deposit(Txid(1)),
+            Err(TransactionError::ReversalRejected {
+                txid: Txid(1),
+                reason: IgnoreReason::Reversed
+            })
+        );
+        assert_eq!(
+            account.dispute(Txid(1), None),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::Reversed))
+        );
+        assert_eq!(account.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn reverse_deposit_insufficient_funds_fails() {
+        let mut account = Account::new(ClientId(1));
+        account.deposit(Txid(1), dec!(10)).unwrap();
+        account.withdraw(Txid(2), dec!(6)).unwrap();
+        assert_eq!(
+            account.reverse_deposit(Txid(1)),
+            Err(TransactionError::ReversalRejected {
+                txid: Txid(1),
+                reason: IgnoreReason::InsufficientFunds
+            })
+        );
+        assert_eq!(
+            account.reverse_deposit(Txid(2)),
+            Err(TransactionError::ReversalRejected {
+                txid: Txid(2),
+                reason: IgnoreReason::NotADeposit
+            })
+        );
+        assert_eq!(account.available, dec!(4));
+    }
+
+    #[test]
+    fn partial_resolve_more_than_held_ignores() {
+        let mut account = Account::new(ClientId(1));
+        let txid = Txid(1);
+        account.deposit(txid, dec!(100)).unwrap();
+        account.dispute(txid, Some(dec!(60))).unwrap();
+        // drift the held balance just below the portion to release
+        account.held = dec!(19.9999);
+        assert_eq!(
+            account.resolve(txid, Some(dec!(20))),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::HeldInsufficient))
+        );
+        assert_eq!((account.available, account.held), (dec!(40), dec!(19.9999)));
+        assert_eq!(account.records[&txid].held_amount, dec!(60));
+    }
+
+    #[test]
+    fn string_snapshot_formats_to_scale() {
+        let snapshot = AccountSnapshot::new(ClientId(1), dec!(1.5), dec!(0.00005), false, 0);
+        let snapshot = snapshot.to_string_snapshot(4);
+        assert_eq!(snapshot.available, "1.5000");
+        assert_eq!(snapshot.held, "0.0000");
+        assert_eq!(snapshot.total, "1.5000");
+    }
+
+    #[test]
+    fn snapshot_validate_rejects_inconsistent_total() {
+        let snapshot: AccountSnapshot = serde_json::from_str(
+            r#"{"client":1,"available":"1.5","held":"0.5","total":"2.0","locked":false,"disputed_count":0}"#,
+        )
+        .unwrap();
+        assert_eq!(snapshot.validate(), Ok(()));
+        let snapshot: AccountSnapshot = serde_json::from_str(
+            r#"{"client":1,"available":"1.5","held":"0.5","total":"2.5","locked":false,"disputed_count":0}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            snapshot.validate(),
+            Err(TransactionError::InconsistentTotal(ClientId(1)))
+        );
+    }
+
+    #[test]
+    fn dispute_beyond_deposits_ignores() {
+        let mut account = Account::new(ClientId(1));
+        account.dispute_policy = DisputePolicy::AllowNegative;
+        account.deposit(Txid(1), dec!(10)).unwrap();
+        account.withdraw(Txid(2), dec!(10)).unwrap();
+        assert_eq!(account.dispute(Txid(2), None), Ok(ProcessOutcome::Applied));
+        // holding the deposit too would hold 20 of the 10 ever deposited
+        assert_eq!(
+            account.dispute(Txid(1), None),
+            Ok(ProcessOutcome::Ignored(
+                IgnoreReason::HeldWouldExceedDeposits
+            ))
+        );
+        assert_eq!(account.held, dec!(10));
+        assert_eq!(account.available, dec!(0));
+    }
+
+    #[test]
+    fn saturating_withdrawal_partially_fills() {
+        let config = HandlerConfig {
+            withdrawal_mode: WithdrawalMode::Saturating,
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        account.deposit(Txid(1), dec!(1.5)).unwrap();
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(2)),
+            Ok(ProcessOutcome::PartiallyApplied(dec!(1.5)))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert_eq!(account.records[&Txid(2)].amount, dec!(1.5));
+        // nothing is left to withdraw
+        assert_eq!(
+            account.withdraw(Txid(3), dec!(1)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        // disputing the withdrawal holds the amount actually withdrawn
+        assert_eq!(account.dispute(Txid(2), None), Ok(ProcessOutcome::Applied));
+        assert_eq!(account.held, dec!(1.5));
+    }
+
+    #[test]
+    fn saturating_withdrawal_fully_fills() {
+        let config = HandlerConfig {
+            withdrawal_mode: WithdrawalMode::Saturating,
+            withdrawal_fee: dec!(0.1),
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        account.deposit(Txid(1), dec!(2)).unwrap();
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(account.available, dec!(0.9));
+        // the fee is still collected on top of a partial withdrawal
+        assert_eq!(
+            account.withdraw(Txid(3), dec!(1)),
+            Ok(ProcessOutcome::PartiallyApplied(dec!(0.8)))
+        );
+        assert_eq!(account.available, dec!(0));
+        assert_eq!(account.collected_fees, dec!(0.2));
+    }
+
+    #[test]
+    fn snapshot_unifies_scales() {
+        let mut account = Account::new(ClientId(1));
+        for (tx_type, txid) in [
+            (TransactionType::Deposit(amount(dec!(0.1235))), 1),
+            (TransactionType::Dispute, 1),
+            (
+                TransactionType::Deposit(amount(dec!(9000000000000000000000000))),
+                2,
+            ),
+        ] {
+            let tx = Transaction {
+                tx_type,
+                client_id: ClientId(1),
+                txid: Txid(txid),
+                timestamp: None,
+            };
+            assert_eq!(account.process(tx, txid), Ok(ProcessOutcome::Applied));
+        }
+
+        // available cannot be represented with 4 decimal places, so every balance has 3
+        let snapshot = account.snapshot_with_scale(4);
+        assert_eq!(
+            snapshot.available.to_string(),
+            "9000000000000000000000000.000"
+        );
+        assert_eq!(snapshot.held.to_string(), "0.124");
+        assert_eq!(snapshot.total.to_string(), "9000000000000000000000000.124");
+        assert_eq!(account.snapshot_with_scale(2).held.to_string(), "0.12");
+    }
+
+    #[test]
+    fn snapshots_compare_by_value() {
+        let snapshot =
+            |available, held| AccountSnapshot::new(ClientId(1), available, held, false, 0);
+        let (a, b) = (snapshot(dec!(2), dec!(0)), snapshot(dec!(2.00), dec!(0.0)));
+        assert_eq!(a, b);
+        let hash = |snapshot: &AccountSnapshot| {
+            let mut hasher = DefaultHasher::new();
+            snapshot.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&a), hash(&b));
+        assert_ne!(a.available.to_string(), b.available.to_string());
+    }
+
+    #[test]
+    fn withdrawal_fee_overflow_fails() {
+        let config = HandlerConfig {
+            withdrawal_fee: dec!(1),
+            ..HandlerConfig::default()
+        };
+        let mut account = Account::with_config(ClientId(1), &config);
+        account.deposit(Txid(1), dec!(2)).unwrap();
+        account.collected_fees = Decimal::MAX;
+        assert_eq!(
+            account.withdraw(Txid(2), dec!(1)),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(account.available, dec!(2));
+        assert_eq!(account.collected_fees, Decimal::MAX);
+        assert!(!account.has_record(Txid(2)));
+    }
+
+    #[test]
+    fn disputed_amount_overflow_fails() {
+        let mut account = Account::new(ClientId(1));
+        for txid in [Txid(1), Txid(2)] {
+            account.deposit(txid, dec!(1)).unwrap();
+            account.dispute(txid, None).unwrap();
+            // corrupt the held amounts as if edited by hand
+            account.records.get_mut(&txid).unwrap().held_amount = Decimal::MAX;
+        }
+        let overflow = InvariantError::TotalOverflow {
+            client_id: ClientId(1),
+        };
+        assert_eq!(account.validate_invariants(), Err(overflow));
+        assert_eq!(account.repair_held(), Err(overflow));
+        assert_eq!(account.held, dec!(2));
     }
 }
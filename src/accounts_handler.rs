@@ -3,65 +3,1486 @@
 //! `accounts_handler` is a module providing functionality for managing multiple accounts for
 //! multiple clients.
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    fs::File,
+    hash::BuildHasherDefault,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    thread,
+};
 
-use crate::{account::AccountSnapshot, Account, ClientId, Transaction, Txid};
+use log::warn;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{AccountSnapshot, AccountStatus, AppliedEvent},
+    diff_snapshots, read_csv_transactions, Account, Amount, ClientId, CsvOptions, HandlerConfig,
+    IgnoreReason, IngestionStats, InvariantError, NegativeAmountPolicy, ProcessOutcome,
+    SnapshotDelta, Transaction, TransactionError, TransactionType, TxKind, Txid, TxidAllocator,
+    UniquenessScope, ValidationConfig, DEFAULT_OUTPUT_SCALE,
+};
+
+/// Txid keyed by client id only if txids are unique per client
+type TxidKey = (Option<ClientId>, Txid);
+
+/// Map of client id to accounts, hashed with fixed keys so that iterating over it visits the
+/// accounts in the same order for the same input on every run
+///
+/// Client ids are too few for hash flooding to be a concern.
+type AccountMap = HashMap<ClientId, Account, BuildHasherDefault<DefaultHasher>>;
+
+/// Factory of the opening snapshot of each new account
+type AccountFactory = Box<dyn Fn(ClientId) -> AccountSnapshot + Send + Sync>;
+
+/// Callback invoked with each applied transaction and the resulting snapshot of its account
+type AppliedCallback = Box<dyn FnMut(&Transaction, &AccountSnapshot) + Send + Sync>;
 
 /// Accounts handler for multiple clients
+///
+/// The handler is `Send` and `Sync`, requiring as much of the callbacks it is given, so it can be
+/// moved to another thread or task. Submitting requires a mutable borrow, so threads submitting
+/// concurrently should share a [`crate::SyncAccountsHandler`] instead.
 pub struct AccountsHandler {
     /// Map of client id to accounts
-    accounts: HashMap<ClientId, Account>,
-    /// Set of txids, used to ensure global uniqueness of txids
-    txids: HashSet<Txid>,
+    accounts: AccountMap,
+    /// Set of consumed txids, keyed by client id only if txids are unique per client
+    txids: HashSet<TxidKey>,
+    /// Set of txids consumed in the previous window, kept only if txids are forgotten after one
+    retired_txids: HashSet<TxidKey>,
+    /// Policy for handling transactions
+    config: HandlerConfig,
+    /// Counts of the outcomes of submitted transactions
+    stats: IngestionStats,
+    /// Sequence number of the last submitted transaction
+    sequence: u64,
+    /// Buffered disputes, resolves and chargebacks with their sequence numbers, keyed like the
+    /// txids they reference
+    pending: HashMap<TxidKey, Vec<(u64, Transaction)>>,
+    /// Transactions that consumed each txid, retained only if resubmits are idempotent
+    submitted: HashMap<TxidKey, Transaction>,
+    /// Number of transactions applied per client, counted only if rate limited
+    applied: HashMap<ClientId, usize>,
+    /// Clients whose accounts changed since the last call to [`AccountsHandler::snapshot_dirty`]
+    dirty: HashSet<ClientId>,
+    /// Factory of the opening snapshot of each new account, if not opened empty
+    account_factory: Option<AccountFactory>,
+    /// Handler following the shadow policy, fed every submitted transaction, if any
+    shadow: Option<Box<AccountsHandler>>,
+    /// Applied transactions in the order they were applied, logged only if configured to
+    events: Vec<Transaction>,
+    /// Callback invoked with each applied transaction, if any
+    on_applied: Option<AppliedCallback>,
+    /// Allocator of the txids of the transactions submitted through the convenience methods
+    txid_allocator: TxidAllocator,
+    /// Withdrawal fees collected from the accounts of each client since closed
+    closed_fees: HashMap<ClientId, Decimal>,
+}
+
+/// Report of the end of input returned by [`AccountsHandler::finalize`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinalizeReport {
+    /// Buffered transactions dropped as their referenced txid never arrived, in submission order
+    pub dropped: Vec<Transaction>,
+    /// Counts of the outcomes of every submitted transaction, including the dropped ones
+    pub stats: IngestionStats,
+    /// Result of checking the invariants of every account
+    pub invariants: Result<(), InvariantError>,
+}
+
+impl FinalizeReport {
+    /// Check whether nothing was dropped and every invariant holds
+    pub fn is_empty(&self) -> bool {
+        self.dropped.is_empty() && self.invariants.is_ok()
+    }
+}
+
+/// Serializable state of an accounts handler, used to persist it across restarts
+#[derive(Deserialize, Serialize)]
+pub struct HandlerState {
+    /// Every account, including its transaction records
+    accounts: Vec<Account>,
+    /// Set of consumed txids
+    txids: HashSet<TxidKey>,
+    /// Policy for handling transactions
+    config: HandlerConfig,
+    /// Sequence number of the last submitted transaction
+    sequence: u64,
+    /// Withdrawal fees collected from the accounts of each client since closed
+    #[serde(default)]
+    closed_fees: HashMap<ClientId, Decimal>,
+}
+
+impl Default for AccountsHandler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AccountsHandler {
-    /// Create a new accounts handler
+    /// Create a new accounts handler, the same as [`AccountsHandler::default`]
     pub fn new() -> Self {
+        Self::with_capacity(0, 0)
+    }
+
+    /// Create a new accounts handler with room for at least `clients` accounts and `txids`
+    /// transaction ids without reallocating
+    pub fn with_capacity(clients: usize, txids: usize) -> Self {
+        Self {
+            accounts: AccountMap::with_capacity_and_hasher(clients, Default::default()),
+            txids: HashSet::with_capacity(txids),
+            retired_txids: HashSet::new(),
+            config: HandlerConfig::default(),
+            stats: IngestionStats::default(),
+            sequence: 0,
+            pending: HashMap::new(),
+            submitted: HashMap::new(),
+            applied: HashMap::new(),
+            dirty: HashSet::new(),
+            account_factory: None,
+            shadow: None,
+            events: Vec::new(),
+            on_applied: None,
+            txid_allocator: TxidAllocator::new(),
+            closed_fees: HashMap::new(),
+        }
+    }
+
+    /// Create a new accounts handler whose accounts record the history of applied transactions
+    pub fn new_with_history() -> Self {
+        Self::with_config(HandlerConfig {
+            history: true,
+            ..HandlerConfig::default()
+        })
+    }
+
+    /// Create a new accounts handler following the given policy
+    pub fn with_config(config: HandlerConfig) -> Self {
+        Self {
+            config,
+            ..Self::new()
+        }
+    }
+
+    /// Create a new accounts handler following the given policy, opening each new account with
+    /// the snapshot `factory` returns for its client, e.g. to lock the accounts of clients
+    /// flagged in a registry
+    ///
+    /// Accounts are opened as with [`AccountsHandler::seed_from_snapshot`], so the snapshot must
+    /// be of the given client, with non-negative balances adding up to its total. Otherwise,
+    /// opening the account panics.
+    pub fn with_account_factory(
+        config: HandlerConfig,
+        factory: impl Fn(ClientId) -> AccountSnapshot + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            account_factory: Some(Box::new(factory)),
+            ..Self::with_config(config)
+        }
+    }
+
+    /// Create a new accounts handler following `config`, alongside a shadow handler following
+    /// `shadow_config`, e.g. to verify a new policy against production data
+    ///
+    /// Every submitted transaction is also submitted to the shadow handler, whose outcomes are
+    /// discarded, so the snapshots of this handler are unaffected. Administrative operations such
+    /// as seeding or freezing are not mirrored, and the shadow handler is neither dumped nor
+    /// merged.
+    pub fn with_shadow(config: HandlerConfig, shadow_config: HandlerConfig) -> Self {
         Self {
-            accounts: HashMap::new(),
-            txids: HashSet::new(),
+            shadow: Some(Box::new(Self::with_config(shadow_config))),
+            ..Self::with_config(config)
+        }
+    }
+
+    /// Register `callback` to be invoked after each applied transaction, e.g. to publish it to a
+    /// message bus, replacing any callback registered before
+    ///
+    /// The callback is passed the transaction and the resulting snapshot of its account, the
+    /// sender's for a transfer, and is not invoked for ignored, buffered or rejected transactions.
+    /// A buffered transaction fires once it is replayed and applied. The callback runs while the
+    /// handler is borrowed mutably, so it cannot access the handler, and must be `Send` and `Sync`
+    /// for the handler to be shared between threads.
+    pub fn on_applied(
+        &mut self,
+        callback: impl FnMut(&Transaction, &AccountSnapshot) + Send + Sync + 'static,
+    ) {
+        self.on_applied = Some(Box::new(callback));
+    }
+
+    /// Get the shadow handler, if created with [`AccountsHandler::with_shadow`]
+    pub fn shadow(&self) -> Option<&AccountsHandler> {
+        self.shadow.as_deref()
+    }
+
+    /// Compare the accounts of this handler to those of the shadow handler, reporting the change
+    /// from the former to the latter of every account that diverges in order of client id
+    ///
+    /// Without a shadow handler, nothing diverges.
+    pub fn shadow_divergence(&self) -> Vec<SnapshotDelta> {
+        self.shadow.as_ref().map_or_else(Vec::new, |shadow| {
+            diff_snapshots(
+                &self.snapshot_accounts_sorted(),
+                &shadow.snapshot_accounts_sorted(),
+            )
+        })
+    }
+
+    /// Demultiplex a transaction by client id, reporting whether it was applied or ignored
+    ///
+    /// A transfer debits the sending client's account and, only if the debit is applied, credits
+    /// the recipient's account, creating it if needed. A transaction failing with an error leaves
+    /// every account untouched and does not consume its txid.
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn submit_transaction(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        self.sequence += 1;
+        if let Some(shadow) = &mut self.shadow {
+            // the shadow's outcomes only show in its divergence
+            let _ = shadow.submit_transaction(tx);
+        }
+        let result = self.try_submit(tx);
+        self.stats.record(&tx, &result);
+        // start a new generation of txids at the end of each window, if configured to
+        if self
+            .config
+            .txid_window
+            .is_some_and(|window| self.sequence.is_multiple_of(window))
+        {
+            self.retired_txids = std::mem::take(&mut self.txids);
+        }
+        result
+    }
+
+    /// Submit a transaction bypassing the lock of the client's account, e.g. for a court-ordered
+    /// credit to a locked account
+    ///
+    /// The account is locked again afterward, as strictly as a chargeback applied by `tx` locks
+    /// it if stricter. Only the client's own lock is bypassed, so a transfer to a locked recipient
+    /// is still not applied. Every forced transaction is logged at `warn` for auditing.
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn submit_transaction_forced(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        let Some(status) = self.status(tx.client_id) else {
+            return self.submit_transaction(tx);
+        };
+        warn!(
+            "client {}: forcing transaction {} on a {:?} account",
+            tx.client_id, tx.txid, status
+        );
+        // the shadow is forced alike rather than submitted to as usual
+        let mut shadow = self.shadow.take();
+        if let Some(shadow) = &mut shadow {
+            let _ = shadow.submit_transaction_forced(tx);
+        }
+        self.account_mut(tx.client_id).unlock();
+        let result = self.submit_transaction(tx);
+        self.account_mut(tx.client_id).relock(status);
+        self.shadow = shadow;
+        result
+    }
+
+    /// Deposit `amount` to the client's account under a fresh txid, returning the txid along with
+    /// the outcome
+    ///
+    /// Txids are allocated in increasing order, skipping those already consumed, so that
+    /// transactions built by hand need no txid bookkeeping.
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn deposit(
+        &mut self,
+        client_id: ClientId,
+        amount: Amount,
+    ) -> Result<(Txid, ProcessOutcome), TransactionError> {
+        self.submit_fresh(client_id, TransactionType::Deposit(amount))
+    }
+
+    /// Withdraw `amount` from the client's account under a fresh txid, returning the txid along
+    /// with the outcome
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn withdraw(
+        &mut self,
+        client_id: ClientId,
+        amount: Amount,
+    ) -> Result<(Txid, ProcessOutcome), TransactionError> {
+        self.submit_fresh(client_id, TransactionType::Withdrawal(amount))
+    }
+
+    /// Dispute the client's deposit or withdrawal with `txid`
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn dispute(
+        &mut self,
+        client_id: ClientId,
+        txid: Txid,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        self.submit_reference(client_id, TransactionType::Dispute, txid)
+    }
+
+    /// Resolve the client's disputed deposit or withdrawal with `txid`
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn resolve(
+        &mut self,
+        client_id: ClientId,
+        txid: Txid,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        self.submit_reference(client_id, TransactionType::Resolve, txid)
+    }
+
+    /// Chargeback the client's disputed deposit or withdrawal with `txid`
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn chargeback(
+        &mut self,
+        client_id: ClientId,
+        txid: Txid,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        self.submit_reference(client_id, TransactionType::Chargeback, txid)
+    }
+
+    /// Submit a generative transaction of the client under a fresh txid
+    fn submit_fresh(
+        &mut self,
+        client_id: ClientId,
+        tx_type: TransactionType,
+    ) -> Result<(Txid, ProcessOutcome), TransactionError> {
+        let mut tx = Transaction {
+            tx_type,
+            client_id,
+            txid: self.txid_allocator.allocate(),
+            timestamp: None,
+        };
+        while self.is_consumed(&self.txid_key(tx)) {
+            tx.txid = self.txid_allocator.allocate();
+        }
+        let outcome = self.submit_transaction(tx)?;
+        Ok((tx.txid, outcome))
+    }
+
+    /// Submit a transaction of the client referencing `txid`
+    fn submit_reference(
+        &mut self,
+        client_id: ClientId,
+        tx_type: TransactionType,
+        txid: Txid,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        self.submit_transaction(Transaction {
+            tx_type,
+            client_id,
+            txid,
+            timestamp: None,
+        })
+    }
+
+    /// Check whether the txid of `key` was consumed and is still remembered
+    fn is_consumed(&self, key: &TxidKey) -> bool {
+        self.txids.contains(key)
+            || (!self.retired_txids.is_empty() && self.retired_txids.contains(key))
+    }
+
+    /// Submit a transaction without counting its outcome
+    fn try_submit(&mut self, tx: Transaction) -> Result<ProcessOutcome, TransactionError> {
+        // reject transactions of clients that reached the cap, if configured to
+        if let Some(cap) = self.config.max_transactions_per_client {
+            if self.applied.get(&tx.client_id).copied().unwrap_or_default() >= cap {
+                return Err(TransactionError::RateLimited {
+                    client_id: tx.client_id,
+                });
+            }
+        }
+
+        if let TransactionType::Transfer { to, amount } = tx.tx_type {
+            // reject transfers to self
+            if to == tx.client_id {
+                return Err(TransactionError::SelfTransfer(tx.client_id));
+            }
+            // reject negative amounts outright if configured to, which only transfers can carry
+            if self.config.negative_amount_policy == NegativeAmountPolicy::Reject
+                && amount < Decimal::ZERO
+            {
+                return Err(TransactionError::NegativeAmount { txid: tx.txid });
+            }
+        }
+
+        // ensure txid is unique within its scope
+        let key = self.txid_key(tx);
+        let generative = tx.is_generative_tx();
+        let mut amended = None;
+        if generative && self.is_consumed(&key) {
+            // an identical resubmission is harmless, if configured to be
+            if self.config.idempotent_resubmits && self.submitted.get(&key) == Some(&tx) {
+                return Ok(ProcessOutcome::Idempotent);
+            }
+            // a repeated deposit amends the earlier one, if configured to
+            amended = self.amend_deposit(tx);
+            if amended.is_none() {
+                warn!("client {}: duplicate txid {}", tx.client_id, tx.txid);
+                return Err(TransactionError::DuplicateTxid(tx.txid));
+            }
+        }
+
+        let known = self.accounts.contains_key(&tx.client_id);
+        let routed = match amended {
+            Some(result) => result?,
+            None => self.route(tx)?,
+        };
+        let outcome = match routed {
+            // tell apart txids never seen at all from those not recorded on the account
+            ProcessOutcome::Ignored(IgnoreReason::NotADeposit) if !self.is_consumed(&key) => {
+                // hold references to txids not seen yet until they arrive, if configured to
+                if self.config.buffer_disputes {
+                    self.pending
+                        .entry(key)
+                        .or_default()
+                        .push((self.sequence, tx));
+                    return Ok(ProcessOutcome::Buffered);
+                }
+                ProcessOutcome::Ignored(IgnoreReason::UnknownTxid)
+            }
+            // reject references to a globally unique txid recorded on another client's account
+            ProcessOutcome::Ignored(IgnoreReason::NotADeposit)
+                if self.config.uniqueness == UniquenessScope::Global =>
+            {
+                if let Some(owner) = self.record_owner(tx.txid) {
+                    warn!(
+                        "client {}: txid {} belongs to client {}",
+                        tx.client_id, tx.txid, owner
+                    );
+                    // failing leaves every account untouched
+                    if !known {
+                        self.accounts.remove(&tx.client_id);
+                    }
+                    return Err(TransactionError::TxidClientMismatch {
+                        txid: tx.txid,
+                        owner,
+                        got: tx.client_id,
+                    });
+                }
+                ProcessOutcome::Ignored(IgnoreReason::NotADeposit)
+            }
+            outcome => outcome,
+        };
+
+        // log and report before replaying the buffered transactions referencing it, which are
+        // applied after
+        if outcome.is_applied() {
+            if self.config.event_log {
+                self.events.push(tx);
+            }
+            if let (Some(callback), Some(account)) =
+                (&mut self.on_applied, self.accounts.get(&tx.client_id))
+            {
+                callback(&tx, &account.snapshot());
+            }
+        }
+
+        // consume txid, even if the transaction was ignored
+        if generative {
+            self.txids.insert(key);
+            if self.config.idempotent_resubmits {
+                self.submitted.insert(key, tx);
+            }
+            // skip hashing the key when nothing is buffered, as for most deposits
+            if !self.pending.is_empty() {
+                self.replay_pending(key);
+            }
+        }
+
+        if outcome.is_applied() {
+            self.dirty.insert(tx.client_id);
+            if let TransactionType::Transfer { to, .. } = tx.tx_type {
+                self.dirty.insert(to);
+            }
+            if self.config.max_transactions_per_client.is_some() {
+                *self.applied.entry(tx.client_id).or_default() += 1;
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Replay the transactions buffered for `key` in submission order, counting their outcomes
+    fn replay_pending(&mut self, key: TxidKey) {
+        for (_, tx) in self.pending.remove(&key).unwrap_or_default() {
+            let result = self.try_submit(tx);
+            self.stats.record(&tx, &result);
+        }
+    }
+
+    /// Drop every buffered transaction whose referenced txid never arrived, e.g. at the end of
+    /// input, counting each as ignored for an unknown txid and returning them in submission order
+    pub fn flush_pending(&mut self) -> Vec<Transaction> {
+        let mut pending: Vec<_> = self.pending.drain().flat_map(|(_, txs)| txs).collect();
+        pending.sort_unstable_by_key(|(sequence, _)| *sequence);
+        pending
+            .into_iter()
+            .map(|(_, tx)| {
+                let ignored = Ok(ProcessOutcome::Ignored(IgnoreReason::UnknownTxid));
+                self.stats.record(&tx, &ignored);
+                tx
+            })
+            .collect()
+    }
+
+    /// Mark the end of input, dropping the buffered transactions as [`AccountsHandler::flush_pending`]
+    /// does and checking the invariants of every account
+    ///
+    /// Without buffered transactions, nothing changes and the report is empty besides the stats.
+    /// The handler may keep being used afterwards.
+    pub fn finalize(&mut self) -> FinalizeReport {
+        let dropped = self.flush_pending();
+        FinalizeReport {
+            dropped,
+            stats: self.stats.clone(),
+            invariants: self.validate_invariants(),
+        }
+    }
+
+    /// Submit a transaction and get a fresh snapshot of the account of its client, e.g. for
+    /// interactive use
+    ///
+    /// The snapshot reflects the current state even if the transaction was ignored, and that of an
+    /// empty account if the client still has none, e.g. after a buffered dispute. For a transfer,
+    /// the snapshot is of the sender's account.
+    pub fn submit_and_snapshot(
+        &mut self,
+        tx: Transaction,
+    ) -> Result<AccountSnapshot, TransactionError> {
+        self.submit_transaction(tx)?;
+        Ok(self
+            .snapshot(tx.client_id)
+            .unwrap_or_else(|| Account::with_config(tx.client_id, &self.config).snapshot()))
+    }
+
+    /// Submit transactions in order, reporting the result of each one
+    ///
+    /// The results must be used, e.g. to report rejected transactions. To drop them, extend the
+    /// handler with the transactions instead.
+    ///
+    /// ```compile_fail
+    /// #![deny(unused_must_use)]
+    /// use coding_test::{AccountsHandler, Amount, ClientId, Transaction, TransactionType, Txid};
+    /// use rust_decimal::Decimal;
+    ///
+    /// let mut accounts = AccountsHandler::new();
+    /// accounts.submit_batch([Transaction {
+    ///     tx_type: TransactionType::Deposit(Amount::new(Decimal::ONE).unwrap()),
+    ///     client_id: ClientId(1),
+    ///     txid: Txid(1),
+    ///     timestamp: None,
+    /// }]);
+    /// ```
+    #[must_use = "transactions may be rejected with errors, which should be handled"]
+    pub fn submit_batch(
+        &mut self,
+        txs: impl IntoIterator<Item = Transaction>,
+    ) -> Vec<Result<ProcessOutcome, TransactionError>> {
+        txs.into_iter()
+            .map(|tx| self.submit_transaction(tx))
+            .collect()
+    }
+
+    /// Submit transactions in order up to the cutoff timestamp `as_of`, e.g. to reconstruct the
+    /// accounts at that point in time, dropping errors like [`Extend`] does
+    ///
+    /// Transactions timestamped after the cutoff are not submitted and are returned in order
+    /// instead, so they can be submitted later. Those without a timestamp are always submitted.
+    pub fn ingest_until(
+        &mut self,
+        txs: impl IntoIterator<Item = Transaction>,
+        as_of: u64,
+    ) -> Vec<Transaction> {
+        let mut deferred = Vec::new();
+        for tx in txs {
+            if tx.timestamp.is_some_and(|timestamp| timestamp > as_of) {
+                deferred.push(tx);
+            } else {
+                let _ = self.submit_transaction(tx);
+            }
+        }
+        deferred
+    }
+
+    /// Process transactions across `shards` worker threads, each owning the accounts of a disjoint
+    /// set of clients, and merge the resulting accounts into a single handler
+    ///
+    /// Transactions are partitioned by client id, preserving their order within each client's
+    /// stream, so disputes, resolves and chargebacks must still follow the transaction they
+    /// reference within that stream. Duplicate txids are dropped up front on the calling thread,
+    /// thus a transaction failing with an error still consumes its txid, unlike with
+    /// [`AccountsHandler::submit_transaction`]. A reference to the txid of another client, which
+    /// may be owned by another shard, is settled once every shard is done, so its outcome matches
+    /// the sequential one. As a transfer affects two accounts, inputs containing any transfer are
+    /// processed sequentially instead.
+    pub fn process_parallel(txs: Vec<Transaction>, shards: usize) -> Self {
+        let mut handler = Self::new();
+        let has_transfer = txs
+            .iter()
+            .any(|tx| matches!(tx.tx_type, TransactionType::Transfer { .. }));
+        if shards <= 1 || has_transfer {
+            handler.extend(txs);
+            return handler;
+        }
+
+        // partition by client id, dropping duplicate txids and numbering the rest in input order,
+        // and flag references to the txids of other clients
+        let mut partitions = vec![Vec::new(); shards];
+        let mut owners = HashMap::new();
+        for tx in txs {
+            handler.sequence += 1;
+            let key = handler.txid_key(tx);
+            if tx.is_generative_tx() && !handler.txids.insert(key) {
+                let duplicate = Err(TransactionError::DuplicateTxid(tx.txid));
+                handler.stats.record(&tx, &duplicate);
+                continue;
+            }
+            let foreign = if tx.is_generative_tx() {
+                owners.insert(tx.txid, tx.client_id);
+                false
+            } else {
+                owners
+                    .get(&tx.txid)
+                    .is_some_and(|&owner| owner != tx.client_id)
+            };
+            partitions[usize::from(tx.client_id.0) % shards].push((handler.sequence, tx, foreign));
+        }
+
+        // process each partition on its own thread, leaving the outcomes of foreign references
+        // uncounted
+        let shard_results: Vec<(Self, Vec<_>)> = thread::scope(|scope| {
+            let workers: Vec<_> = partitions
+                .into_iter()
+                .map(|partition| {
+                    scope.spawn(move || {
+                        let mut shard_handler = Self::new();
+                        let mut foreign_results = Vec::new();
+                        for (sequence, tx, foreign) in partition {
+                            shard_handler.sequence = sequence - 1;
+                            if foreign {
+                                // the txid was consumed by the shard of its owner
+                                let key = shard_handler.txid_key(tx);
+                                shard_handler.txids.insert(key);
+                                shard_handler.sequence += 1;
+                                foreign_results.push((tx, shard_handler.try_submit(tx)));
+                            } else {
+                                let _ = shard_handler.submit_transaction(tx);
+                            }
+                        }
+                        (shard_handler, foreign_results)
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("shard worker panicked"))
+                .collect()
+        });
+
+        // merge the disjoint accounts of every shard
+        let mut foreign_results = Vec::new();
+        for (shard_handler, results) in shard_results {
+            handler.accounts.extend(shard_handler.accounts);
+            handler.stats.merge(&shard_handler.stats);
+            foreign_results.extend(results);
+        }
+
+        // reject the foreign references whose txid is recorded on its owner's account, as the
+        // sequential path does
+        for (tx, mut result) in foreign_results {
+            if result == Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit)) {
+                if let Some(owner) = handler.record_owner(tx.txid) {
+                    result = Err(TransactionError::TxidClientMismatch {
+                        txid: tx.txid,
+                        owner,
+                        got: tx.client_id,
+                    });
+                }
+            }
+            handler.stats.record(&tx, &result);
+        }
+
+        handler
+    }
+
+    /// Submit every transaction read from CSV `reader` with a header row, validating amounts
+    /// against the handler's maximum scale, and count the outcomes of this ingestion alone
+    ///
+    /// Rows that cannot be parsed or validated are skipped and counted as errors.
+    pub fn ingest_csv<R: io::Read>(&mut self, reader: R) -> io::Result<IngestionStats> {
+        let config = ValidationConfig {
+            max_scale: self.config.max_scale,
+            ..ValidationConfig::default()
+        };
+        let mut stats = IngestionStats::default();
+        read_csv_transactions(reader, &config, CsvOptions::default(), |_, tx| match tx {
+            Ok(tx) => {
+                let result = self.submit_transaction(tx);
+                stats.record(&tx, &result);
+            }
+            Err(_) => stats.errors += 1,
+        })?;
+        Ok(stats)
+    }
+
+    /// Get the sequence number of the last submitted transaction, i.e. the number of transactions
+    /// submitted since the handler was created or reset
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Get the transactions applied since the handler was created or reset, in the order they were
+    /// applied, if created with [`HandlerConfig::event_log`]
+    ///
+    /// Replaying them into a fresh handler with the same configuration reproduces the same
+    /// snapshots. Ignored and rejected transactions are left out, as are administrative operations
+    /// such as seeding or freezing, which replaying thus does not reproduce. The log is neither
+    /// dumped nor loaded, and merging appends `other`'s log to this handler's.
+    pub fn export_events(&self) -> Vec<Transaction> {
+        self.events.clone()
+    }
+
+    /// Get the counts of the outcomes of every transaction submitted since the handler was
+    /// created, loaded or reset
+    pub fn stats(&self) -> &IngestionStats {
+        &self.stats
+    }
+
+    /// Find the client whose account records the deposit or withdrawal with `txid`
+    fn record_owner(&self, txid: Txid) -> Option<ClientId> {
+        self.accounts
+            .values()
+            .find(|account| account.has_record(txid))
+            .map(|account| account.id())
+    }
+
+    /// Key a transaction's txid by the scope it must be unique within
+    fn txid_key(&self, tx: Transaction) -> TxidKey {
+        match self.config.uniqueness {
+            UniquenessScope::Global => (None, tx.txid),
+            UniquenessScope::PerClient => (Some(tx.client_id), tx.txid),
+        }
+    }
+
+    /// Route a transaction to the account(s) it affects
+    ///
+    /// An account created for the transaction is dropped again if the transaction fails with an
+    /// error, as is the recipient created for a transfer that is not applied.
+    fn route(&mut self, tx: Transaction) -> Result<ProcessOutcome, TransactionError> {
+        let known = self.accounts.contains_key(&tx.client_id);
+        let result = self.route_to_accounts(tx);
+        if result.is_err() && !known {
+            self.accounts.remove(&tx.client_id);
+        }
+        result
+    }
+
+    /// Route a transaction to the account(s) it affects, leaving the sender to [`Self::route`]
+    fn route_to_accounts(&mut self, tx: Transaction) -> Result<ProcessOutcome, TransactionError> {
+        let TransactionType::Transfer { to, amount } = tx.tx_type else {
+            let sequence = self.sequence;
+            return self.account_mut(tx.client_id).process(tx, sequence);
+        };
+
+        // resolve the recipient, possibly through the account factory, to check it before
+        // debiting the sender
+        let known = self.accounts.contains_key(&to);
+        let recipient = self.account_mut(to);
+        let checked = if recipient.is_locked() {
+            // a locked recipient cannot be credited, so the transfer must not debit the sender
+            // either
+            Some(recipient.locked_outcome())
+        } else {
+            // fail before debiting the sender if the credit would overflow or exceed the cap
+            recipient.check_credit(amount).err().map(Err)
+        };
+        let result = match checked {
+            Some(result) => result,
+            None => {
+                let sequence = self.sequence;
+                self.account_mut(tx.client_id).process(tx, sequence)
+            }
+        };
+
+        match result {
+            // credit recipient of an applied transfer, which cannot fail as it was checked above
+            Ok(ProcessOutcome::Applied) => self.account_mut(to).credit(tx, amount)?,
+            // a recipient that is not credited must not be created
+            _ if !known => {
+                self.accounts.remove(&to);
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Amend the earlier deposit of the client with the txid of repeated deposit `tx` following
+    /// the repeat deposit policy, returning `None` if `tx` must be rejected as a duplicate instead
+    fn amend_deposit(
+        &mut self,
+        tx: Transaction,
+    ) -> Option<Result<ProcessOutcome, TransactionError>> {
+        let TransactionType::Deposit(amount) = tx.tx_type else {
+            return None;
+        };
+        let policy = self.config.repeat_deposit_policy;
+        self.accounts
+            .get_mut(&tx.client_id)?
+            .amend_deposit(tx, amount.value(), policy)
+    }
+
+    /// Get the account for a client, creating it with the account factory if needed
+    fn account_mut(&mut self, client_id: ClientId) -> &mut Account {
+        let config = self.config;
+        let factory = &self.account_factory;
+        self.accounts.entry(client_id).or_insert_with(|| {
+            let Some(factory) = factory else {
+                return Account::with_config(client_id, &config);
+            };
+            let snapshot = factory(client_id);
+            assert_eq!(
+                snapshot.id, client_id,
+                "account factory returned a snapshot of another client"
+            );
+            snapshot
+                .try_into_account(&config)
+                .unwrap_or_else(|e| panic!("account factory returned an invalid snapshot: {}", e))
+        })
+    }
+
+    /// Get a snapshot of a single account, if the client is known
+    ///
+    /// # Panics
+    ///
+    /// Panics if the account's total balance overflows, which only holding and depositing amounts
+    /// near `Decimal::MAX` can cause. Use [`AccountsHandler::try_snapshot`] where that is possible.
+    pub fn snapshot(&self, client_id: ClientId) -> Option<AccountSnapshot> {
+        self.accounts.get(&client_id).map(|a| a.snapshot())
+    }
+
+    /// Get a snapshot of a single account, if the client is known, failing with
+    /// [`InvariantError::TotalOverflow`] if its total balance overflows
+    pub fn try_snapshot(
+        &self,
+        client_id: ClientId,
+    ) -> Option<Result<AccountSnapshot, InvariantError>> {
+        self.accounts.get(&client_id).map(Account::try_snapshot)
+    }
+
+    /// Get the full-precision available balance of a client's account, if the client is known
+    pub fn available(&self, client_id: ClientId) -> Option<Decimal> {
+        self.accounts.get(&client_id).map(Account::available)
+    }
+
+    /// Get the full-precision held balance of a client's account, if the client is known
+    pub fn held(&self, client_id: ClientId) -> Option<Decimal> {
+        self.accounts.get(&client_id).map(Account::held)
+    }
+
+    /// Get the full-precision total balance of a client's account, if the client is known
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total overflows, which only holding and depositing amounts near
+    /// `Decimal::MAX` can cause. Use [`AccountsHandler::try_total`] where that is possible.
+    pub fn total(&self, client_id: ClientId) -> Option<Decimal> {
+        self.try_total(client_id).map(|total| match total {
+            Ok(total) => total,
+            Err(e) => panic!("{}", e),
+        })
+    }
+
+    /// Get the full-precision total balance of a client's account, if the client is known,
+    /// failing with [`InvariantError::TotalOverflow`] if it overflows
+    pub fn try_total(&self, client_id: ClientId) -> Option<Result<Decimal, InvariantError>> {
+        self.accounts.get(&client_id).map(|account| {
+            account
+                .available()
+                .checked_add(account.held())
+                .ok_or(InvariantError::TotalOverflow { client_id })
+        })
+    }
+
+    /// Get the sum of the withdrawal fees collected from every account, including those since
+    /// closed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum overflows, which only fees adding up to near `Decimal::MAX` can cause.
+    /// Use [`AccountsHandler::try_collected_fees`] where that is possible.
+    pub fn collected_fees(&self) -> Decimal {
+        self.try_collected_fees()
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Get the sum of the withdrawal fees collected from every account, including those since
+    /// closed, failing with [`TransactionError::BalanceOverflow`] for a client whose fees make it
+    /// overflow
+    pub fn try_collected_fees(&self) -> Result<Decimal, TransactionError> {
+        self.accounts
+            .values()
+            .map(|account| (account.id(), account.collected_fees()))
+            .chain(self.closed_fees.iter().map(|(&id, &fees)| (id, fees)))
+            .try_fold(Decimal::ZERO, |total, (client_id, fees)| {
+                add_fees(client_id, total, fees)
+            })
+    }
+
+    /// Get the history of applied transactions of a client's account, if the client is known and
+    /// the handler was created with [`AccountsHandler::new_with_history`]
+    pub fn history(&self, client_id: ClientId) -> Option<&[AppliedEvent]> {
+        self.accounts.get(&client_id).and_then(Account::history)
+    }
+
+    /// Get the txids of a client's disputed deposits and withdrawals in ascending order, along with
+    /// the amount each dispute holds, if the client is known
+    ///
+    /// A seeded held balance is not tied to any dispute and thus not listed.
+    pub fn disputed_breakdown(&self, client_id: ClientId) -> Option<Vec<(Txid, Decimal)>> {
+        self.accounts
+            .get(&client_id)
+            .map(Account::disputed_breakdown)
+    }
+
+    /// Get whether a client's transaction with `txid` is a recorded deposit or withdrawal, if it is
+    /// either, e.g. to enforce a policy of one's own on disputes of withdrawals
+    pub fn record_kind(&self, client_id: ClientId, txid: Txid) -> Option<TxKind> {
+        self.accounts
+            .get(&client_id)
+            .and_then(|account| account.record_kind(txid))
+    }
+
+    /// Get every dispute still open across all accounts, in ascending order of client id then
+    /// txid, along with the amount each dispute holds
+    ///
+    /// This reports the disputes neither resolved nor charged back, e.g. at the end of ingestion.
+    pub fn open_disputes(&self) -> Vec<(ClientId, Txid, Decimal)> {
+        self.client_ids()
+            .into_iter()
+            .flat_map(|client_id| {
+                self.accounts[&client_id]
+                    .disputed_breakdown()
+                    .into_iter()
+                    .map(move |(txid, held)| (client_id, txid, held))
+            })
+            .collect()
+    }
+
+    /// Check whether an account exists for the client
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.accounts.contains_key(&client_id)
+    }
+
+    /// Get the number of distinct clients with an account
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Get the ids of every client with an account, sorted in ascending order
+    pub fn client_ids(&self) -> Vec<ClientId> {
+        let mut client_ids: Vec<_> = self.accounts.keys().copied().collect();
+        client_ids.sort_unstable();
+        client_ids
+    }
+
+    /// Get the number of accepted deposits, withdrawals and transfers, i.e. consumed txids
+    ///
+    /// If txids are forgotten after a window, only those still remembered are counted.
+    pub fn transaction_count(&self) -> usize {
+        self.txids.len() + self.retired_txids.len()
+    }
+
+    /// Check whether `txid` was already consumed by an accepted deposit, withdrawal or transfer
+    ///
+    /// Under [`UniquenessScope::PerClient`], this checks whether any client consumed `txid`.
+    pub fn has_txid(&self, txid: Txid) -> bool {
+        match self.config.uniqueness {
+            UniquenessScope::Global => self.is_consumed(&(None, txid)),
+            UniquenessScope::PerClient => self
+                .txids
+                .iter()
+                .chain(&self.retired_txids)
+                .any(|(_, t)| *t == txid),
+        }
+    }
+
+    /// Get the number of consumed txids, the same as [`Self::transaction_count`]
+    pub fn txid_count(&self) -> usize {
+        self.transaction_count()
+    }
+
+    /// Get the status of a client's account, if the client is known
+    pub fn status(&self, client_id: ClientId) -> Option<AccountStatus> {
+        self.accounts.get(&client_id).map(Account::status)
+    }
+
+    /// Unlock a client's account, e.g. after a chargeback was adjudicated in the institution's
+    /// favor, whatever the reason it is locked
+    pub fn unlock_account(&mut self, client_id: ClientId) -> Result<(), TransactionError> {
+        let account = self
+            .accounts
+            .get_mut(&client_id)
+            .ok_or(TransactionError::UnknownClient(client_id))?;
+        account.unlock();
+        self.dirty.insert(client_id);
+        Ok(())
+    }
+
+    /// Freeze a client's account, e.g. when it looks suspicious, locking it without charging back
+    /// any transaction
+    ///
+    /// A frozen account is locked just like after a chargeback, so it is subject to the locked
+    /// policy and shows as locked in snapshots, but its status tells the two apart: a frozen
+    /// account is unlocked by [`Self::unfreeze_account`] or [`Self::unlock_account`], whereas an
+    /// account locked by a chargeback is only unlocked by the latter and stays so when frozen.
+    pub fn freeze_account(&mut self, client_id: ClientId) -> Result<(), TransactionError> {
+        let account = self
+            .accounts
+            .get_mut(&client_id)
+            .ok_or(TransactionError::UnknownClient(client_id))?;
+        account.freeze();
+        self.dirty.insert(client_id);
+        Ok(())
+    }
+
+    /// Unfreeze a client's account frozen with [`Self::freeze_account`]
+    ///
+    /// Fails without unlocking the account if it is locked by a chargeback, which only
+    /// [`Self::unlock_account`] unlocks.
+    pub fn unfreeze_account(&mut self, client_id: ClientId) -> Result<(), TransactionError> {
+        self.accounts
+            .get_mut(&client_id)
+            .ok_or(TransactionError::UnknownClient(client_id))?
+            .unfreeze()?;
+        self.dirty.insert(client_id);
+        Ok(())
+    }
+
+    /// Reverse a client's deposit administratively, e.g. an erroneous credit, debiting its amount
+    /// from the available balance without the dispute lifecycle and without locking the account
+    ///
+    /// The deposit must be undisputed and covered by the available balance, and can no longer be
+    /// disputed afterwards. Its txid stays consumed.
+    pub fn reverse_deposit(
+        &mut self,
+        client_id: ClientId,
+        txid: Txid,
+    ) -> Result<(), TransactionError> {
+        self.accounts
+            .get_mut(&client_id)
+            .ok_or(TransactionError::UnknownClient(client_id))?
+            .reverse_deposit(txid)?;
+        self.dirty.insert(client_id);
+        Ok(())
+    }
+
+    /// Resolve every dispute opened more than `max_age` submissions ago, releasing its held funds,
+    /// and return the number of disputes resolved
+    ///
+    /// Every call to [`AccountsHandler::submit_transaction`] counts as a submission, whatever its
+    /// outcome.
+    pub fn auto_resolve_stale(&mut self, max_age: u64) -> Result<usize, TransactionError> {
+        let sequence = self.sequence;
+        let mut resolved = 0;
+        for account in self.accounts.values_mut() {
+            let count = account.resolve_stale(sequence, max_age)?;
+            if count > 0 {
+                self.dirty.insert(account.id());
+            }
+            resolved += count;
+        }
+        Ok(resolved)
+    }
+
+    /// Resolve every open dispute of a client's account, e.g. after a batch adjudication, releasing
+    /// the held funds, and return the number of disputes resolved, zero for an unknown client
+    ///
+    /// The disputes are resolved in the order they were opened, without consuming any txid.
+    pub fn resolve_all(&mut self, client_id: ClientId) -> usize {
+        let sequence = self.sequence;
+        let resolved = self
+            .accounts
+            .get_mut(&client_id)
+            .map_or(0, |account| account.resolve_all(sequence));
+        if resolved > 0 {
+            self.dirty.insert(client_id);
+        }
+        resolved
+    }
+
+    /// Dispute every deposit of a client's account not already under dispute, e.g. to set up
+    /// tests, and return the number of deposits disputed, zero for an unknown client
+    ///
+    /// Deposits that cannot be disputed under the usual rules, e.g. for insufficient available
+    /// funds, are skipped.
+    pub fn dispute_all_deposits(&mut self, client_id: ClientId) -> usize {
+        let sequence = self.sequence;
+        let disputed = self
+            .accounts
+            .get_mut(&client_id)
+            .map_or(0, |account| account.dispute_all_deposits(sequence));
+        if disputed > 0 {
+            self.dirty.insert(client_id);
+        }
+        disputed
+    }
+
+    /// Credit every account's available balance with the interest accrued on it at `rate`, e.g.
+    /// `0.05` for 5%, without consuming any txid
+    ///
+    /// Held funds do not accrue interest, nor do locked accounts or non-positive balances. Interest
+    /// is rounded following [`HandlerConfig::scale_policy`] if set, or otherwise half to even to
+    /// [`HandlerConfig::max_scale`] decimal places, so sub-cent interest on small balances may
+    /// round to zero. Fails without crediting any account if crediting one would overflow or
+    /// exceed its cap.
+    pub fn accrue_interest(&mut self, rate: Decimal) -> Result<(), TransactionError> {
+        let interest = self
+            .accounts
+            .values()
+            .map(|account| Ok((account.id(), account.interest(rate)?)))
+            .collect::<Result<Vec<_>, TransactionError>>()?;
+        for (client_id, interest) in interest {
+            if let Some(account) = self.accounts.get_mut(&client_id) {
+                account.credit_interest(interest)?;
+                if !interest.is_zero() {
+                    self.dirty.insert(client_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Seed an account for a new client with opening balances, e.g. when migrating from another
+    /// system, without consuming any txid
+    ///
+    /// Seeded balances have no associated deposit records and thus cannot be disputed, and a seeded
+    /// held balance cannot be released or charged back. Balances whose total overflows are
+    /// rejected.
+    pub fn seed_account(
+        &mut self,
+        client_id: ClientId,
+        available: Decimal,
+        held: Decimal,
+        locked: bool,
+    ) -> Result<(), TransactionError> {
+        if available.checked_add(held).is_none() {
+            return Err(TransactionError::BalanceOverflow { client_id });
+        }
+        self.seed_from_snapshot(&AccountSnapshot::new(client_id, available, held, locked, 0))
+    }
+
+    /// Seed an account for a new client from a snapshot, e.g. one read back from a prior output,
+    /// following the same rules as [`AccountsHandler::seed_account`]
+    pub fn seed_from_snapshot(
+        &mut self,
+        snapshot: &AccountSnapshot,
+    ) -> Result<(), TransactionError> {
+        if self.accounts.contains_key(&snapshot.id) {
+            return Err(TransactionError::AccountExists(snapshot.id));
+        }
+        let account = snapshot.try_into_account(&self.config)?;
+        self.accounts.insert(snapshot.id, account);
+        self.dirty.insert(snapshot.id);
+        Ok(())
+    }
+
+    /// Fold the accounts, consumed txids, buffered transactions and stats of `other` into this
+    /// handler, e.g. to combine the results of processing separate files or shards
+    ///
+    /// The handlers are typically shards owning disjoint sets of clients, whose accounts are simply
+    /// combined. The balances and records of a client present in both are summed, with `other`'s
+    /// history appended to this handler's, as if the client's transactions had been split between
+    /// the handlers. Both handlers are assumed to share the same configuration, and sequence numbers
+    /// are not renumbered, so the ages of `other`'s disputes are relative to its own sequence.
+    ///
+    /// Fails without modifying this handler if both handlers consumed the same txid or a combined
+    /// balance cannot be represented.
+    pub fn merge(&mut self, mut other: AccountsHandler) -> Result<(), TransactionError> {
+        if let Some((_, txid)) = other
+            .txids
+            .iter()
+            .chain(&other.retired_txids)
+            .find(|key| self.is_consumed(key))
+        {
+            return Err(TransactionError::DuplicateTxid(*txid));
+        }
+        // merge the accounts of clients present in both before modifying anything
+        let mut merged = Vec::new();
+        for (client_id, account) in &self.accounts {
+            if let Some(other_account) = other.accounts.remove(client_id) {
+                let mut account = account.clone();
+                account.merge(other_account)?;
+                merged.push(account);
+            }
+        }
+        let mut closed_fees = self.closed_fees.clone();
+        for (&client_id, &fees) in &other.closed_fees {
+            let total = closed_fees.get(&client_id).copied().unwrap_or_default();
+            closed_fees.insert(client_id, add_fees(client_id, total, fees)?);
         }
+
+        self.accounts
+            .extend(merged.into_iter().map(|account| (account.id(), account)));
+        self.accounts.extend(other.accounts);
+        self.txids.extend(other.txids);
+        self.retired_txids.extend(other.retired_txids);
+        self.stats.merge(&other.stats);
+        self.sequence = self.sequence.max(other.sequence);
+        for (key, txs) in other.pending {
+            self.pending.entry(key).or_default().extend(txs);
+        }
+        self.submitted.extend(other.submitted);
+        for (client_id, count) in other.applied {
+            *self.applied.entry(client_id).or_default() += count;
+        }
+        self.dirty.extend(other.dirty);
+        self.events.extend(other.events);
+        self.closed_fees = closed_fees;
+        Ok(())
     }
 
-    /// Demultiplex a transaction by client id
-    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<(), String> {
-        // ensure txid is unique
-        if tx.is_generative_tx() && !self.txids.insert(tx.txid) {
-            return Err(format!("duplicate txid: {}", tx.txid));
+    /// Reset the handler for a fresh batch of transactions, keeping its policy and allocated
+    /// capacity
+    ///
+    /// This discards every account, including its balances and history, frees every txid and zeroes
+    /// the stats.
+    pub fn reset(&mut self) {
+        self.accounts.clear();
+        self.txids.clear();
+        self.retired_txids.clear();
+        self.stats = IngestionStats::default();
+        self.sequence = 0;
+        self.pending.clear();
+        self.submitted.clear();
+        self.applied.clear();
+        self.dirty.clear();
+        self.events.clear();
+        self.txid_allocator = TxidAllocator::new();
+        self.closed_fees.clear();
+        if let Some(shadow) = &mut self.shadow {
+            shadow.reset();
         }
+    }
 
-        // get account for client
+    /// Close a client's account, removing it only if both its available and held balances are zero
+    ///
+    /// The account's txids remain consumed, so they cannot be reused after closing.
+    pub fn close_account(&mut self, client_id: ClientId) -> Result<(), TransactionError> {
         let account = self
             .accounts
-            .entry(tx.client_id)
-            .or_insert_with(|| Account::new(tx.client_id));
+            .get(&client_id)
+            .ok_or(TransactionError::UnknownClient(client_id))?;
+        if !account.is_zeroed() {
+            return Err(TransactionError::NonZeroBalance(client_id));
+        }
+        // the fees already collected stay collected once the account is gone
+        let fees = self
+            .closed_fees
+            .get(&client_id)
+            .copied()
+            .unwrap_or_default();
+        let fees = add_fees(client_id, fees, account.collected_fees())?;
+        self.closed_fees.insert(client_id, fees);
+        self.accounts.remove(&client_id);
+        Ok(())
+    }
+
+    /// Dump the full state of the handler, except for buffered transactions and those retained for
+    /// idempotent resubmits
+    pub fn dump_state(&self) -> HandlerState {
+        HandlerState {
+            accounts: self.accounts.values().cloned().collect(),
+            txids: self.txids.union(&self.retired_txids).copied().collect(),
+            config: self.config,
+            sequence: self.sequence,
+            closed_fees: self.closed_fees.clone(),
+        }
+    }
+
+    /// Restore a handler from a dumped state
+    pub fn load_state(state: HandlerState) -> Self {
+        Self {
+            accounts: state
+                .accounts
+                .into_iter()
+                .map(|account| (account.id(), account))
+                .collect(),
+            txids: state.txids,
+            retired_txids: HashSet::new(),
+            config: state.config,
+            stats: IngestionStats::default(),
+            sequence: state.sequence,
+            pending: HashMap::new(),
+            submitted: HashMap::new(),
+            applied: HashMap::new(),
+            dirty: HashSet::new(),
+            account_factory: None,
+            shadow: None,
+            events: Vec::new(),
+            on_applied: None,
+            txid_allocator: TxidAllocator::new(),
+            closed_fees: state.closed_fees,
+        }
+    }
+
+    /// Save the full state of the handler to a JSON file at `path`
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut writer, &self.dump_state())?;
+        writer.flush()
+    }
+
+    /// Restore a handler from a JSON file at `path` written by [`AccountsHandler::save_to_path`]
+    pub fn load_from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(Self::load_state(serde_json::from_reader(reader)?))
+    }
+
+    /// Repair every account whose held balance differs from the amounts held by its open disputes,
+    /// e.g. after a state file was edited by hand, returning the ids of the corrected clients in
+    /// ascending order
+    ///
+    /// The available balance cannot be recomputed, as only deposits and withdrawals are recorded,
+    /// so the invariants are checked once held balances are repaired, failing if one still does
+    /// not hold. Repairs are kept even then.
+    pub fn recompute_from_deposits(&mut self) -> Result<Vec<ClientId>, InvariantError> {
+        let mut corrected = Vec::new();
+        for account in self.accounts.values_mut() {
+            // an account whose disputed amount overflows cannot be repaired, which the check
+            // below reports
+            if account.repair_held().unwrap_or(false) {
+                corrected.push(account.id());
+            }
+        }
+        corrected.sort_unstable();
+        self.dirty.extend(&corrected);
+        self.validate_invariants()?;
+        Ok(corrected)
+    }
 
-        // process transaction
-        Ok(account.process(tx))
+    /// Check the invariants of every account
+    pub fn validate_invariants(&self) -> Result<(), InvariantError> {
+        self.accounts
+            .values()
+            .try_for_each(Account::validate_invariants)
     }
 
-    /// Get snapshots of all accounts
+    /// Get snapshots of all accounts, in an unspecified order that is the same on every run for the
+    /// same transactions
     pub fn snapshot_accounts(&self) -> Vec<AccountSnapshot> {
-        self.accounts.values().map(|a| a.snapshot()).collect()
+        self.snapshots_iter().collect()
+    }
+
+    /// Get snapshots of all accounts, sorted in ascending order of client id
+    pub fn snapshot_accounts_sorted(&self) -> Vec<AccountSnapshot> {
+        self.snapshots_sorted_iter().collect()
+    }
+
+    /// Get snapshots of the accounts changed since the last call, in ascending order of client id,
+    /// e.g. for incremental exports
+    ///
+    /// An account changes with every applied transaction and with administrative operations such as
+    /// seeding, freezing or interest, but not with ignored or rejected transactions. Closed accounts
+    /// are not included. The first call returns every account changed since the handler was
+    /// created or loaded.
+    pub fn snapshot_dirty(&mut self) -> Vec<AccountSnapshot> {
+        let mut client_ids: Vec<_> = self.dirty.drain().collect();
+        client_ids.sort_unstable();
+        client_ids
+            .into_iter()
+            .filter_map(|client_id| self.snapshot(client_id))
+            .collect()
+    }
+
+    /// Take a point-in-time snapshot of all accounts mid-stream, the same as
+    /// [`AccountsHandler::snapshot_accounts_sorted`], to be tagged with
+    /// [`AccountsHandler::sequence`]
+    pub fn checkpoint(&self) -> Vec<AccountSnapshot> {
+        self.snapshot_accounts_sorted()
+    }
+
+    /// Get the number of decimal places snapshots round balances to by default, the larger of
+    /// `DEFAULT_OUTPUT_SCALE` and [`HandlerConfig::max_scale`], so that balances summed from
+    /// accepted amounts are not rounded
+    pub fn output_scale(&self) -> u32 {
+        self.config.max_scale.max(DEFAULT_OUTPUT_SCALE)
+    }
+
+    /// Lazily iterate over snapshots of all accounts
+    pub fn snapshots_iter(&self) -> impl Iterator<Item = AccountSnapshot> + '_ {
+        self.accounts.values().map(|a| a.snapshot())
+    }
+
+    /// Lazily iterate over snapshots of all accounts in ascending order of client id
+    ///
+    /// Only the client ids are buffered for sorting, the snapshots themselves are taken lazily.
+    pub fn snapshots_sorted_iter(&self) -> impl Iterator<Item = AccountSnapshot> + '_ {
+        self.snapshots_sorted_iter_with_scale(self.output_scale())
+    }
+
+    /// Lazily iterate over snapshots of all accounts in ascending order of client id, with
+    /// balances rounded half to even to `scale` decimal places
+    pub fn snapshots_sorted_iter_with_scale(
+        &self,
+        scale: u32,
+    ) -> impl Iterator<Item = AccountSnapshot> + '_ {
+        let mut client_ids: Vec<_> = self.accounts.keys().copied().collect();
+        client_ids.sort_unstable();
+        client_ids
+            .into_iter()
+            .map(move |id| self.accounts[&id].snapshot_with_scale(scale))
+    }
+}
+
+/// Add the `fees` collected from the client to a total of collected fees, failing on overflow
+fn add_fees(
+    client_id: ClientId,
+    total: Decimal,
+    fees: Decimal,
+) -> Result<Decimal, TransactionError> {
+    total
+        .checked_add(fees)
+        .ok_or(TransactionError::BalanceOverflow { client_id })
+}
+
+/// Submit every transaction in order, dropping errors such as duplicate txids like the CLI does
+impl Extend<Transaction> for AccountsHandler {
+    fn extend<I: IntoIterator<Item = Transaction>>(&mut self, txs: I) {
+        let txs = txs.into_iter();
+        // reserve room for the txids up front rather than growing the set along the way
+        self.txids.reserve(txs.size_hint().0);
+        for tx in txs {
+            let _ = self.submit_transaction(tx);
+        }
+    }
+}
+
+/// Collect transactions into a new handler, dropping errors such as duplicate txids like the CLI
+/// does
+impl FromIterator<Transaction> for AccountsHandler {
+    fn from_iter<I: IntoIterator<Item = Transaction>>(txs: I) -> Self {
+        let mut handler = Self::new();
+        handler.extend(txs);
+        handler
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Transaction, TransactionType};
-    use rust_decimal::Decimal;
+    use crate::amount;
+    use crate::{DeltaKind, DisputePolicy, LockedPolicy, RepeatDepositPolicy};
 
     #[test]
     fn submit_transaction_works() {
         let mut handler = AccountsHandler::new();
         let tx = Transaction {
-            tx_type: TransactionType::Deposit(Decimal::new(100, 0)),
+            tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
             client_id: ClientId(1),
             txid: Txid(1),
+            timestamp: None,
         };
-        handler.submit_transaction(tx).unwrap();
+        assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
         assert_eq!(handler.accounts.len(), 1);
         assert_eq!(handler.txids.len(), 1);
     }
@@ -70,11 +1491,2912 @@ mod tests {
     fn duplicate_txid_fails() {
         let mut handler = AccountsHandler::new();
         let tx = Transaction {
-            tx_type: TransactionType::Deposit(Decimal::new(100, 0)),
+            tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
             client_id: ClientId(1),
             txid: Txid(1),
+            timestamp: None,
         };
         handler.submit_transaction(tx).unwrap();
-        assert!(handler.submit_transaction(tx).is_err());
+        assert_eq!(
+            handler.submit_transaction(tx),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+    }
+
+    #[test]
+    fn snapshot_known_client_works() {
+        let mut handler = AccountsHandler::new();
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        handler.submit_transaction(tx).unwrap();
+        assert!(handler.contains(ClientId(1)));
+        let snapshot = handler.snapshot(ClientId(1)).unwrap();
+        assert_eq!(snapshot.id, ClientId(1));
+        assert_eq!(snapshot.available, Decimal::new(100, 0));
+    }
+
+    #[test]
+    fn snapshot_unknown_client_none() {
+        let handler = AccountsHandler::new();
+        assert!(!handler.contains(ClientId(1)));
+        assert_eq!(handler.snapshot(ClientId(1)), None);
+    }
+
+    #[test]
+    fn snapshot_client_with_only_duplicate_txid_none() {
+        let mut handler = AccountsHandler::new();
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        handler.submit_transaction(tx).unwrap();
+        let duplicate = Transaction {
+            client_id: ClientId(2),
+            ..tx
+        };
+        assert!(handler.submit_transaction(duplicate).is_err());
+        assert!(!handler.contains(ClientId(2)));
+        assert_eq!(handler.snapshot(ClientId(2)), None);
+    }
+
+    #[test]
+    fn snapshot_accounts_sorted_ascending() {
+        let mut handler = AccountsHandler::new();
+        for id in (1..=5).rev() {
+            let tx = Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                client_id: ClientId(id),
+                txid: Txid(id.into()),
+                timestamp: None,
+            };
+            handler.submit_transaction(tx).unwrap();
+        }
+        let ids: Vec<_> = handler
+            .snapshot_accounts_sorted()
+            .into_iter()
+            .map(|s| s.id)
+            .collect();
+        assert_eq!(ids, (1..=5).map(ClientId).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unlock_account_allows_withdrawal() {
+        let mut handler = AccountsHandler::new();
+        let client_id = ClientId(1);
+        for (tx_type, txid) in [
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 1),
+            (TransactionType::Deposit(amount(Decimal::new(50, 0))), 2),
+            (TransactionType::Dispute, 1),
+            (TransactionType::Chargeback, 1),
+        ] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type,
+                    client_id,
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        assert!(handler.snapshot(client_id).unwrap().locked);
+
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(20, 0))),
+            client_id,
+            txid: Txid(3),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(withdrawal),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+
+        handler.unlock_account(client_id).unwrap();
+        let withdrawal = Transaction {
+            txid: Txid(4),
+            ..withdrawal
+        };
+        assert_eq!(
+            handler.submit_transaction(withdrawal),
+            Ok(ProcessOutcome::Applied)
+        );
+        let snapshot = handler.snapshot(client_id).unwrap();
+        assert!(!snapshot.locked);
+        assert_eq!(snapshot.available, Decimal::new(30, 0));
+    }
+
+    #[test]
+    fn unlock_unknown_account_fails() {
+        let mut handler = AccountsHandler::new();
+        assert_eq!(
+            handler.unlock_account(ClientId(1)),
+            Err(TransactionError::UnknownClient(ClientId(1)))
+        );
+    }
+
+    #[test]
+    fn transfer_works() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::new(40, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(60, 0)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(2)).unwrap().available,
+            Decimal::new(40, 0)
+        );
+    }
+
+    #[test]
+    fn transfer_insufficient_funds_ignored() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::new(150, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(100, 0)
+        );
+        assert!(!handler.contains(ClientId(2)));
+    }
+
+    #[test]
+    fn self_transfer_fails() {
+        let mut handler = AccountsHandler::new();
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(1),
+                amount: Decimal::new(40, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Err(TransactionError::SelfTransfer(ClientId(1)))
+        );
+        assert!(handler.txids.is_empty());
+    }
+
+    #[test]
+    fn transfer_to_locked_ignored() {
+        let mut handler = AccountsHandler::new();
+        for (tx_type, client_id, txid) in [
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 1, 1),
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 2, 2),
+            (TransactionType::Dispute, 2, 2),
+            (TransactionType::Chargeback, 2, 2),
+        ] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type,
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::new(40, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(3),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(100, 0)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(2)).unwrap().available,
+            Decimal::ZERO
+        );
+    }
+
+    #[test]
+    fn balance_overflow_fails() {
+        let mut handler = AccountsHandler::new();
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::MAX)),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        handler.submit_transaction(tx).unwrap();
+        let tx = Transaction {
+            txid: Txid(2),
+            ..tx
+        };
+        assert_eq!(
+            handler.submit_transaction(tx),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::MAX
+        );
+        assert!(!handler.txids.contains(&(None, Txid(2))));
+    }
+
+    #[test]
+    fn transfer_overflow_leaves_sender_untouched() {
+        let mut handler = AccountsHandler::new();
+        for (client_id, txid) in [(1, 1), (2, 2)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::MAX)),
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::ONE,
+            },
+            client_id: ClientId(1),
+            txid: Txid(3),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(2)
+            })
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::MAX
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(2)).unwrap().available,
+            Decimal::MAX
+        );
+    }
+
+    #[test]
+    fn counts_exclude_duplicates() {
+        let mut handler = AccountsHandler::new();
+        for (tx_type, client_id, txid) in [
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 1, 1),
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 2, 2),
+            (
+                TransactionType::Withdrawal(amount(Decimal::new(50, 0))),
+                1,
+                3,
+            ),
+            (TransactionType::Dispute, 2, 2),
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 3, 1),
+            (
+                TransactionType::Withdrawal(amount(Decimal::new(50, 0))),
+                2,
+                3,
+            ),
+        ] {
+            let _ = handler.submit_transaction(Transaction {
+                tx_type,
+                client_id: ClientId(client_id),
+                txid: Txid(txid),
+                timestamp: None,
+            });
+        }
+        assert_eq!(handler.account_count(), 2);
+        assert_eq!(handler.transaction_count(), 3);
+    }
+
+    #[test]
+    fn with_capacity_matches_new() {
+        let transactions = [
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 1, 1),
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 2, 2),
+            (
+                TransactionType::Withdrawal(amount(Decimal::new(50, 0))),
+                1,
+                3,
+            ),
+            (TransactionType::Dispute, 2, 2),
+            (TransactionType::Deposit(amount(Decimal::new(100, 0))), 3, 1),
+        ]
+        .map(|(tx_type, client_id, txid)| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        });
+        let mut handler = AccountsHandler::new();
+        let mut presized = AccountsHandler::with_capacity(16, 1024);
+        for tx in transactions {
+            assert_eq!(
+                handler.submit_transaction(tx),
+                presized.submit_transaction(tx)
+            );
+        }
+        assert_eq!(
+            handler.snapshot_accounts_sorted(),
+            presized.snapshot_accounts_sorted()
+        );
+    }
+
+    #[test]
+    fn snapshots_iter_matches_snapshot_accounts() {
+        let mut handler = AccountsHandler::new();
+        for id in [3, 1, 2] {
+            let tx = Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(id.into(), 0))),
+                client_id: ClientId(id),
+                txid: Txid(id.into()),
+                timestamp: None,
+            };
+            handler.submit_transaction(tx).unwrap();
+        }
+        assert_eq!(
+            handler.snapshots_iter().collect::<HashSet<_>>(),
+            handler
+                .snapshot_accounts()
+                .into_iter()
+                .collect::<HashSet<_>>()
+        );
+        assert_eq!(
+            handler.snapshots_sorted_iter().collect::<Vec<_>>(),
+            handler.snapshot_accounts_sorted()
+        );
+    }
+
+    #[test]
+    fn history_records_applied_transactions() {
+        let mut handler = AccountsHandler::new_with_history();
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(20, 0))),
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        handler.submit_transaction(deposit).unwrap();
+        assert_eq!(
+            handler.submit_transaction(withdrawal),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        handler.submit_transaction(dispute).unwrap();
+        assert_eq!(
+            handler.history(ClientId(1)),
+            Some(
+                &[
+                    AppliedEvent {
+                        txid: Txid(1),
+                        kind: deposit.tx_type,
+                        available: Decimal::new(10, 0),
+                        held: Decimal::ZERO,
+                    },
+                    AppliedEvent {
+                        txid: Txid(1),
+                        kind: dispute.tx_type,
+                        available: Decimal::ZERO,
+                        held: Decimal::new(10, 0),
+                    },
+                ][..]
+            )
+        );
+        assert_eq!(handler.history(ClientId(2)), None);
+    }
+
+    #[test]
+    fn history_records_transfer_for_both_accounts() {
+        let mut handler = AccountsHandler::new_with_history();
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::new(4, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        handler.submit_transaction(transfer).unwrap();
+        assert_eq!(
+            handler.history(ClientId(1)).unwrap().last(),
+            Some(&AppliedEvent {
+                txid: Txid(2),
+                kind: transfer.tx_type,
+                available: Decimal::new(6, 0),
+                held: Decimal::ZERO,
+            })
+        );
+        assert_eq!(
+            handler.history(ClientId(2)),
+            Some(
+                &[AppliedEvent {
+                    txid: Txid(2),
+                    kind: transfer.tx_type,
+                    available: Decimal::new(4, 0),
+                    held: Decimal::ZERO,
+                }][..]
+            )
+        );
+    }
+
+    #[test]
+    fn history_disabled_by_default() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        assert_eq!(handler.history(ClientId(1)), None);
+    }
+
+    #[test]
+    fn uniqueness_scope_works() {
+        let transactions = [1, 2].map(|client_id| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(client_id),
+            txid: Txid(1),
+            timestamp: None,
+        });
+
+        let mut handler = AccountsHandler::new();
+        handler.submit_transaction(transactions[0]).unwrap();
+        assert_eq!(
+            handler.submit_transaction(transactions[1]),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            uniqueness: UniquenessScope::PerClient,
+            ..HandlerConfig::default()
+        });
+        handler.submit_transaction(transactions[0]).unwrap();
+        assert_eq!(
+            handler.submit_transaction(transactions[1]),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.submit_transaction(transactions[0]),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(handler.transaction_count(), 2);
+    }
+
+    #[test]
+    fn close_zeroed_account_works() {
+        let mut handler = AccountsHandler::new();
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        handler.submit_transaction(deposit).unwrap();
+        handler.submit_transaction(withdrawal).unwrap();
+        assert_eq!(handler.close_account(ClientId(1)), Ok(()));
+        assert!(!handler.contains(ClientId(1)));
+        assert_eq!(
+            handler.submit_transaction(deposit),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(
+            handler.close_account(ClientId(1)),
+            Err(TransactionError::UnknownClient(ClientId(1)))
+        );
+    }
+
+    #[test]
+    fn close_account_with_held_funds_fails() {
+        let mut handler = AccountsHandler::new();
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        let dispute = Transaction {
+            tx_type: TransactionType::Dispute,
+            ..deposit
+        };
+        handler.submit_transaction(deposit).unwrap();
+        handler.submit_transaction(dispute).unwrap();
+        assert_eq!(
+            handler.close_account(ClientId(1)),
+            Err(TransactionError::NonZeroBalance(ClientId(1)))
+        );
+        assert!(handler.contains(ClientId(1)));
+    }
+
+    #[test]
+    fn dispute_unknown_txid_distinguished() {
+        let mut handler = AccountsHandler::new();
+        for tx in [
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Withdrawal(amount(Decimal::new(20, 0))),
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            },
+        ] {
+            handler.submit_transaction(tx).unwrap();
+        }
+        let dispute = |client_id, txid| Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        // never seen
+        assert_eq!(
+            handler.submit_transaction(dispute(1, 3)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::UnknownTxid))
+        );
+        // rejected withdrawal
+        assert_eq!(
+            handler.submit_transaction(dispute(1, 2)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotADeposit))
+        );
+        // deposit of another client
+        assert_eq!(
+            handler.submit_transaction(dispute(2, 1)),
+            Err(TransactionError::TxidClientMismatch {
+                txid: Txid(1),
+                owner: ClientId(1),
+                got: ClientId(2)
+            })
+        );
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+    }
+
+    #[test]
+    fn submit_batch_reports_each_result() {
+        let mut handler = AccountsHandler::new();
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(5, 0))),
+            client_id: ClientId(1),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([deposit, deposit, withdrawal]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::DuplicateTxid(Txid(1))),
+                Ok(ProcessOutcome::Applied),
+            ]
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(5, 0)
+        );
+    }
+
+    #[test]
+    fn process_parallel_matches_sequential() {
+        let mut txs = Vec::new();
+        for i in 0..200u32 {
+            let client_id = ClientId((i % 7) as u16);
+            txs.push(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(i.into(), 1))),
+                client_id,
+                txid: Txid::from(i),
+                timestamp: None,
+            });
+            // duplicate, rejected alike
+            txs.push(Transaction {
+                tx_type: TransactionType::Withdrawal(amount(Decimal::ONE)),
+                client_id: ClientId(1),
+                txid: Txid::from(i),
+                timestamp: None,
+            });
+            if i % 3 == 0 {
+                txs.push(Transaction {
+                    tx_type: TransactionType::Dispute,
+                    client_id,
+                    txid: Txid::from(i),
+                    timestamp: None,
+                });
+            }
+            if i % 9 == 0 {
+                txs.push(Transaction {
+                    tx_type: TransactionType::Chargeback,
+                    client_id,
+                    txid: Txid::from(i),
+                    timestamp: None,
+                });
+            }
+            // dispute of another client's deposit, rejected alike
+            if i % 5 == 0 {
+                txs.push(Transaction {
+                    tx_type: TransactionType::Dispute,
+                    client_id: ClientId((i % 7 + 1) as u16),
+                    txid: Txid::from(i),
+                    timestamp: None,
+                });
+            }
+        }
+        // dispute of another client's txid never recorded, ignored alike
+        txs.push(Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(1000, 0))),
+            client_id: ClientId(2),
+            txid: Txid(1000),
+            timestamp: None,
+        });
+        txs.push(Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(3),
+            txid: Txid(1000),
+            timestamp: None,
+        });
+        let mut sequential = AccountsHandler::new();
+        sequential.extend(txs.clone());
+        let parallel = AccountsHandler::process_parallel(txs, 4);
+        assert_eq!(
+            parallel.snapshot_accounts_sorted(),
+            sequential.snapshot_accounts_sorted()
+        );
+        assert_eq!(parallel.transaction_count(), sequential.transaction_count());
+        assert!(sequential.stats().errors > 0);
+        assert_eq!(parallel.stats(), sequential.stats());
+    }
+
+    #[test]
+    fn state_round_trip_works() {
+        let mut handler = AccountsHandler::new_with_history();
+        handler.extend([
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(20, 0))),
+                client_id: ClientId(2),
+                txid: Txid(2),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(2),
+                txid: Txid(2),
+                timestamp: None,
+            },
+        ]);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        handler.save_to_path(file.path()).unwrap();
+        let mut loaded = AccountsHandler::load_from_path(file.path()).unwrap();
+        assert_eq!(
+            loaded.snapshot_accounts_sorted(),
+            handler.snapshot_accounts_sorted()
+        );
+        assert_eq!(loaded.history(ClientId(2)), handler.history(ClientId(2)));
+
+        // records, disputed flags and txids survive the round trip
+        let resolve = Transaction {
+            tx_type: TransactionType::Resolve,
+            client_id: ClientId(2),
+            txid: Txid(2),
+            timestamp: None,
+        };
+        assert_eq!(
+            loaded.submit_transaction(resolve),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            loaded.submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+                client_id: ClientId(3),
+                txid: Txid(1),
+                timestamp: None,
+            }),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+    }
+
+    #[test]
+    fn balance_getters_work() {
+        let mut handler = AccountsHandler::new();
+        handler.extend([
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(25, 1))),
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            },
+        ]);
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(25, 1)));
+        assert_eq!(handler.total(ClientId(1)), Some(Decimal::new(125, 1)));
+        assert_eq!(handler.available(ClientId(2)), None);
+        assert_eq!(handler.held(ClientId(2)), None);
+        assert_eq!(handler.total(ClientId(2)), None);
+    }
+
+    #[test]
+    fn reset_allows_reuse() {
+        let mut handler = AccountsHandler::new();
+        let deposit = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        handler.submit_transaction(deposit).unwrap();
+        handler.reset();
+        assert_eq!(handler.account_count(), 0);
+        assert_eq!(handler.transaction_count(), 0);
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                client_id: ClientId(2),
+                ..deposit
+            }),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert!(!handler.contains(ClientId(1)));
+        assert_eq!(
+            handler.snapshot(ClientId(2)).unwrap().available,
+            Decimal::new(10, 0)
+        );
+    }
+
+    #[test]
+    fn stats_count_outcomes() {
+        let mut handler = AccountsHandler::new();
+        let deposit = |client_id, txid, value| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(value, 0))),
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let withdrawal = |client_id, txid, value| Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(value, 0))),
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let dispute = |client_id, txid| Transaction {
+            tx_type: TransactionType::Dispute,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            deposit(1, 1, 10),
+            deposit(2, 2, 5),
+            deposit(2, 1, 5),
+            withdrawal(1, 3, 3),
+            withdrawal(2, 4, 20),
+            dispute(2, 2),
+            dispute(2, 2),
+            dispute(1, 9),
+        ]);
+        let stats = handler.stats();
+        assert_eq!(stats.deposits, 2);
+        assert_eq!(stats.withdrawals, 1);
+        assert_eq!(stats.transfers, 0);
+        assert_eq!(stats.disputes, 1);
+        assert_eq!(stats.resolves, 0);
+        assert_eq!(stats.chargebacks, 0);
+        assert_eq!(
+            stats.ignored,
+            [
+                (IgnoreReason::InsufficientFunds, 1),
+                (IgnoreReason::UnknownTxid, 1),
+                (IgnoreReason::AlreadyDisputed, 1),
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(stats.duplicate_txids, 1);
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[test]
+    fn negative_amount_policy_works() {
+        let transfer = Transaction {
+            tx_type: TransactionType::Transfer {
+                to: ClientId(2),
+                amount: Decimal::new(-10, 0),
+            },
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+
+        let mut handler = AccountsHandler::new();
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NonPositiveAmount))
+        );
+        assert!(handler.contains(ClientId(1)));
+        assert_eq!(handler.transaction_count(), 1);
+
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            negative_amount_policy: NegativeAmountPolicy::Reject,
+            ..HandlerConfig::default()
+        });
+        assert_eq!(
+            handler.submit_transaction(transfer),
+            Err(TransactionError::NegativeAmount { txid: Txid(1) })
+        );
+        assert!(!handler.contains(ClientId(1)));
+        assert_eq!(handler.transaction_count(), 0);
+        // the txid can still be used by a valid transaction
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                ..transfer
+            }),
+            Ok(ProcessOutcome::Applied)
+        );
+    }
+
+    #[test]
+    fn auto_resolve_stale_works() {
+        let mut handler = AccountsHandler::new();
+        handler.extend([
+            Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            },
+            Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            },
+        ]);
+        assert_eq!(handler.auto_resolve_stale(2), Ok(0));
+        for txid in 2..=4 {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+                    client_id: ClientId(2),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.auto_resolve_stale(2), Ok(1));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+        assert_eq!(handler.auto_resolve_stale(2), Ok(0));
+    }
+
+    #[test]
+    fn seed_account_works() {
+        let mut handler = AccountsHandler::new();
+        assert_eq!(
+            handler.seed_account(ClientId(1), Decimal::new(10, 0), Decimal::new(2, 0), true),
+            Ok(())
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)),
+            Some(AccountSnapshot::new(
+                ClientId(1),
+                Decimal::new(10, 0),
+                Decimal::new(2, 0),
+                true,
+                0
+            ))
+        );
+        assert_eq!(handler.transaction_count(), 0);
+        assert_eq!(handler.validate_invariants(), Ok(()));
+        assert_eq!(
+            handler.seed_account(ClientId(1), Decimal::ONE, Decimal::ZERO, false),
+            Err(TransactionError::AccountExists(ClientId(1)))
+        );
+        assert_eq!(
+            handler.seed_account(ClientId(2), Decimal::NEGATIVE_ONE, Decimal::ZERO, false),
+            Err(TransactionError::NegativeSeed(ClientId(2)))
+        );
+        assert!(!handler.contains(ClientId(2)));
+    }
+
+    #[test]
+    fn has_txid_works() {
+        let mut handler = AccountsHandler::new();
+        assert!(!handler.has_txid(Txid(1)));
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        assert!(handler.has_txid(Txid(1)));
+        assert!(!handler.has_txid(Txid(2)));
+        assert_eq!(handler.txid_count(), 1);
+
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            uniqueness: UniquenessScope::PerClient,
+            ..HandlerConfig::default()
+        });
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                client_id: ClientId(2),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        assert!(handler.has_txid(Txid(1)));
+        assert!(!handler.has_txid(Txid(2)));
+    }
+
+    #[test]
+    fn snapshot_round_trip_works() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .seed_account(ClientId(1), Decimal::new(15, 1), Decimal::ONE, true)
+            .unwrap();
+        let snapshot = handler.snapshot(ClientId(1)).unwrap();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let read_back: AccountSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(read_back, snapshot);
+
+        let mut reloaded = AccountsHandler::new();
+        reloaded.seed_from_snapshot(&read_back).unwrap();
+        assert_eq!(reloaded.snapshot(ClientId(1)), Some(snapshot));
+
+        let inconsistent = AccountSnapshot {
+            id: ClientId(2),
+            total: Decimal::ZERO,
+            ..read_back
+        };
+        assert_eq!(
+            reloaded.seed_from_snapshot(&inconsistent),
+            Err(TransactionError::InconsistentTotal(ClientId(2)))
+        );
+    }
+
+    #[test]
+    fn withdrawal_fee_works() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            withdrawal_fee: Decimal::ONE,
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(5, 0))), 3),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(35, 1))), 4),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                // 5 plus the fee exceeds the remaining 5
+                Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds)),
+                Ok(ProcessOutcome::Applied),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(5, 1)));
+        assert_eq!(handler.collected_fees(), Decimal::new(2, 0));
+    }
+
+    #[test]
+    fn dispute_of_spent_deposit_allowed_negative() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            dispute_policy: DisputePolicy::AllowNegative,
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        for tx in [
+            tx(TransactionType::Deposit(amount(Decimal::new(100, 0))), 1),
+            tx(TransactionType::Withdrawal(amount(Decimal::new(100, 0))), 2),
+            tx(TransactionType::Dispute, 1),
+        ] {
+            assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
+        }
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(-100, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(100, 0)));
+        assert_eq!(handler.total(ClientId(1)), Some(Decimal::ZERO));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Chargeback, 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)),
+            Some(AccountSnapshot::new(
+                ClientId(1),
+                Decimal::new(-100, 0),
+                Decimal::ZERO,
+                true,
+                0
+            ))
+        );
+        assert_eq!(handler.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn ingest_csv_works() {
+        let input = b"type, client, tx, amount
+deposit, 1, 1, 10.0
+deposit, 1, 2, 5.0
+withdrawal, 1, 3, 4.0
+deposit, 1, 1, 5.0
+deposit, 1, 4
+dispute, 1, 2,
+";
+        let mut handler = AccountsHandler::new();
+        let stats = handler.ingest_csv(&input[..]).unwrap();
+        assert_eq!(stats.deposits, 2);
+        assert_eq!(stats.withdrawals, 1);
+        assert_eq!(stats.disputes, 1);
+        assert_eq!(stats.duplicate_txids, 1);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(
+            handler.snapshot(ClientId(1)),
+            Some(AccountSnapshot::new(
+                ClientId(1),
+                Decimal::new(6, 0),
+                Decimal::new(5, 0),
+                false,
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn locked_policy_reject_works() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            locked_policy: LockedPolicy::Reject,
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        for tx in [
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 2, 2),
+            tx(TransactionType::Dispute, 1, 1),
+            tx(TransactionType::Chargeback, 1, 1),
+        ] {
+            assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
+        }
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Deposit(amount(Decimal::ONE)), 1, 3)),
+            Err(TransactionError::AccountLocked(ClientId(1)))
+        );
+        assert_eq!(
+            handler.submit_transaction(tx(
+                TransactionType::Transfer {
+                    to: ClientId(1),
+                    amount: Decimal::ONE
+                },
+                2,
+                4
+            )),
+            Err(TransactionError::AccountLocked(ClientId(1)))
+        );
+        assert_eq!(handler.available(ClientId(2)), Some(Decimal::new(10, 0)));
+        assert!(!handler.has_txid(Txid(3)));
+    }
+
+    #[test]
+    fn buffer_disputes_replays_after_deposit() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            buffer_disputes: true,
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Dispute, 1),
+                tx(TransactionType::Dispute, 2),
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Buffered),
+                Ok(ProcessOutcome::Buffered),
+                Ok(ProcessOutcome::Applied),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::ZERO));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.stats().disputes, 1);
+        assert_eq!(handler.stats().buffered, 2);
+
+        let flushed = handler.flush_pending();
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].txid, Txid(2));
+        assert_eq!(handler.stats().ignored[&IgnoreReason::UnknownTxid], 1);
+        assert!(handler.flush_pending().is_empty());
+    }
+
+    #[test]
+    fn default_starts_empty() {
+        let handler = AccountsHandler::default();
+        assert_eq!(handler.account_count(), 0);
+        assert_eq!(handler.transaction_count(), 0);
+        assert_eq!(handler.stats(), &IngestionStats::default());
+    }
+
+    #[test]
+    fn max_available_caps_deposits() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            max_available: Some(Decimal::new(10, 0)),
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(6, 0))), 1, 1),
+                tx(TransactionType::Deposit(amount(Decimal::new(4, 0))), 1, 2),
+                tx(TransactionType::Deposit(amount(Decimal::ONE)), 1, 3),
+                tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2, 4),
+                tx(
+                    TransactionType::Transfer {
+                        to: ClientId(1),
+                        amount: Decimal::ONE
+                    },
+                    2,
+                    5
+                ),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::BalanceCapExceeded {
+                    client_id: ClientId(1)
+                }),
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::BalanceCapExceeded {
+                    client_id: ClientId(1)
+                }),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.available(ClientId(2)), Some(Decimal::new(5, 0)));
+        // rejected deposits leave their txid unused
+        assert!(!handler.has_txid(Txid(3)));
+        // and do not open the account of a new client
+        assert_eq!(
+            handler.submit_transaction(tx(
+                TransactionType::Deposit(amount(Decimal::new(11, 0))),
+                3,
+                7
+            )),
+            Err(TransactionError::BalanceCapExceeded {
+                client_id: ClientId(3)
+            })
+        );
+        assert!(!handler.contains(ClientId(3)));
+
+        // disputes moving funds between available and held are not capped
+        for tx in [
+            tx(TransactionType::Dispute, 1, 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(6, 0))), 1, 6),
+            tx(TransactionType::Resolve, 1, 1),
+        ] {
+            assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
+        }
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(16, 0)));
+    }
+
+    #[test]
+    fn client_ids_sorted() {
+        let mut handler = AccountsHandler::new();
+        for (client_id, txid) in [(3, 1), (1, 2), (3, 3), (2, 1)] {
+            let _ = handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+                client_id: ClientId(client_id),
+                txid: Txid(txid),
+                timestamp: None,
+            });
+        }
+        // client 2 only submitted a duplicate txid, so never got an account
+        assert_eq!(handler.client_ids(), vec![ClientId(1), ClientId(3)]);
+    }
+
+    #[test]
+    fn idempotent_resubmits_work() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            idempotent_resubmits: true,
+            ..HandlerConfig::default()
+        });
+        let deposit = |value| Transaction {
+            tx_type: TransactionType::Deposit(amount(value)),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                deposit(Decimal::new(10, 0)),
+                deposit(Decimal::new(10, 0)),
+                deposit(Decimal::new(11, 0)),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Idempotent),
+                Err(TransactionError::DuplicateTxid(Txid(1))),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.stats().resubmissions, 1);
+
+        // without the mode, identical resubmissions are duplicates
+        let mut handler = AccountsHandler::new();
+        handler.submit_transaction(deposit(Decimal::ONE)).unwrap();
+        assert_eq!(
+            handler.submit_transaction(deposit(Decimal::ONE)),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+    }
+
+    #[test]
+    fn withdrawal_recorded_and_disputable() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        for tx in [
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+        ] {
+            handler.submit_transaction(tx).unwrap();
+        }
+        assert!(handler.accounts[&ClientId(1)].has_record(Txid(2)));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Dispute, 2)),
+            Ok(ProcessOutcome::Applied)
+        );
+        // the withdrawn amount is held as a potential clawback, leaving available untouched
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(6, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(4, 0)));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Chargeback, 2)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+    }
+
+    #[test]
+    fn merge_disjoint_clients_works() {
+        let mut handler = AccountsHandler::new();
+        let mut other = AccountsHandler::new();
+        for (handler, client_id, txid) in [(&mut handler, 1, 1), (&mut other, 2, 2)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        other
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(2),
+                txid: Txid(2),
+                timestamp: None,
+            })
+            .unwrap();
+
+        handler.merge(other).unwrap();
+        assert_eq!(handler.client_ids(), vec![ClientId(1), ClientId(2)]);
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(100, 0)));
+        assert_eq!(handler.held(ClientId(2)), Some(Decimal::new(100, 0)));
+        assert!(handler.has_txid(Txid(2)));
+        assert_eq!(handler.stats().deposits, 2);
+        assert_eq!(handler.stats().disputes, 1);
+
+        // the merged dispute can still settle
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Resolve,
+                client_id: ClientId(2),
+                txid: Txid(2),
+                timestamp: None,
+            }),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn merge_shared_client_sums_balances() {
+        let mut handler = AccountsHandler::new();
+        let mut other = AccountsHandler::new();
+        for (handler, txid) in [(&mut handler, 1), (&mut other, 2)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                    client_id: ClientId(1),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+
+        handler.merge(other).unwrap();
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(200, 0)));
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            }),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(100, 0)));
+        assert_eq!(handler.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn merge_colliding_txid_fails() {
+        let mut handler = AccountsHandler::new();
+        let mut other = AccountsHandler::new();
+        for (handler, client_id) in [(&mut handler, 1), (&mut other, 2)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::new(100, 0))),
+                    client_id: ClientId(client_id),
+                    txid: Txid(1),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            handler.merge(other),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(handler.client_ids(), vec![ClientId(1)]);
+    }
+
+    #[test]
+    fn disputed_breakdown_lists_disputed_deposits() {
+        let mut handler = AccountsHandler::new();
+        for (txid, value) in [(1, 10), (2, 20), (3, 30)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::new(value, 0))),
+                    client_id: ClientId(1),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        for txid in [3, 1] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Dispute,
+                    client_id: ClientId(1),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            handler.disputed_breakdown(ClientId(1)),
+            Some(vec![
+                (Txid(1), Decimal::new(10, 0)),
+                (Txid(3), Decimal::new(30, 0))
+            ])
+        );
+        assert_eq!(handler.disputed_breakdown(ClientId(2)), None);
+    }
+
+    #[test]
+    fn eight_decimal_places_kept_through_pipeline() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            max_scale: 8,
+            ..HandlerConfig::default()
+        });
+        let mut csv = String::from("type,client,tx,amount\n");
+        for txid in 1..=8 {
+            csv.push_str(&format!("deposit,1,{},0.00000001\n", txid));
+        }
+        csv.push_str("dispute,1,8,\nwithdrawal,1,9,0.00000002\n");
+        let stats = handler.ingest_csv(csv.as_bytes()).unwrap();
+        assert_eq!(stats.deposits, 8);
+        assert_eq!(stats.withdrawals, 1);
+
+        // the default output scale follows the maximum scale
+        assert_eq!(handler.output_scale(), 8);
+        let snapshot = handler.snapshot_accounts_sorted().remove(0);
+        assert_eq!(snapshot.total.to_string(), "0.00000006");
+        // a smaller scale rounds the balances away, but the account is unaffected
+        let snapshot = handler.snapshots_sorted_iter_with_scale(4).next().unwrap();
+        assert_eq!(snapshot.total.to_string(), "0.0000");
+        let snapshot = handler.snapshot(ClientId(1)).unwrap();
+        assert_eq!(snapshot.available.to_string(), "0.00000005");
+        assert_eq!(snapshot.held.to_string(), "0.00000001");
+        assert_eq!(snapshot.total.to_string(), "0.00000006");
+    }
+
+    #[test]
+    fn accrue_interest_skips_locked_accounts() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .seed_account(ClientId(1), Decimal::new(10000, 2), Decimal::ZERO, false)
+            .unwrap();
+        handler
+            .seed_account(ClientId(2), Decimal::new(10000, 2), Decimal::ZERO, true)
+            .unwrap();
+        handler
+            .seed_account(ClientId(3), Decimal::new(1, 4), Decimal::ZERO, false)
+            .unwrap();
+
+        handler.accrue_interest(Decimal::new(5, 2)).unwrap();
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10500, 2)));
+        assert_eq!(handler.available(ClientId(2)), Some(Decimal::new(10000, 2)));
+        // sub-cent interest rounds to zero at the maximum scale
+        assert_eq!(handler.available(ClientId(3)), Some(Decimal::new(1, 4)));
+        assert_eq!(handler.txid_count(), 0);
+    }
+
+    #[test]
+    fn accrue_interest_over_cap_credits_nothing() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            max_available: Some(Decimal::new(200, 0)),
+            ..HandlerConfig::default()
+        });
+        handler
+            .seed_account(ClientId(1), Decimal::new(100, 0), Decimal::ZERO, false)
+            .unwrap();
+        handler
+            .seed_account(ClientId(2), Decimal::new(199, 0), Decimal::ZERO, false)
+            .unwrap();
+
+        assert_eq!(
+            handler.accrue_interest(Decimal::new(5, 2)),
+            Err(TransactionError::BalanceCapExceeded {
+                client_id: ClientId(2)
+            })
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(100, 0)));
+    }
+
+    #[test]
+    fn open_disputes_lists_unsettled_disputes() {
+        let mut handler = AccountsHandler::new();
+        for (client_id, txid) in [(2, 1), (1, 2)] {
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+            handler
+                .submit_transaction(Transaction {
+                    tx_type: TransactionType::Dispute,
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        handler
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Resolve,
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            })
+            .unwrap();
+
+        assert_eq!(
+            handler.open_disputes(),
+            vec![(ClientId(2), Txid(1), Decimal::new(10, 0))]
+        );
+    }
+
+    #[test]
+    fn checkpoint_reflects_submissions_so_far() {
+        let mut handler = AccountsHandler::new();
+        let deposit = |txid| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(1, 0))),
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.submit_transaction(deposit(1)).unwrap();
+        let checkpoint = handler.checkpoint();
+        assert_eq!(handler.sequence(), 1);
+        handler.submit_transaction(deposit(2)).unwrap();
+
+        assert_eq!(checkpoint[0].available, Decimal::new(1, 0));
+        assert_eq!(handler.checkpoint()[0].available, Decimal::new(2, 0));
+        assert_eq!(handler.sequence(), 2);
+    }
+
+    #[test]
+    fn submit_and_snapshot_returns_fresh_state() {
+        let mut handler = AccountsHandler::new();
+        let snapshot = handler
+            .submit_and_snapshot(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(150, 2))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        assert_eq!(snapshot.available, Decimal::new(150, 2));
+
+        // an ignored transaction still returns the current state
+        let snapshot = handler
+            .submit_and_snapshot(Transaction {
+                tx_type: TransactionType::Withdrawal(amount(Decimal::new(2, 0))),
+                client_id: ClientId(1),
+                txid: Txid(2),
+                timestamp: None,
+            })
+            .unwrap();
+        assert_eq!(snapshot.available, Decimal::new(150, 2));
+
+        let snapshot = handler
+            .submit_and_snapshot(Transaction {
+                tx_type: TransactionType::Dispute,
+                client_id: ClientId(2),
+                txid: Txid(3),
+                timestamp: None,
+            })
+            .unwrap();
+        assert_eq!(snapshot.id, ClientId(2));
+        assert_eq!(snapshot.total, Decimal::ZERO);
+    }
+
+    #[test]
+    fn max_transactions_per_client_rejects_beyond_cap() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            max_transactions_per_client: Some(2),
+            ..HandlerConfig::default()
+        });
+        let deposit = |client_id, txid| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::new(10, 0))),
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        // an ignored transaction is not counted
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal(amount(Decimal::new(100, 0))),
+            ..deposit(1, 1)
+        };
+        assert_eq!(
+            handler.submit_transaction(withdrawal),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        assert_eq!(
+            handler.submit_transaction(deposit(1, 2)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.submit_transaction(deposit(1, 3)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.submit_transaction(deposit(1, 4)),
+            Err(TransactionError::RateLimited {
+                client_id: ClientId(1)
+            })
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(20, 0)));
+        // other clients proceed
+        assert_eq!(
+            handler.submit_transaction(deposit(2, 4)),
+            Ok(ProcessOutcome::Applied)
+        );
+    }
+
+    #[test]
+    fn min_available_floors_withdrawals() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            min_available: Decimal::new(5, 0),
+            ..HandlerConfig::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(2, 0))), 3),
+                tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 4),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(7, 0))), 5),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::MinimumBalanceBreach {
+                    client_id: ClientId(1)
+                }),
+                // the floor itself may be reached
+                Ok(ProcessOutcome::Applied),
+                // overdrawing is still ignored
+                Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds)),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(5, 0)));
+
+        // disputes may hold funds below the floor
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Dispute, 1)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::PartialDispute(Decimal::new(3, 0)), 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(2, 0)));
+    }
+
+    #[test]
+    fn txid_window_forgets_old_txids() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            txid_window: Some(2),
+            ..HandlerConfig::default()
+        });
+        let deposit_of = |client_id, txid| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let deposit = |txid| deposit_of(1, txid);
+        assert_eq!(
+            handler.submit_batch([deposit(1), deposit(2), deposit(3), deposit(1)]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                // txid 1 is still within the window
+                Err(TransactionError::DuplicateTxid(Txid(1))),
+            ]
+        );
+        // the window rolled over twice since txid 1 was consumed
+        assert!(!handler.has_txid(Txid(1)));
+        assert!(handler.has_txid(Txid(3)));
+        assert_eq!(
+            handler.submit_batch([deposit(1), deposit_of(2, 1), deposit(3)]),
+            vec![
+                // the deposit of txid 1 is still recorded on the account
+                Err(TransactionError::DuplicateTxid(Txid(1))),
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::DuplicateTxid(Txid(3))),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(3, 0)));
+        assert_eq!(handler.available(ClientId(2)), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn txid_window_reuse_keeps_records() {
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let deposit = |value, txid| {
+            tx(
+                TransactionType::Deposit(amount(Decimal::new(value, 0))),
+                txid,
+            )
+        };
+
+        // a reused txid does not leave a second copy to evict
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            txid_window: Some(2),
+            max_records_per_account: Some(1),
+            ..HandlerConfig::default()
+        });
+        let results = handler.submit_batch([
+            deposit(1, 1),
+            deposit(1, 4),
+            deposit(1, 5),
+            deposit(1, 6),
+            deposit(1, 1),
+            deposit(1, 2),
+            deposit(1, 3),
+        ]);
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(handler.validate_invariants(), Ok(()));
+
+        // a reused txid cannot strand the amount held by the dispute of its record
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            txid_window: Some(2),
+            ..HandlerConfig::default()
+        });
+        handler.extend([
+            deposit(10, 1),
+            tx(TransactionType::Dispute, 1),
+            deposit(1, 2),
+            deposit(1, 3),
+            deposit(1, 4),
+        ]);
+        assert!(!handler.has_txid(Txid(1)));
+        assert_eq!(
+            handler.submit_transaction(deposit(3, 1)),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Resolve, 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(13, 0)));
+        assert_eq!(handler.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn resolve_of_withdrawal_releases_hold() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+            tx(
+                TransactionType::Transfer {
+                    to: ClientId(2),
+                    amount: Decimal::ONE,
+                },
+                3,
+            ),
+        ]);
+        assert_eq!(
+            handler.record_kind(ClientId(1), Txid(1)),
+            Some(TxKind::Deposit)
+        );
+        assert_eq!(
+            handler.record_kind(ClientId(1), Txid(2)),
+            Some(TxKind::Withdrawal)
+        );
+        // transfers are not recorded
+        assert_eq!(handler.record_kind(ClientId(1), Txid(3)), None);
+        assert_eq!(handler.record_kind(ClientId(2), Txid(2)), None);
+
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Resolve, 2)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed))
+        );
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Dispute, 2),
+                tx(TransactionType::Resolve, 2),
+            ]),
+            vec![Ok(ProcessOutcome::Applied), Ok(ProcessOutcome::Applied)]
+        );
+        // the withdrawal stands, so available is untouched
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(5, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+    }
+
+    #[test]
+    fn freeze_account_blocks_withdrawals() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler
+            .submit_transaction(tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1))
+            .unwrap();
+        assert_eq!(handler.freeze_account(ClientId(1)), Ok(()));
+        assert!(handler.snapshot(ClientId(1)).unwrap().locked);
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 2)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+
+        assert_eq!(handler.unfreeze_account(ClientId(1)), Ok(()));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 3)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(9, 0)));
+
+        assert_eq!(
+            handler.freeze_account(ClientId(2)),
+            Err(TransactionError::UnknownClient(ClientId(2)))
+        );
+    }
+
+    #[test]
+    fn snapshot_accounts_order_is_stable() {
+        let txs: Vec<_> = (1..=50)
+            .map(|id| Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+                client_id: ClientId(id * 7 % 101),
+                txid: Txid(u64::from(id)),
+                timestamp: None,
+            })
+            .collect();
+        let order = || {
+            let mut handler = AccountsHandler::new();
+            handler.extend(txs.clone());
+            handler
+                .snapshot_accounts()
+                .into_iter()
+                .map(|snapshot| snapshot.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(), order());
+    }
+
+    #[test]
+    fn ingest_until_defers_later_transactions() {
+        let mut handler = AccountsHandler::new();
+        let deposit = |txid, timestamp| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::from(txid))),
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp,
+        };
+        let deferred = handler.ingest_until(
+            [
+                deposit(1, Some(100)),
+                deposit(2, Some(300)),
+                deposit(4, None),
+                deposit(8, Some(200)),
+                deposit(16, Some(201)),
+            ],
+            200,
+        );
+        assert_eq!(
+            deferred,
+            vec![deposit(2, Some(300)), deposit(16, Some(201))]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(13, 0)));
+    }
+
+    #[test]
+    fn recompute_from_deposits_repairs_held() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(4, 0))), 1, 2),
+            tx(TransactionType::Dispute, 1, 2),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2, 3),
+        ]);
+
+        // corrupt the held balance of client 1 as if edited by hand
+        let mut state = serde_json::to_value(handler.dump_state()).unwrap();
+        for account in state["accounts"].as_array_mut().unwrap() {
+            if account["id"] == 1 {
+                account["held"] = "7".into();
+            }
+        }
+        let mut handler = AccountsHandler::load_state(serde_json::from_value(state).unwrap());
+        assert!(handler.validate_invariants().is_err());
+        // the broken state can still be inspected before repairing it
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().held,
+            Decimal::new(7, 0)
+        );
+
+        assert_eq!(handler.recompute_from_deposits(), Ok(vec![ClientId(1)]));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(4, 0)));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert_eq!(handler.recompute_from_deposits(), Ok(vec![]));
+    }
+
+    #[test]
+    fn snapshot_dirty_returns_changed_accounts() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 2, 2),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 3, 3),
+        ]);
+        assert_eq!(handler.snapshot_dirty().len(), 3);
+        assert_eq!(handler.snapshot_dirty(), vec![]);
+
+        handler.extend([
+            tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 3, 4),
+            tx(TransactionType::Dispute, 1, 1),
+            // ignored transactions leave client 2 unchanged
+            tx(
+                TransactionType::Withdrawal(amount(Decimal::new(100, 0))),
+                2,
+                5,
+            ),
+        ]);
+        let ids: Vec<_> = handler
+            .snapshot_dirty()
+            .into_iter()
+            .map(|snapshot| snapshot.id)
+            .collect();
+        assert_eq!(ids, vec![ClientId(1), ClientId(3)]);
+        assert_eq!(handler.snapshot_dirty(), vec![]);
+    }
+
+    #[test]
+    fn resolve_all_works() {
+        let mut handler = AccountsHandler::new();
+        let deposit = |client_id, txid, value| Transaction {
+            tx_type: TransactionType::Deposit(amount(value)),
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            deposit(1, 1, Decimal::new(1, 0)),
+            deposit(1, 2, Decimal::new(2, 0)),
+            deposit(1, 3, Decimal::new(3, 0)),
+            deposit(2, 4, Decimal::new(4, 0)),
+        ]);
+        assert_eq!(handler.dispute_all_deposits(ClientId(1)), 3);
+        assert_eq!(handler.dispute_all_deposits(ClientId(1)), 0);
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(6, 0)));
+
+        assert_eq!(handler.resolve_all(ClientId(1)), 3);
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(6, 0)));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+        assert_eq!(handler.resolve_all(ClientId(1)), 0);
+        assert_eq!(handler.resolve_all(ClientId(3)), 0);
+        assert_eq!(handler.validate_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn account_factory_opens_accounts() {
+        let config = HandlerConfig {
+            locked_policy: LockedPolicy::Reject,
+            ..HandlerConfig::default()
+        };
+        let mut handler = AccountsHandler::with_account_factory(config, |client_id| {
+            AccountSnapshot::new(client_id, Decimal::ZERO, Decimal::ZERO, true, 0)
+        });
+        let deposit = |client_id| Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+            client_id: ClientId(client_id),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_transaction(deposit(1)),
+            Err(TransactionError::AccountLocked(ClientId(1)))
+        );
+        // the failing deposit leaves no account behind
+        assert!(!handler.contains(ClientId(1)));
+        // a resolve reaches a locked account, opening it
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Resolve,
+                ..deposit(1)
+            }),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::UnknownTxid))
+        );
+        assert!(handler.snapshot(ClientId(1)).unwrap().locked);
+        handler.unlock_account(ClientId(1)).unwrap();
+        assert_eq!(
+            handler.submit_transaction(deposit(1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn transfer_checks_new_recipient() {
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let transactions = [
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(
+                TransactionType::Transfer {
+                    to: ClientId(9),
+                    amount: Decimal::new(5, 0),
+                },
+                1,
+                2,
+            ),
+        ];
+
+        // a recipient opened locked is not credited
+        let mut handler = AccountsHandler::with_account_factory(HandlerConfig::default(), |id| {
+            AccountSnapshot::new(id, Decimal::ZERO, Decimal::ZERO, id == ClientId(9), 0)
+        });
+        assert_eq!(
+            handler.submit_batch(transactions),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount)),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert!(!handler.contains(ClientId(9)));
+
+        // a recipient opened at the cap fails the transfer before the sender is debited
+        let config = HandlerConfig {
+            max_available: Some(Decimal::new(100, 0)),
+            ..HandlerConfig::default()
+        };
+        let mut handler = AccountsHandler::with_account_factory(config, |id| {
+            let available = if id == ClientId(9) {
+                Decimal::new(99, 0)
+            } else {
+                Decimal::ZERO
+            };
+            AccountSnapshot::new(id, available, Decimal::ZERO, false, 0)
+        });
+        assert_eq!(
+            handler.submit_batch(transactions),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Err(TransactionError::BalanceCapExceeded {
+                    client_id: ClientId(9)
+                }),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(10, 0)));
+        assert!(!handler.has_txid(Txid(2)));
+        assert!(!handler.contains(ClientId(9)));
+    }
+
+    #[test]
+    #[should_panic(expected = "account factory returned a snapshot of another client")]
+    fn account_factory_client_mismatch_panics() {
+        let mut handler = AccountsHandler::with_account_factory(HandlerConfig::default(), |_| {
+            AccountSnapshot::new(ClientId(2), Decimal::ZERO, Decimal::ZERO, false, 0)
+        });
+        let _ = handler.submit_transaction(Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        });
+    }
+
+    #[test]
+    fn settling_undisputed_deposit_is_not_disputed() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler
+            .submit_transaction(tx(TransactionType::Deposit(amount(Decimal::ONE)), 1))
+            .unwrap();
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Resolve, 1),
+                tx(TransactionType::Chargeback, 1),
+                tx(TransactionType::Chargeback, 2),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed)),
+                Ok(ProcessOutcome::Ignored(IgnoreReason::NotDisputed)),
+                // told apart from a txid never seen
+                Ok(ProcessOutcome::Ignored(IgnoreReason::UnknownTxid)),
+            ]
+        );
+        assert_eq!(handler.stats().ignored[&IgnoreReason::NotDisputed], 2);
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn settlements_reuse_deposit_txid() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::ONE))),
+                tx(TransactionType::Dispute),
+                tx(TransactionType::Resolve),
+                tx(TransactionType::Dispute),
+                tx(TransactionType::Chargeback),
+            ]),
+            vec![Ok(ProcessOutcome::Applied); 5]
+        );
+        // only deposits, withdrawals and transfers must be unique
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Withdrawal(amount(Decimal::ONE)))),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(handler.stats().duplicate_txids, 1);
+    }
+
+    #[test]
+    fn finalize_drops_pending() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            buffer_disputes: true,
+            ..Default::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert!(handler.finalize().is_empty());
+
+        let dispute = tx(TransactionType::Dispute, 2);
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::ONE)), 1),
+                dispute
+            ]),
+            vec![Ok(ProcessOutcome::Applied), Ok(ProcessOutcome::Buffered)]
+        );
+        let report = handler.finalize();
+        assert!(!report.is_empty());
+        assert_eq!(report.dropped, vec![dispute]);
+        assert_eq!(report.stats.ignored[&IgnoreReason::UnknownTxid], 1);
+        assert_eq!(report.invariants, Ok(()));
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+        assert!(handler.finalize().is_empty());
+    }
+
+    #[test]
+    fn shadow_divergence_works() {
+        let mut handler = AccountsHandler::with_shadow(
+            HandlerConfig::default(),
+            HandlerConfig {
+                dispute_policy: DisputePolicy::AllowNegative,
+                ..Default::default()
+            },
+        );
+        assert!(handler.shadow_divergence().is_empty());
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+                tx(
+                    TransactionType::Withdrawal(amount(Decimal::new(6, 0))),
+                    1,
+                    2
+                ),
+                tx(TransactionType::Dispute, 1, 1),
+                tx(TransactionType::Deposit(amount(Decimal::ONE)), 2, 3),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds)),
+                Ok(ProcessOutcome::Applied),
+            ]
+        );
+
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::ZERO));
+        assert_eq!(
+            handler.shadow().unwrap().held(ClientId(1)),
+            Some(Decimal::new(10, 0))
+        );
+        assert_eq!(
+            handler.shadow_divergence(),
+            vec![SnapshotDelta {
+                id: ClientId(1),
+                kind: DeltaKind::Changed,
+                available: Decimal::new(-10, 0),
+                held: Decimal::new(10, 0),
+                total: Decimal::ZERO,
+                locked: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn export_events_replays() {
+        let config = HandlerConfig {
+            event_log: true,
+            buffer_disputes: true,
+            ..Default::default()
+        };
+        let mut handler = AccountsHandler::with_config(config);
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Dispute, 1, 3),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(
+                TransactionType::Withdrawal(amount(Decimal::new(20, 0))),
+                1,
+                2,
+            ),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 1, 3),
+            tx(TransactionType::Deposit(amount(Decimal::new(3, 0))), 2, 4),
+            tx(TransactionType::Chargeback, 2, 4),
+            tx(TransactionType::Dispute, 2, 4),
+            tx(TransactionType::Chargeback, 2, 4),
+        ]);
+
+        let events = handler.export_events();
+        assert_eq!(
+            events.iter().map(|tx| tx.txid.0).collect::<Vec<_>>(),
+            vec![1, 3, 3, 4, 4, 4]
+        );
+        let mut replayed = AccountsHandler::with_config(config);
+        replayed.extend(events);
+        assert_eq!(
+            replayed.snapshot_accounts_sorted(),
+            handler.snapshot_accounts_sorted()
+        );
+        assert!(AccountsHandler::new().export_events().is_empty());
+    }
+
+    #[test]
+    fn settlements_do_not_consume_txids() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Dispute, 1),
+                tx(TransactionType::Resolve, 1),
+                tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2),
+            ]),
+            vec![Ok(ProcessOutcome::Applied); 4]
+        );
+        assert_eq!(handler.txid_count(), 2);
+        assert!(handler.has_txid(Txid(1)) && handler.has_txid(Txid(2)));
+
+        // a dispute of a txid never deposited does not consume it either
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Dispute, 3)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::UnknownTxid))
+        );
+        assert!(!handler.has_txid(Txid(3)));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Deposit(amount(Decimal::ONE)), 3)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.txid_count(), 3);
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(16, 0)));
+    }
+
+    #[test]
+    fn repeat_deposit_policies_work() {
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let deposit = |value, client_id| tx(TransactionType::Deposit(amount(value)), client_id, 1);
+        let process = |repeat_deposit_policy, value| {
+            let mut handler = AccountsHandler::with_config(HandlerConfig {
+                repeat_deposit_policy,
+                ..Default::default()
+            });
+            assert_eq!(
+                handler.submit_batch([
+                    deposit(Decimal::new(10, 0), 1),
+                    tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 1, 2),
+                ]),
+                vec![Ok(ProcessOutcome::Applied); 2]
+            );
+            let result = handler.submit_transaction(deposit(value, 1));
+            (handler, result)
+        };
+
+        let (handler, result) = process(RepeatDepositPolicy::Reject, Decimal::new(4, 0));
+        assert_eq!(result, Err(TransactionError::DuplicateTxid(Txid(1))));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(15, 0)));
+
+        let (mut handler, result) = process(RepeatDepositPolicy::Overwrite, Decimal::new(4, 0));
+        assert_eq!(result, Ok(ProcessOutcome::Applied));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(9, 0)));
+        assert_eq!(handler.txid_count(), 2);
+        assert_eq!(handler.stats().deposits, 3);
+        // the amended deposit is disputed for its amended amount
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Dispute, 1, 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(4, 0)));
+        assert_eq!(
+            handler.submit_transaction(deposit(Decimal::new(6, 0), 1)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::AlreadyDisputed))
+        );
+        assert_eq!(handler.validate_invariants(), Ok(()));
+
+        let (handler, result) = process(RepeatDepositPolicy::Overwrite, Decimal::new(30, 0));
+        assert_eq!(result, Ok(ProcessOutcome::Applied));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(35, 0)));
+
+        let (mut handler, result) = process(RepeatDepositPolicy::Sum, Decimal::new(4, 0));
+        assert_eq!(result, Ok(ProcessOutcome::Applied));
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(19, 0)));
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Dispute, 1, 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(handler.held(ClientId(1)), Some(Decimal::new(14, 0)));
+
+        // only an earlier deposit of the same client can be amended
+        let (mut handler, _) = process(RepeatDepositPolicy::Sum, Decimal::new(4, 0));
+        assert_eq!(
+            handler.submit_transaction(deposit(Decimal::ONE, 2)),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 1, 1)),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+    }
+
+    #[test]
+    fn repeat_deposit_overwrite_insufficient_funds_ignored() {
+        let mut handler = AccountsHandler::with_config(HandlerConfig {
+            repeat_deposit_policy: RepeatDepositPolicy::Overwrite,
+            ..Default::default()
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        assert_eq!(
+            handler.submit_batch([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(8, 0))), 2),
+                tx(TransactionType::Deposit(amount(Decimal::new(1, 0))), 1),
+            ]),
+            vec![
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Applied),
+                Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds)),
+            ]
+        );
+        assert_eq!(handler.available(ClientId(1)), Some(Decimal::new(2, 0)));
+    }
+
+    #[test]
+    fn account_status_transitions() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2),
+        ]);
+        assert_eq!(handler.status(ClientId(1)), Some(AccountStatus::Active));
+        assert_eq!(handler.status(ClientId(2)), None);
+
+        assert_eq!(handler.freeze_account(ClientId(1)), Ok(()));
+        assert_eq!(handler.status(ClientId(1)), Some(AccountStatus::Frozen));
+        assert_eq!(handler.unfreeze_account(ClientId(1)), Ok(()));
+        assert_eq!(handler.status(ClientId(1)), Some(AccountStatus::Active));
+
+        handler.extend([
+            tx(TransactionType::Dispute, 1),
+            tx(TransactionType::Chargeback, 1),
+        ]);
+        assert_eq!(
+            handler.status(ClientId(1)),
+            Some(AccountStatus::ChargebackLocked)
+        );
+        // freezing does not weaken a chargeback lock, which unfreezing cannot lift
+        assert_eq!(handler.freeze_account(ClientId(1)), Ok(()));
+        assert_eq!(
+            handler.unfreeze_account(ClientId(1)),
+            Err(TransactionError::ChargebackLocked(ClientId(1)))
+        );
+        assert_eq!(
+            handler.status(ClientId(1)),
+            Some(AccountStatus::ChargebackLocked)
+        );
+        assert_eq!(handler.unlock_account(ClientId(1)), Ok(()));
+        assert_eq!(handler.status(ClientId(1)), Some(AccountStatus::Active));
+    }
+
+    #[test]
+    fn account_status_serializes_locked_flag() {
+        let mut handler = AccountsHandler::new();
+        handler
+            .seed_account(ClientId(1), Decimal::ONE, Decimal::ZERO, false)
+            .unwrap();
+        handler
+            .seed_account(ClientId(2), Decimal::ONE, Decimal::ZERO, false)
+            .unwrap();
+        handler.freeze_account(ClientId(2)).unwrap();
+        let json: Vec<_> = handler
+            .snapshots_sorted_iter()
+            .map(|snapshot| serde_json::to_value(snapshot).unwrap()["locked"].clone())
+            .collect();
+        assert_eq!(
+            json,
+            vec![
+                serde_json::Value::Bool(false),
+                serde_json::Value::Bool(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn on_applied_fires_for_applied_transactions() {
+        let mut handler = AccountsHandler::new();
+        let applied = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = std::sync::Arc::clone(&applied);
+        handler.on_applied(move |tx, snapshot| {
+            collected
+                .lock()
+                .unwrap()
+                .push((tx.txid, snapshot.available, snapshot.held))
+        });
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let results = handler.submit_batch([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            tx(TransactionType::Withdrawal(amount(Decimal::new(20, 0))), 2),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 1),
+            tx(TransactionType::Dispute, 1),
+            tx(TransactionType::Resolve, 3),
+        ]);
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert_eq!(
+            *applied.lock().unwrap(),
+            vec![
+                (Txid(1), Decimal::new(10, 0), Decimal::ZERO),
+                (Txid(1), Decimal::ZERO, Decimal::new(10, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn submit_transaction_forced_keeps_lock() {
+        let mut handler = AccountsHandler::new();
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2),
+            tx(TransactionType::Dispute, 1),
+            tx(TransactionType::Chargeback, 1),
+        ]);
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Deposit(amount(Decimal::new(3, 0))), 3)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+
+        assert_eq!(
+            handler.submit_transaction_forced(tx(
+                TransactionType::Deposit(amount(Decimal::new(3, 0))),
+                4
+            )),
+            Ok(ProcessOutcome::Applied)
+        );
+        let snapshot = handler.snapshot(ClientId(1)).unwrap();
+        assert_eq!(snapshot.available, Decimal::new(8, 0));
+        assert!(snapshot.locked);
+        assert_eq!(
+            handler.status(ClientId(1)),
+            Some(AccountStatus::ChargebackLocked)
+        );
+        // the lock still applies to the transactions submitted as usual
+        assert_eq!(
+            handler.submit_transaction(tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 5)),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount))
+        );
+    }
+
+    #[test]
+    fn withdrawal_disputes_settle() {
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let balances = |settlement| {
+            let handler: AccountsHandler = [
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+                tx(TransactionType::Dispute, 2),
+                tx(settlement, 2),
+            ]
+            .into_iter()
+            .collect();
+            let snapshot = handler.snapshot(ClientId(1)).unwrap();
+            (snapshot.available, snapshot.held, snapshot.locked)
+        };
+        // resolving leaves the withdrawal standing, netting the dispute out
+        assert_eq!(
+            balances(TransactionType::Resolve),
+            (Decimal::new(6, 0), Decimal::ZERO, false)
+        );
+        // charging back claws the withdrawn funds back into available
+        assert_eq!(
+            balances(TransactionType::Chargeback),
+            (Decimal::new(10, 0), Decimal::ZERO, true)
+        );
+    }
+
+    #[test]
+    fn convenience_methods_allocate_txids() {
+        let mut handler = AccountsHandler::new();
+        // a txid consumed by hand is skipped
+        assert_eq!(
+            handler.submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(Decimal::new(2, 0))),
+                client_id: ClientId(2),
+                txid: Txid(1),
+                timestamp: None,
+            }),
+            Ok(ProcessOutcome::Applied)
+        );
+        let (deposit, outcome) = handler.deposit(ClientId(1), amount(Decimal::ONE)).unwrap();
+        assert_eq!((deposit, outcome), (Txid(2), ProcessOutcome::Applied));
+        assert_eq!(
+            handler.deposit(ClientId(1), amount(Decimal::new(2, 0))),
+            Ok((Txid(3), ProcessOutcome::Applied))
+        );
+        assert_eq!(
+            handler.withdraw(ClientId(1), amount(Decimal::new(15, 1))),
+            Ok((Txid(4), ProcessOutcome::Applied))
+        );
+        assert_eq!(
+            handler.withdraw(ClientId(2), amount(Decimal::new(3, 0))),
+            Ok((
+                Txid(5),
+                ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds)
+            ))
+        );
+        assert_eq!(
+            handler.dispute(ClientId(1), deposit),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.resolve(ClientId(1), deposit),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.dispute(ClientId(1), deposit),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.chargeback(ClientId(1), deposit),
+            Ok(ProcessOutcome::Applied)
+        );
+
+        let client = handler.snapshot(ClientId(1)).unwrap();
+        assert_eq!(
+            (client.available, client.held, client.locked),
+            (Decimal::new(5, 1), Decimal::ZERO, true)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(2)).unwrap().available,
+            Decimal::new(2, 0)
+        );
+    }
+
+    #[test]
+    fn collected_fees_survive_closing() {
+        let config = HandlerConfig {
+            withdrawal_fee: Decimal::ONE,
+            ..HandlerConfig::default()
+        };
+        // deposit 11 then withdraw 10 and the fee of 1, emptying the account
+        let emptied = |client_id, txid| {
+            let mut handler = AccountsHandler::with_config(config);
+            for (tx_type, txid) in [
+                (TransactionType::Deposit(amount(Decimal::new(11, 0))), txid),
+                (
+                    TransactionType::Withdrawal(amount(Decimal::new(10, 0))),
+                    txid + 1,
+                ),
+            ] {
+                let tx = Transaction {
+                    tx_type,
+                    client_id,
+                    txid: Txid(txid),
+                    timestamp: None,
+                };
+                assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
+            }
+            handler
+        };
+        let mut handler = emptied(ClientId(1), 1);
+        assert_eq!(handler.collected_fees(), Decimal::ONE);
+        assert_eq!(handler.close_account(ClientId(1)), Ok(()));
+        assert_eq!(handler.snapshot(ClientId(1)), None);
+        assert_eq!(handler.collected_fees(), Decimal::ONE);
+
+        // the fees of closed accounts are persisted and merged
+        let mut restored = AccountsHandler::load_state(handler.dump_state());
+        assert_eq!(restored.collected_fees(), Decimal::ONE);
+        let mut other = emptied(ClientId(1), 3);
+        other.close_account(ClientId(1)).unwrap();
+        restored.merge(other).unwrap();
+        assert_eq!(restored.collected_fees(), Decimal::new(2, 0));
+    }
+
+    #[test]
+    fn seed_account_overflow_fails() {
+        let mut handler = AccountsHandler::new();
+        assert_eq!(
+            handler.seed_account(ClientId(1), Decimal::MAX, Decimal::MAX, false),
+            Err(TransactionError::BalanceOverflow {
+                client_id: ClientId(1)
+            })
+        );
+        assert!(!handler.contains(ClientId(1)));
+    }
+
+    #[test]
+    fn try_total_overflow_fails() {
+        let mut handler = AccountsHandler::new();
+        // twice this is one more than `Decimal::MAX`
+        let half = Decimal::from_i128_with_scale(39614081257132168796771975168, 0);
+        let tx = |tx_type, txid| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        handler.extend([
+            tx(TransactionType::Deposit(amount(half)), 1),
+            tx(TransactionType::Dispute, 1),
+            tx(TransactionType::Deposit(amount(half)), 2),
+        ]);
+        assert_eq!(
+            handler.try_total(ClientId(1)),
+            Some(Err(InvariantError::TotalOverflow {
+                client_id: ClientId(1)
+            }))
+        );
+        assert_eq!(handler.try_total(ClientId(2)), None);
+
+        handler.extend([tx(TransactionType::Withdrawal(amount(Decimal::ONE)), 3)]);
+        assert_eq!(handler.try_total(ClientId(1)), Some(Ok(Decimal::MAX)));
+        assert_eq!(handler.total(ClientId(1)), Some(Decimal::MAX));
+    }
+
+    #[test]
+    fn try_collected_fees_overflow_fails() {
+        let mut handler = AccountsHandler::new();
+        handler.closed_fees.insert(ClientId(1), Decimal::MAX);
+        assert_eq!(handler.try_collected_fees(), Ok(Decimal::MAX));
+        handler.closed_fees.insert(ClientId(2), Decimal::MAX);
+        assert!(matches!(
+            handler.try_collected_fees(),
+            Err(TransactionError::BalanceOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn extend_matches_submitting_one_by_one() {
+        let tx = |tx_type, client_id, txid| Transaction {
+            tx_type,
+            client_id: ClientId(client_id),
+            txid: Txid(txid),
+            timestamp: None,
+        };
+        let txs = [
+            tx(TransactionType::Dispute, 1, 3),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1, 1),
+            tx(
+                TransactionType::Withdrawal(amount(Decimal::new(4, 0))),
+                2,
+                2,
+            ),
+            tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 1, 3),
+            tx(
+                TransactionType::Transfer {
+                    to: ClientId(2),
+                    amount: Decimal::new(-1, 0),
+                },
+                1,
+                4,
+            ),
+            tx(TransactionType::Deposit(amount(Decimal::new(2, 0))), 2, 5),
+        ];
+        let config = HandlerConfig {
+            buffer_disputes: true,
+            negative_amount_policy: NegativeAmountPolicy::Reject,
+            txid_window: Some(3),
+            ..HandlerConfig::default()
+        };
+
+        let mut extended = AccountsHandler::with_config(config);
+        extended.extend(txs);
+        let mut submitted = AccountsHandler::with_config(config);
+        for tx in txs {
+            let _ = submitted.submit_transaction(tx);
+        }
+        assert_eq!(
+            extended.snapshot_accounts_sorted(),
+            submitted.snapshot_accounts_sorted()
+        );
+        assert_eq!(extended.stats(), submitted.stats());
+        assert_eq!(extended.held(ClientId(1)), Some(Decimal::new(5, 0)));
     }
 }
@@ -0,0 +1,180 @@
+//! # Server
+//!
+//! `server` is an example binary embedding the accounts handler in a minimal HTTP/1.1 server,
+//! built with the `server` feature.
+//!
+//! `POST /transactions` submits a JSON array of transactions, each with the fields of a CSV row,
+//! and `GET /accounts` returns the snapshots of every account in ascending order of client id.
+//! Every connection is served by its own thread, sharing a single [`SyncAccountsHandler`], and is
+//! closed after one response. Transactions posted in the same request may thus interleave with
+//! those of concurrent requests.
+
+use std::{
+    env,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+};
+
+use serde::Serialize;
+
+use coding_test::{RawTransaction, SyncAccountsHandler, ValidationConfig};
+
+/// Address listened on if none is given
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+/// Maximum length of a request body, guarding against allocating for a bogus content length
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+/// Response to `POST /transactions`
+#[derive(Serialize)]
+struct SubmitResponse {
+    /// Number of transactions submitted without an error, whether applied or ignored
+    submitted: usize,
+    /// Errors of the transactions that could not be validated or submitted, tagged with their
+    /// 0-based index in the request
+    errors: Vec<String>,
+}
+
+/// HTTP response, serialized with its status line
+struct Response {
+    status: &'static str,
+    body: String,
+}
+
+impl Response {
+    /// Create a response with a JSON body
+    fn json(body: &impl Serialize) -> Self {
+        Self {
+            status: "200 OK",
+            body: serde_json::to_string(body).expect("responses serialize to JSON"),
+        }
+    }
+
+    /// Create an error response with a plain text body
+    fn error(status: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: message.into(),
+        }
+    }
+
+    /// Write the response to `output`
+    fn write_to(&self, mut output: impl Write) -> io::Result<()> {
+        let content_type = if self.status.starts_with("200") {
+            "application/json"
+        } else {
+            "text/plain"
+        };
+        write!(
+            output,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            content_type,
+            self.body.len(),
+            self.body
+        )?;
+        output.flush()
+    }
+}
+
+/// Submit the JSON array of transactions in `body` to `accounts`
+fn submit_transactions(accounts: &SyncAccountsHandler, body: &[u8]) -> Response {
+    let transactions: Vec<RawTransaction> = match serde_json::from_slice(body) {
+        Ok(transactions) => transactions,
+        Err(e) => return Response::error("400 Bad Request", e.to_string()),
+    };
+    let config = ValidationConfig::default();
+    let mut response = SubmitResponse {
+        submitted: 0,
+        errors: Vec::new(),
+    };
+    for (index, transaction) in transactions.into_iter().enumerate() {
+        let result = transaction
+            .into_transaction(&config)
+            .map_err(|e| e.to_string())
+            .and_then(|tx| accounts.submit_transaction(tx).map_err(|e| e.to_string()));
+        match result {
+            Ok(_) => response.submitted += 1,
+            Err(e) => response.errors.push(format!("{}: {}", index, e)),
+        }
+    }
+    Response::json(&response)
+}
+
+/// Read a request from `stream` and route it, returning the response
+fn handle(stream: &TcpStream, accounts: &SyncAccountsHandler) -> io::Result<Response> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(Response::error("400 Bad Request", "malformed request line"));
+    };
+
+    // only the length of the body is of interest among the headers
+    let mut content_len = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                match value.trim().parse() {
+                    Ok(len) => content_len = len,
+                    Err(_) => {
+                        return Ok(Response::error("400 Bad Request", "invalid content length"))
+                    }
+                }
+            }
+        }
+    }
+    if content_len > MAX_BODY_LEN {
+        return Ok(Response::error(
+            "413 Payload Too Large",
+            format!("body exceeds {} bytes", MAX_BODY_LEN),
+        ));
+    }
+    let mut body = vec![0; content_len];
+    reader.read_exact(&mut body)?;
+
+    Ok(match (method, path) {
+        ("POST", "/transactions") => submit_transactions(accounts, &body),
+        ("GET", "/accounts") => Response::json(&accounts.snapshot_accounts_sorted()),
+        (_, "/transactions" | "/accounts") => {
+            Response::error("405 Method Not Allowed", "method not allowed")
+        }
+        _ => Response::error("404 Not Found", "not found"),
+    })
+}
+
+fn main() -> io::Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let listener = TcpListener::bind(addr)?;
+    // the address is reported as bound, so that port 0 can be used to pick a free port
+    println!("listening on {}", listener.local_addr()?);
+    io::stdout().flush()?;
+
+    let accounts = Arc::new(SyncAccountsHandler::default());
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let accounts = Arc::clone(&accounts);
+        thread::spawn(move || {
+            let result = handle(&stream, &accounts).and_then(|response| response.write_to(&stream));
+            if let Err(e) = result {
+                eprintln!("failed to serve connection: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+//! # Binary Reader
+//!
+//! `binary_reader` is a module providing the reading of transactions from a compact binary stream,
+//! avoiding the cost of parsing CSV.
+//!
+//! The stream is a sequence of frames, each made of the length in bytes of its payload as a
+//! little-endian `u32`, followed by the payload, a [`BinaryTransaction`] encoded with `bincode`'s
+//! default options: integers in fixed-width little-endian, the type as a `u32` variant index in
+//! declaration order and the amount as an optional decimal string.
+
+use std::io::{self, Read, Write};
+
+use csv::Position;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::{RawAmount, RawTransaction, RawTransactionType, Transaction, ValidationConfig};
+
+/// Maximum length of the payload of a frame, guarding against allocating for a corrupt length
+pub const MAX_FRAME_LEN: u32 = 1024;
+
+/// Transaction encoded in the payload of a frame of a binary stream
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BinaryTransaction {
+    pub tx_type: RawTransactionType,
+    pub client: u16,
+    pub txid: u32,
+    #[serde(with = "rust_decimal::serde::str_option")]
+    pub amount: Option<Decimal>,
+}
+
+impl From<BinaryTransaction> for RawTransaction {
+    fn from(tx: BinaryTransaction) -> Self {
+        Self {
+            tx_type: tx.tx_type,
+            client: tx.client,
+            txid: u64::from(tx.txid),
+            amount: tx.amount.map(|value| RawAmount {
+                value,
+                currency: None,
+            }),
+            timestamp: None,
+        }
+    }
+}
+
+/// Write `tx` to `output` as a frame of a binary stream
+pub fn write_binary_transaction(mut output: impl Write, tx: &BinaryTransaction) -> io::Result<()> {
+    let payload =
+        bincode::serialize(tx).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|&len| len <= MAX_FRAME_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame too long"))?;
+    output.write_all(&len.to_le_bytes())?;
+    output.write_all(&payload)
+}
+
+/// Read every frame of binary `input`, passing its position along with either the transaction it
+/// converts into, validated against `config`, or the reason it cannot be converted to `f`
+///
+/// The position's line and record are the 1-based and 0-based indices of the frame. A frame whose
+/// payload cannot be decoded is passed on as an error, while a truncated stream or a frame longer
+/// than [`MAX_FRAME_LEN`] fails the read, as the following frames cannot be located.
+pub fn read_binary_transactions(
+    mut input: impl Read,
+    config: &ValidationConfig,
+    mut f: impl FnMut(Option<&Position>, Result<Transaction, String>),
+) -> io::Result<()> {
+    let mut position = Position::new();
+    let mut payload = Vec::new();
+    loop {
+        let mut len = [0; 4];
+        if !read_frame_len(&mut input, &mut len)? {
+            return Ok(());
+        }
+        let len = u32::from_le_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame of {} bytes exceeds {} bytes", len, MAX_FRAME_LEN),
+            ));
+        }
+        payload.resize(len as usize, 0);
+        input.read_exact(&mut payload)?;
+
+        position.set_line(position.record() + 1);
+        let transaction = bincode::deserialize::<BinaryTransaction>(&payload)
+            .map_err(|e| e.to_string())
+            .and_then(|tx| {
+                RawTransaction::from(tx)
+                    .into_transaction(config)
+                    .map_err(|e| e.to_string())
+            });
+        f(Some(&position), transaction);
+
+        position.set_byte(position.byte() + 4 + u64::from(len));
+        position.set_record(position.record() + 1);
+    }
+}
+
+/// Read the length prefix of a frame into `len`, returning whether there was one, i.e. the stream
+/// did not end cleanly before it
+fn read_frame_len(input: &mut impl Read, len: &mut [u8; 4]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < len.len() {
+        match input.read(&mut len[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{ClientId, TransactionType, Txid};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn round_trip_works() {
+        let mut stream = Vec::new();
+        for (tx_type, txid, amount) in [
+            (RawTransactionType::Deposit, 1, Some(dec!(1.5))),
+            (RawTransactionType::Dispute, 1, None),
+        ] {
+            let tx = BinaryTransaction {
+                tx_type,
+                client: 1,
+                txid,
+                amount,
+            };
+            write_binary_transaction(&mut stream, &tx).unwrap();
+        }
+
+        let mut read = Vec::new();
+        read_binary_transactions(&stream[..], &ValidationConfig::default(), |position, tx| {
+            read.push((position.unwrap().line(), tx))
+        })
+        .unwrap();
+        let tx = |tx_type| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        assert_eq!(
+            read,
+            vec![
+                (1, Ok(tx(TransactionType::Deposit(amount(dec!(1.5)))))),
+                (2, Ok(tx(TransactionType::Dispute))),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_stream_fails() {
+        let mut stream = Vec::new();
+        let tx = BinaryTransaction {
+            tx_type: RawTransactionType::Deposit,
+            client: 1,
+            txid: 1,
+            amount: Some(dec!(1)),
+        };
+        write_binary_transaction(&mut stream, &tx).unwrap();
+        stream.pop();
+        let result = read_binary_transactions(&stream[..], &ValidationConfig::default(), |_, _| {});
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
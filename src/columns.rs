@@ -0,0 +1,178 @@
+//! # Columns
+//!
+//! `columns` is a module providing the export of account snapshots as an Apache Arrow record
+//! batch, e.g. for loading into analytics systems, built with the `arrow` feature.
+//!
+//! Balances are mapped to Arrow's `Decimal128` type of precision [`DECIMAL128_PRECISION`] and
+//! the scale of [`AccountsHandler::output_scale`], each stored as the `i128` mantissa of the
+//! balance at that scale. At the default scale of 4, any balance fits.
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{ArrayRef, BooleanArray, Decimal128Array, UInt16Array},
+    datatypes::{DataType, Field, Schema},
+    record_batch::RecordBatch,
+};
+use rust_decimal::Decimal;
+
+use crate::{AccountSnapshot, AccountsHandler};
+
+/// Precision of the `Decimal128` balance columns, the largest Arrow allows
+pub const DECIMAL128_PRECISION: u8 = 38;
+
+impl AccountsHandler {
+    /// Export snapshots of all accounts in ascending order of client id as a record batch with
+    /// the columns `client`, `available`, `held`, `total` and `locked`
+    ///
+    /// Balances are `Decimal128` columns rounded to [`AccountsHandler::output_scale`] decimal
+    /// places, as the snapshots are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a balance has more than [`DECIMAL128_PRECISION`] digits at the output scale,
+    /// which only balances near `Decimal::MAX` at more than 9 decimal places can have.
+    pub fn to_record_batch(&self) -> RecordBatch {
+        let scale = self.output_scale();
+        let snapshots: Vec<AccountSnapshot> = self.snapshots_sorted_iter().collect();
+        let balances = |balance: fn(&AccountSnapshot) -> Decimal| -> ArrayRef {
+            let array = Decimal128Array::from_iter_values(
+                snapshots.iter().map(|s| mantissa(balance(s), scale)),
+            )
+            .with_precision_and_scale(DECIMAL128_PRECISION, arrow_scale(scale))
+            .expect("the output scale is within the precision");
+            Arc::new(array)
+        };
+        let decimal = DataType::Decimal128(DECIMAL128_PRECISION, arrow_scale(scale));
+        let schema = Schema::new(vec![
+            Field::new("client", DataType::UInt16, false),
+            Field::new("available", decimal.clone(), false),
+            Field::new("held", decimal.clone(), false),
+            Field::new("total", decimal, false),
+            Field::new("locked", DataType::Boolean, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt16Array::from_iter_values(
+                snapshots.iter().map(|s| s.id.0),
+            )),
+            balances(|s| s.available),
+            balances(|s| s.held),
+            balances(|s| s.total),
+            Arc::new(BooleanArray::from_iter(
+                snapshots.iter().map(|s| Some(s.locked)),
+            )),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .expect("the columns match the schema and have one row per account")
+    }
+}
+
+/// Convert a scale of at most `Decimal::MAX_SCALE` into an Arrow scale
+fn arrow_scale(scale: u32) -> i8 {
+    i8::try_from(scale).expect("the output scale is at most Decimal::MAX_SCALE")
+}
+
+/// Get the mantissa of `value` at `scale` decimal places, truncating any further places
+///
+/// A decimal too large to be rescaled keeps a lower scale, so its mantissa is scaled up in `i128`.
+///
+/// # Panics
+///
+/// Panics if the mantissa has more than [`DECIMAL128_PRECISION`] digits.
+fn mantissa(value: Decimal, scale: u32) -> i128 {
+    let mut value = value.trunc_with_scale(scale);
+    value.rescale(scale);
+    10_i128
+        .checked_pow(scale - value.scale())
+        .and_then(|factor| value.mantissa().checked_mul(factor))
+        .filter(|mantissa| mantissa.unsigned_abs() < 10_u128.pow(DECIMAL128_PRECISION.into()))
+        .unwrap_or_else(|| {
+            panic!(
+                "balance {} has more than {} digits at {} decimal places",
+                value, DECIMAL128_PRECISION, scale
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{ClientId, HandlerConfig, Transaction, TransactionType, Txid};
+    use arrow::array::Array;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn to_record_batch_works() {
+        let mut accounts = AccountsHandler::new();
+        for (tx_type, client_id, txid) in [
+            (TransactionType::Deposit(amount(dec!(1.5))), 2, 1),
+            (TransactionType::Deposit(amount(dec!(0.25))), 1, 2),
+            (TransactionType::Dispute, 2, 1),
+        ] {
+            accounts
+                .submit_transaction(Transaction {
+                    tx_type,
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap();
+        }
+        let batch = accounts.to_record_batch();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 5);
+        assert!(batch.columns().iter().all(|column| column.len() == 2));
+        assert_eq!(
+            batch.schema().field_with_name("held").unwrap().data_type(),
+            &DataType::Decimal128(38, 4)
+        );
+
+        let client = batch.column_by_name("client").unwrap();
+        let client = client.as_any().downcast_ref::<UInt16Array>().unwrap();
+        assert_eq!(client.values(), &[1, 2]);
+        let held = batch.column_by_name("held").unwrap();
+        let held = held.as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(held.value(1), 15000);
+        assert_eq!(held.value_as_string(1), "1.5000");
+        let locked = batch.column_by_name("locked").unwrap();
+        let locked = locked.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(!locked.value(0));
+    }
+
+    #[test]
+    fn to_record_batch_follows_output_scale() {
+        let mut accounts = AccountsHandler::with_config(HandlerConfig {
+            max_scale: 8,
+            ..HandlerConfig::default()
+        });
+        accounts
+            .submit_transaction(Transaction {
+                tx_type: TransactionType::Deposit(amount(dec!(0.00000001))),
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            })
+            .unwrap();
+        let batch = accounts.to_record_batch();
+        let total = batch.column_by_name("total").unwrap();
+        let total = total.as_any().downcast_ref::<Decimal128Array>().unwrap();
+        assert_eq!(total.scale(), 8);
+        assert_eq!(total.value(0), 1);
+    }
+
+    #[test]
+    fn mantissa_of_large_decimal_is_scaled() {
+        assert_eq!(
+            mantissa(Decimal::MAX, 4),
+            792_281_625_142_643_375_935_439_503_350_000
+        );
+        assert_eq!(mantissa(dec!(-1.23456), 4), -12345);
+    }
+
+    #[test]
+    #[should_panic(expected = "has more than 38 digits")]
+    fn mantissa_beyond_precision_panics() {
+        mantissa(Decimal::MAX, 12);
+    }
+}
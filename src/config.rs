@@ -0,0 +1,236 @@
+//! # Config
+//!
+//! `config` is a module providing the policy for handling transactions across accounts.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Scope within which txids of deposits, withdrawals and transfers must be unique
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum UniquenessScope {
+    /// Txids are unique across all clients
+    #[default]
+    Global,
+    /// Txids are unique per client, so different clients may reuse the same txid
+    PerClient,
+}
+
+/// Policy for disputing a deposit whose amount exceeds the available balance, e.g. because the
+/// deposited funds were already withdrawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum DisputePolicy {
+    /// The dispute is ignored
+    #[default]
+    RequireAvailable,
+    /// The full amount is held regardless, driving the available balance negative
+    AllowNegative,
+}
+
+/// Policy for transfers with a negative amount
+///
+/// Deposits and withdrawals carry an [`Amount`](crate::Amount), which cannot be negative, so a
+/// negative deposit or withdrawal is rejected as an invalid row when it is read instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum NegativeAmountPolicy {
+    /// The transaction is ignored, consuming its txid
+    #[default]
+    Ignore,
+    /// The transaction fails with an error, leaving its txid unused
+    Reject,
+}
+
+/// Policy for transactions routed to a locked account, other than resolves and chargebacks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum LockedPolicy {
+    /// The transaction is ignored
+    #[default]
+    Ignore,
+    /// The transaction fails with an error, e.g. to catch upstream bugs
+    Reject,
+}
+
+/// Mode of withdrawals exceeding the available balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum WithdrawalMode {
+    /// The withdrawal is ignored for insufficient funds
+    #[default]
+    Strict,
+    /// Whatever is available is withdrawn instead, draining the account to zero, or to the minimum
+    /// available balance if positive
+    Saturating,
+}
+
+/// Policy for a deposit reusing the txid of an earlier deposit of the same client
+///
+/// An amended deposit is disputed for its amended amount. A deposit under dispute, charged back or
+/// reversed cannot be amended, so the repeated deposit is ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum RepeatDepositPolicy {
+    /// The deposit is rejected as a duplicate
+    #[default]
+    Reject,
+    /// The deposit replaces the earlier one, adjusting the available balance by the difference
+    Overwrite,
+    /// The deposit is added to the earlier one
+    Sum,
+}
+
+/// Strategy for rounding balances to the scale of a [`ScalePolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum RoundingStrategy {
+    /// Midpoints are rounded to the nearest even digit
+    #[default]
+    BankersRounding,
+    /// Midpoints are rounded away from zero
+    MidpointAwayFromZero,
+}
+
+impl From<RoundingStrategy> for rust_decimal::RoundingStrategy {
+    fn from(strategy: RoundingStrategy) -> Self {
+        match strategy {
+            RoundingStrategy::BankersRounding => Self::MidpointNearestEven,
+            RoundingStrategy::MidpointAwayFromZero => Self::MidpointAwayFromZero,
+        }
+    }
+}
+
+/// Policy for rescaling balances to a fixed number of decimal places after each applied
+/// transaction, bounding the scale of the balances
+///
+/// Rounding loses up to half a unit in the last place per applied transaction, e.g. depositing
+/// `0.00005` three times into an account at scale 4 with banker's rounding leaves it at `0.0000`.
+/// Amounts held by disputes are rounded too, so that the held balance stays the exact sum of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScalePolicy {
+    /// Number of decimal places balances are rescaled to
+    pub scale: u32,
+    /// Strategy for rounding to the scale
+    pub rounding: RoundingStrategy,
+}
+
+impl Default for ScalePolicy {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            rounding: RoundingStrategy::default(),
+        }
+    }
+}
+
+impl ScalePolicy {
+    /// Round `value` to exactly the policy's scale
+    pub fn apply(&self, value: Decimal) -> Decimal {
+        let mut value = value.round_dp_with_strategy(self.scale, self.rounding.into());
+        value.rescale(self.scale);
+        value
+    }
+}
+
+/// Policy for handling transactions across accounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HandlerConfig {
+    /// Scope of txid uniqueness
+    pub uniqueness: UniquenessScope,
+    /// Whether accounts record the history of applied transactions
+    pub history: bool,
+    /// Whether the handler logs every applied transaction in order, for
+    /// [`crate::AccountsHandler::export_events`]
+    pub event_log: bool,
+    /// Policy for disputes exceeding the available balance
+    pub dispute_policy: DisputePolicy,
+    /// Policy for transfers with a negative amount
+    pub negative_amount_policy: NegativeAmountPolicy,
+    /// Policy for transactions routed to a locked account
+    pub locked_policy: LockedPolicy,
+    /// Maximum number of decimal places accepted in amounts of deposits, withdrawals and
+    /// transfers
+    pub max_scale: u32,
+    /// Policy for rescaling balances after each applied transaction, or `None` to keep them at
+    /// full precision
+    pub scale_policy: Option<ScalePolicy>,
+    /// Fee debited from the available balance on top of the amount of each withdrawal
+    pub withdrawal_fee: Decimal,
+    /// Maximum number of deposit and withdrawal records retained per account for future disputes,
+    /// or `None` to retain every record
+    ///
+    /// Once exceeded, the oldest undisputed record is evicted, after which that transaction can no
+    /// longer be disputed. Records under dispute are never evicted, so an account may exceed the
+    /// bound while all of its records are disputed.
+    pub max_records_per_account: Option<usize>,
+    /// Whether disputes, resolves and chargebacks referencing a txid not seen yet are buffered and
+    /// replayed once the transaction arrives, rather than ignored
+    ///
+    /// Buffered transactions are held in memory until their transaction arrives or
+    /// [`crate::AccountsHandler::flush_pending`] is called at the end of input, so a feed with many
+    /// dangling references grows the buffer without bound.
+    pub buffer_disputes: bool,
+    /// Maximum available balance a deposit or transfer may credit an account up to, or `None` if
+    /// uncapped
+    ///
+    /// Disputes, resolves and chargebacks only move funds already on the account and are not
+    /// subject to the cap.
+    pub max_available: Option<Decimal>,
+    /// Minimum available balance a withdrawal or transfer may debit an account down to
+    ///
+    /// A debit that would leave less fails with an error, while one exceeding the available
+    /// balance is still ignored for insufficient funds, so a floor of zero or below has no effect.
+    /// Disputes are not subject to the floor, as holding disputed funds is not the client's doing.
+    pub min_available: Decimal,
+    /// Whether resubmitting a deposit, withdrawal or transfer identical to an earlier one with the
+    /// same txid is a no-op rather than a duplicate txid error, e.g. when re-running overlapping
+    /// files
+    ///
+    /// Every accepted transaction is retained in memory to compare resubmissions against, and is
+    /// not included in the dumped state.
+    pub idempotent_resubmits: bool,
+    /// Maximum number of transactions applied per client in a single run, or `None` if unlimited
+    ///
+    /// Once a client reaches the cap, its further transactions are rejected with an error. The
+    /// count covers applied transactions only, in which a transfer counts against its sender, and
+    /// is neither persisted in the dumped state nor carried over by a reset.
+    pub max_transactions_per_client: Option<usize>,
+    /// Number of submitted transactions after which consumed txids may be forgotten, or `None` to
+    /// remember every txid
+    ///
+    /// Txids are kept in two generations, the older of which is dropped at the end of every
+    /// window, so a txid is remembered for at least one and at most two windows. This bounds the
+    /// memory of long-running ingestions, at the cost of no longer rejecting a txid reused after
+    /// it was forgotten, so txids are then only unique within the window rather than globally. A
+    /// deposit or withdrawal reusing a forgotten txid still recorded on its account, e.g. an
+    /// earlier deposit that can still be disputed, fails with
+    /// [`crate::TransactionError::DuplicateTxid`] instead of replacing the record.
+    pub txid_window: Option<u64>,
+    /// Mode of withdrawals exceeding the available balance
+    ///
+    /// A withdrawal filled only in part under [`WithdrawalMode::Saturating`] is reported as
+    /// [`crate::ProcessOutcome::PartiallyApplied`] with the amount withdrawn, which is also the
+    /// amount a later dispute of it holds. Transfers are not affected.
+    pub withdrawal_mode: WithdrawalMode,
+    /// Policy for a deposit reusing the txid of an earlier deposit of the same client
+    pub repeat_deposit_policy: RepeatDepositPolicy,
+}
+
+impl Default for HandlerConfig {
+    fn default() -> Self {
+        Self {
+            uniqueness: UniquenessScope::default(),
+            history: false,
+            event_log: false,
+            dispute_policy: DisputePolicy::default(),
+            negative_amount_policy: NegativeAmountPolicy::default(),
+            locked_policy: LockedPolicy::default(),
+            max_scale: 4,
+            scale_policy: None,
+            withdrawal_fee: Decimal::ZERO,
+            max_records_per_account: None,
+            buffer_disputes: false,
+            max_available: None,
+            min_available: Decimal::ZERO,
+            idempotent_resubmits: false,
+            max_transactions_per_client: None,
+            txid_window: None,
+            withdrawal_mode: WithdrawalMode::default(),
+            repeat_deposit_policy: RepeatDepositPolicy::default(),
+        }
+    }
+}
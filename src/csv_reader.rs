@@ -0,0 +1,162 @@
+//! # CSV Reader
+//!
+//! `csv_reader` is a module providing the reading of transactions from CSV input.
+
+use std::io;
+
+use csv::{Position, ReaderBuilder, StringRecord};
+
+use crate::{RawTransaction, RawTransactionType, Transaction, ValidationConfig};
+
+/// Layout of CSV input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Whether the first row is a header naming the columns, rather than the columns being in the
+    /// fixed order `type`, `client`, `tx`, `amount`
+    pub has_headers: bool,
+    /// Byte starting comment lines, which are skipped, if any
+    pub comment: Option<u8>,
+    /// Byte separating the fields of a row, e.g. `b'\t'` for TSV
+    pub delimiter: u8,
+    /// Whether commas are stripped from amounts as thousands separators, e.g. `"1,000.50"`, which
+    /// must be quoted if commas also separate the fields
+    pub strip_separators: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            has_headers: true,
+            comment: None,
+            delimiter: b',',
+            strip_separators: false,
+        }
+    }
+}
+
+/// Read every row of CSV `input`, passing its position along with either the transaction it
+/// converts into, validated against `config`, or the reason it cannot be converted to `f`
+///
+/// Whitespace around headers and fields is stripped, rows may omit trailing fields, and blank rows
+/// are skipped. With a header, columns it names that are not part of a transaction, e.g. `notes`,
+/// are ignored, and rows repeating the header, e.g. where files were concatenated, are skipped.
+pub fn read_csv_transactions(
+    input: impl io::Read,
+    config: &ValidationConfig,
+    options: CsvOptions,
+    mut f: impl FnMut(Option<&Position>, Result<Transaction, String>),
+) -> csv::Result<()> {
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .has_headers(options.has_headers)
+        .comment(options.comment)
+        .delimiter(options.delimiter)
+        .from_reader(input);
+
+    // without a header, columns are deserialized by position
+    let headers = if options.has_headers {
+        Some(reader.headers()?.clone())
+    } else {
+        None
+    };
+    for record in reader.records() {
+        let mut record = record?;
+        // skip rows of nothing but whitespace
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        // skip repetitions of the header
+        if headers
+            .as_ref()
+            .is_some_and(|headers| record.iter().eq(headers.iter()))
+        {
+            continue;
+        }
+        if !config.strict {
+            strip_stray_amount(&mut record, headers.as_ref());
+        }
+        if options.strip_separators {
+            strip_amount_separators(&mut record, headers.as_ref());
+        }
+        // reject rows that cannot be parsed
+        let transaction: RawTransaction = match record.deserialize(headers.as_ref()) {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                let reason = match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => e.to_string(),
+                };
+                f(record.position(), Err(reason));
+                continue;
+            }
+        };
+        // reject validation errors, including precision and missing amount
+        f(
+            record.position(),
+            transaction
+                .into_transaction(config)
+                .map_err(|e| e.to_string()),
+        );
+    }
+
+    Ok(())
+}
+
+/// Blank the amount of a dispute, resolve or chargeback row, so that a stray amount is ignored even
+/// if malformed
+fn strip_stray_amount(record: &mut StringRecord, headers: Option<&StringRecord>) {
+    let (Some(type_column), Some(amount_column)) =
+        (column(headers, "type", 0), column(headers, "amount", 3))
+    else {
+        return;
+    };
+    if !matches!(
+        record
+            .get(type_column)
+            .and_then(RawTransactionType::from_keyword),
+        Some(
+            RawTransactionType::Dispute
+                | RawTransactionType::Resolve
+                | RawTransactionType::Chargeback
+        )
+    ) || record.get(amount_column).is_none_or(str::is_empty)
+    {
+        return;
+    }
+    replace_field(record, amount_column, String::new());
+}
+
+/// Strip the commas from the amount of a row, so that thousands separators do not fail parsing
+fn strip_amount_separators(record: &mut StringRecord, headers: Option<&StringRecord>) {
+    let Some(amount_column) = column(headers, "amount", 3) else {
+        return;
+    };
+    let Some(amount) = record
+        .get(amount_column)
+        .filter(|amount| amount.contains(','))
+    else {
+        return;
+    };
+    let stripped = amount.replace(',', "");
+    replace_field(record, amount_column, stripped);
+}
+
+/// Find the column named `name`, or at the fixed `position` without a header
+fn column(headers: Option<&StringRecord>, name: &str, position: usize) -> Option<usize> {
+    match headers {
+        Some(headers) => headers.iter().position(|header| header == name),
+        None => Some(position),
+    }
+}
+
+/// Replace the field of a row in `column` with `value`, keeping the row's position
+fn replace_field(record: &mut StringRecord, column: usize, value: String) {
+    let mut replaced: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(i, field)| if i == column { value.as_str() } else { field })
+        .collect();
+    replaced.set_position(record.position().cloned());
+    *record = replaced;
+}
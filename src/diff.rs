@@ -0,0 +1,167 @@
+//! # Diff
+//!
+//! `diff` is a module providing the comparison of account snapshots taken before and after a batch
+//! of transactions.
+
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+
+use crate::{AccountSnapshot, ClientId};
+
+/// How a client's account differs between two sets of snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeltaKind {
+    /// Account is only in the later snapshots
+    Appeared,
+    /// Account is only in the earlier snapshots
+    Disappeared,
+    /// Account is in both, with different balances or locked status
+    Changed,
+}
+
+/// Change of a client's account between two sets of snapshots
+///
+/// Balances of an account missing from either set count as zero, so the deltas of an account that
+/// appeared are its balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnapshotDelta {
+    /// Client's ID
+    pub id: ClientId,
+    /// How the account differs
+    pub kind: DeltaKind,
+    /// Change of the available balance
+    pub available: Decimal,
+    /// Change of the held balance
+    pub held: Decimal,
+    /// Change of the total balance
+    pub total: Decimal,
+    /// New locked status, if it changed
+    pub locked: Option<bool>,
+}
+
+/// Compare the snapshots `before` and `after`, reporting the change of every account that differs
+/// in order of client ID
+///
+/// Accounts whose balances and locked status are equal in both are left out, even if they differ
+/// in the number of transactions under dispute.
+pub fn diff_snapshots(before: &[AccountSnapshot], after: &[AccountSnapshot]) -> Vec<SnapshotDelta> {
+    let mut pairs: BTreeMap<ClientId, (Option<&AccountSnapshot>, Option<&AccountSnapshot>)> =
+        BTreeMap::new();
+    for snapshot in before {
+        pairs.entry(snapshot.id).or_default().0 = Some(snapshot);
+    }
+    for snapshot in after {
+        pairs.entry(snapshot.id).or_default().1 = Some(snapshot);
+    }
+
+    pairs
+        .into_iter()
+        .filter_map(|(id, pair)| {
+            let kind = match pair {
+                (None, Some(_)) => DeltaKind::Appeared,
+                (Some(_), None) => DeltaKind::Disappeared,
+                _ => DeltaKind::Changed,
+            };
+            let field = |f: fn(&AccountSnapshot) -> Decimal| {
+                pair.1.map_or(Decimal::ZERO, f) - pair.0.map_or(Decimal::ZERO, f)
+            };
+            let delta = SnapshotDelta {
+                id,
+                kind,
+                available: field(|snapshot| snapshot.available),
+                held: field(|snapshot| snapshot.held),
+                total: field(|snapshot| snapshot.total),
+                locked: match pair {
+                    (Some(before), Some(after)) if before.locked == after.locked => None,
+                    (_, after) => Some(after.is_some_and(|after| after.locked)),
+                },
+            };
+            let unchanged = kind == DeltaKind::Changed
+                && delta.available.is_zero()
+                && delta.held.is_zero()
+                && delta.total.is_zero()
+                && delta.locked.is_none();
+            (!unchanged).then_some(delta)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{AccountsHandler, Transaction, TransactionType, Txid};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn diff_snapshots_works() {
+        let mut accounts = AccountsHandler::new();
+        let submit = |accounts: &mut AccountsHandler, tx_type, client_id, txid| {
+            accounts
+                .submit_transaction(Transaction {
+                    tx_type,
+                    client_id: ClientId(client_id),
+                    txid: Txid(txid),
+                    timestamp: None,
+                })
+                .unwrap()
+        };
+        submit(
+            &mut accounts,
+            TransactionType::Deposit(amount(dec!(10))),
+            1,
+            1,
+        );
+        submit(
+            &mut accounts,
+            TransactionType::Deposit(amount(dec!(5))),
+            2,
+            2,
+        );
+        let before = accounts.snapshot_accounts();
+
+        submit(&mut accounts, TransactionType::Dispute, 1, 1);
+        submit(&mut accounts, TransactionType::Chargeback, 1, 1);
+        submit(
+            &mut accounts,
+            TransactionType::Deposit(amount(dec!(3))),
+            3,
+            3,
+        );
+        let after = accounts.snapshot_accounts();
+
+        assert_eq!(
+            diff_snapshots(&before, &after),
+            vec![
+                SnapshotDelta {
+                    id: ClientId(1),
+                    kind: DeltaKind::Changed,
+                    available: dec!(-10),
+                    held: dec!(0),
+                    total: dec!(-10),
+                    locked: Some(true),
+                },
+                SnapshotDelta {
+                    id: ClientId(3),
+                    kind: DeltaKind::Appeared,
+                    available: dec!(3),
+                    held: dec!(0),
+                    total: dec!(3),
+                    locked: Some(false),
+                },
+            ]
+        );
+        assert_eq!(
+            diff_snapshots(&after, &before)[1],
+            SnapshotDelta {
+                id: ClientId(3),
+                kind: DeltaKind::Disappeared,
+                available: dec!(-3),
+                held: dec!(0),
+                total: dec!(-3),
+                locked: Some(false),
+            }
+        );
+    }
+}
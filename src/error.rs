@@ -0,0 +1,300 @@
+//! # Error
+//!
+//! `error` is a module providing the error types returned when validating and submitting
+//! transactions.
+
+use rust_decimal::Decimal;
+use std::fmt;
+
+use crate::{ClientId, Currency, IgnoreReason, Txid};
+
+/// Error returned when a transaction could not be submitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransactionError {
+    /// Transaction id has already been used by an earlier transaction
+    DuplicateTxid(Txid),
+    /// Withdrawal or transfer would debit the available balance below the configured minimum
+    MinimumBalanceBreach { client_id: ClientId },
+    /// Account is locked and cannot take the transaction
+    AccountLocked(ClientId),
+    /// Transaction was routed to an account belonging to a different client
+    ClientMismatch { expected: ClientId, got: ClientId },
+    /// No account exists for the client
+    UnknownClient(ClientId),
+    /// Transfer whose sender and recipient are the same client
+    SelfTransfer(ClientId),
+    /// Transaction would overflow a balance of the client's account
+    BalanceOverflow { client_id: ClientId },
+    /// Transaction would round a balance of the client's account, losing significant digits, as
+    /// the exact balance is not representable
+    PrecisionLoss { client_id: ClientId },
+    /// Deposit or transfer would credit the available balance above the configured cap
+    BalanceCapExceeded { client_id: ClientId },
+    /// Account cannot be closed as it still has available or held funds
+    NonZeroBalance(ClientId),
+    /// Transfer has a negative amount
+    NegativeAmount { txid: Txid },
+    /// Dispute, resolve or chargeback references a transaction of another client
+    TxidClientMismatch {
+        txid: Txid,
+        owner: ClientId,
+        got: ClientId,
+    },
+    /// Dispute, resolve or chargeback references a transaction in another currency
+    CurrencyMismatch {
+        txid: Txid,
+        owner: Currency,
+        got: Currency,
+    },
+    /// Account cannot be unfrozen as it is locked by a chargeback
+    ChargebackLocked(ClientId),
+    /// Account cannot be seeded as the client already has one
+    AccountExists(ClientId),
+    /// Account cannot be seeded with a negative balance
+    NegativeSeed(ClientId),
+    /// Account cannot be seeded from a snapshot whose total is not the sum of its balances
+    InconsistentTotal(ClientId),
+    /// Deposit cannot be reversed, for the same reason a transaction would be ignored
+    ReversalRejected { txid: Txid, reason: IgnoreReason },
+    /// Client already had the maximum number of transactions applied in this run
+    RateLimited { client_id: ClientId },
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TransactionError::*;
+        match self {
+            DuplicateTxid(txid) => write!(f, "duplicate txid: {}", txid),
+            AccountLocked(client_id) => write!(f, "account {} is locked", client_id),
+            ClientMismatch { expected, got } => write!(
+                f,
+                "transaction for client {} submitted to account of client {}",
+                got, expected
+            ),
+            UnknownClient(client_id) => write!(f, "unknown client: {}", client_id),
+            SelfTransfer(client_id) => {
+                write!(f, "client {} cannot transfer to itself", client_id)
+            }
+            BalanceOverflow { client_id } => {
+                write!(f, "balance overflow on account {}", client_id)
+            }
+            PrecisionLoss { client_id } => write!(
+                f,
+                "transaction would lose precision in a balance of account {}",
+                client_id
+            ),
+            BalanceCapExceeded { client_id } => write!(
+                f,
+                "transaction would exceed the maximum available balance of account {}",
+                client_id
+            ),
+            MinimumBalanceBreach { client_id } => write!(
+                f,
+                "transaction would breach the minimum available balance of account {}",
+                client_id
+            ),
+            NonZeroBalance(client_id) => {
+                write!(f, "account {} still has a non-zero balance", client_id)
+            }
+            NegativeAmount { txid } => write!(f, "negative amount in transaction {}", txid),
+            TxidClientMismatch { txid, owner, got } => write!(
+                f,
+                "transaction {} of client {} referenced by client {}",
+                txid, owner, got
+            ),
+            CurrencyMismatch { txid, owner, got } => {
+                write!(f, "transaction {} in {} referenced in {}", txid, owner, got)
+            }
+            ChargebackLocked(client_id) => {
+                write!(f, "account {} is locked by a chargeback", client_id)
+            }
+            AccountExists(client_id) => write!(f, "account {} already exists", client_id),
+            InconsistentTotal(client_id) => write!(
+                f,
+                "snapshot of account {} has a total other than available plus held",
+                client_id
+            ),
+            ReversalRejected { txid, reason } => {
+                write!(f, "deposit {} cannot be reversed: {}", txid, reason)
+            }
+            RateLimited { client_id } => write!(
+                f,
+                "client {} exceeded the maximum number of transactions",
+                client_id
+            ),
+            NegativeSeed(client_id) => {
+                write!(
+                    f,
+                    "account {} cannot be seeded with a negative balance",
+                    client_id
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// Error returned when a transaction read from external input is invalid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// Deposit or withdrawal has no amount
+    MissingAmount,
+    /// Amount has more decimal places than allowed
+    ExcessPrecision { amount: Decimal, max_scale: u32 },
+    /// Amount is suffixed with a currency although plain decimals are expected
+    UnexpectedCurrency(Currency),
+    /// Amount is not suffixed with the expected currency
+    CurrencyMismatch {
+        expected: Currency,
+        got: Option<Currency>,
+    },
+    /// Dispute, resolve or chargeback has an amount under strict validation
+    UnexpectedAmount,
+    /// Deposit, withdrawal or transfer has a negative amount
+    NegativeAmount(Decimal),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ValidationError::*;
+        match self {
+            MissingAmount => write!(f, "amount is required for deposit/withdraw transactions"),
+            ExcessPrecision { amount, max_scale } => write!(
+                f,
+                "amount {} has more than {} decimal places",
+                amount, max_scale
+            ),
+            UnexpectedCurrency(currency) => write!(f, "unexpected currency: {}", currency),
+            CurrencyMismatch {
+                expected,
+                got: Some(got),
+            } => write!(f, "currency {} does not match {}", got, expected),
+            CurrencyMismatch {
+                expected,
+                got: None,
+            } => write!(f, "amount is missing currency {}", expected),
+            UnexpectedAmount => write!(
+                f,
+                "amount is not allowed for dispute/resolve/chargeback transactions"
+            ),
+            NegativeAmount(amount) => write!(f, "amount {} is negative", amount),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl From<AmountError> for ValidationError {
+    fn from(e: AmountError) -> Self {
+        match e {
+            AmountError::Negative(amount) => ValidationError::NegativeAmount(amount),
+            AmountError::ExcessPrecision { value, max_scale } => ValidationError::ExcessPrecision {
+                amount: value,
+                max_scale,
+            },
+        }
+    }
+}
+
+/// Error returned when a decimal is not a valid [`crate::Amount`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountError {
+    /// Amount is negative
+    Negative(Decimal),
+    /// Amount has more decimal places than allowed
+    ExcessPrecision { value: Decimal, max_scale: u32 },
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use AmountError::*;
+        match self {
+            Negative(value) => write!(f, "amount {} is negative", value),
+            ExcessPrecision { value, max_scale } => write!(
+                f,
+                "amount {} has more than {} decimal places",
+                value, max_scale
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// Error returned when an account's balances are inconsistent with its records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantError {
+    /// Total balance is negative although disputes may not drive available negative
+    NegativeTotal { client_id: ClientId, total: Decimal },
+    /// Held balance differs from the sum of the amounts currently under dispute
+    HeldMismatch {
+        client_id: ClientId,
+        held: Decimal,
+        disputed: Decimal,
+    },
+    /// Total balance, the available plus held balance, or the amount held by the open disputes is
+    /// too large to be represented
+    TotalOverflow { client_id: ClientId },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use InvariantError::*;
+        match self {
+            NegativeTotal { client_id, total } => {
+                write!(f, "account {} has negative total {}", client_id, total)
+            }
+            HeldMismatch {
+                client_id,
+                held,
+                disputed,
+            } => write!(
+                f,
+                "account {} holds {} but {} is under dispute",
+                client_id, held, disputed
+            ),
+            TotalOverflow { client_id } => {
+                write!(
+                    f,
+                    "account {} has a total too large to represent",
+                    client_id
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Error returned when a transaction type cannot be parsed from its keyword
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseTransactionTypeError {
+    /// Keyword is not a known transaction type
+    UnknownKeyword(String),
+    /// Deposit or withdrawal has no amount
+    MissingAmount,
+    /// Amount is not a decimal
+    InvalidAmount(String),
+    /// Dispute, resolve or chargeback has an amount
+    UnexpectedAmount,
+}
+
+impl fmt::Display for ParseTransactionTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ParseTransactionTypeError::*;
+        match self {
+            UnknownKeyword(keyword) => write!(f, "unknown transaction type: {}", keyword),
+            MissingAmount => write!(
+                f,
+                "amount is required for deposit/withdrawal, e.g. deposit:1.50"
+            ),
+            InvalidAmount(amount) => write!(f, "invalid amount: {}", amount),
+            UnexpectedAmount => write!(f, "amount is only allowed for deposit/withdrawal"),
+        }
+    }
+}
+
+impl std::error::Error for ParseTransactionTypeError {}
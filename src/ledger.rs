@@ -0,0 +1,112 @@
+//! # Ledger
+//!
+//! `ledger` is a module providing the public single-account API, for integrators building their own
+//! multi-account topologies on the core account logic.
+
+use rust_decimal::Decimal;
+
+use crate::{
+    Account, AccountSnapshot, AppliedEvent, ClientId, HandlerConfig, InvariantError,
+    ProcessOutcome, Transaction, TransactionError,
+};
+
+/// Ledger of a single client's account
+///
+/// Unlike [`crate::AccountsHandler`], a ledger does not check that txids are unique, which is left
+/// to the caller, and a transfer only debits the account, leaving crediting the recipient to the
+/// caller too.
+///
+/// ```
+/// use coding_test::{
+///     Amount, ClientId, Ledger, ProcessOutcome, Transaction, TransactionType, Txid,
+/// };
+/// use rust_decimal::Decimal;
+///
+/// let mut ledger = Ledger::new(ClientId(1));
+/// for (tx_type, txid) in [
+///     (TransactionType::Deposit(Amount::new(Decimal::new(10, 0)).unwrap()), 1),
+///     (TransactionType::Dispute, 1),
+/// ] {
+///     let tx = Transaction {
+///         tx_type,
+///         client_id: ClientId(1),
+///         txid: Txid(txid),
+///         timestamp: None,
+///     };
+///     assert_eq!(ledger.process(tx), Ok(ProcessOutcome::Applied));
+/// }
+/// assert_eq!(ledger.available(), Decimal::ZERO);
+/// assert_eq!(ledger.held(), Decimal::new(10, 0));
+/// assert_eq!(ledger.snapshot().disputed_count, 1);
+/// ```
+#[derive(Clone)]
+pub struct Ledger {
+    /// Underlying account
+    account: Account,
+    /// Number of transactions processed so far
+    sequence: u64,
+}
+
+impl Ledger {
+    /// Create a ledger for an empty account with the default configuration
+    pub fn new(client_id: ClientId) -> Self {
+        Self::with_config(client_id, &HandlerConfig::default())
+    }
+
+    /// Create a ledger for an empty account with the given configuration
+    ///
+    /// [`HandlerConfig::uniqueness`] has no effect, as a ledger does not check txids.
+    pub fn with_config(client_id: ClientId, config: &HandlerConfig) -> Self {
+        Self {
+            account: Account::with_config(client_id, config),
+            sequence: 0,
+        }
+    }
+
+    /// Process a transaction on the account
+    pub fn process(&mut self, tx: Transaction) -> Result<ProcessOutcome, TransactionError> {
+        self.sequence += 1;
+        self.account.process(tx, self.sequence)
+    }
+
+    /// Get the client's ID
+    pub fn client_id(&self) -> ClientId {
+        self.account.id()
+    }
+
+    /// Get the available balance
+    pub fn available(&self) -> Decimal {
+        self.account.available()
+    }
+
+    /// Get the held balance
+    pub fn held(&self) -> Decimal {
+        self.account.held()
+    }
+
+    /// Check whether the account is locked
+    pub fn is_locked(&self) -> bool {
+        self.account.is_locked()
+    }
+
+    /// Get the history of applied transactions, if enabled
+    pub fn history(&self) -> Option<&[AppliedEvent]> {
+        self.account.history()
+    }
+
+    /// Get a snapshot of the account with balances rounded to the larger of
+    /// `DEFAULT_OUTPUT_SCALE` and the maximum scale of amounts decimal places
+    pub fn snapshot(&self) -> AccountSnapshot {
+        self.account.snapshot()
+    }
+
+    /// Get a snapshot of the account with balances rounded half to even to `scale` decimal places
+    pub fn snapshot_with_scale(&self, scale: u32) -> AccountSnapshot {
+        self.account.snapshot_with_scale(scale)
+    }
+
+    /// Check that the account's balances are consistent with its records
+    pub fn validate_invariants(&self) -> Result<(), InvariantError> {
+        self.account.validate_invariants()
+    }
+}
@@ -7,12 +7,24 @@ use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 /// Client's ID wrapper type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct ClientId(pub u16);
 
+impl std::fmt::Display for ClientId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u16> for ClientId {
+    fn from(id: u16) -> Self {
+        Self(id)
+    }
+}
+
 /// Transaction's ID wrapper type
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
-pub struct Txid(pub u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct Txid(pub u64);
 
 impl std::fmt::Display for Txid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -20,37 +32,485 @@ impl std::fmt::Display for Txid {
     }
 }
 
+impl From<u32> for Txid {
+    fn from(id: u32) -> Self {
+        Self(u64::from(id))
+    }
+}
+
+impl From<u64> for Txid {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+/// Amount of a deposit or withdrawal, guaranteed to be non-negative and within a maximum scale
+///
+/// An amount serializes as its decimal, and deserializing one only checks that it is
+/// non-negative, as the maximum scale it was validated against is not known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(try_from = "Decimal", into = "Decimal")]
+pub struct Amount(Decimal);
+
+impl Amount {
+    /// Validate an amount, which must be non-negative and have at most 4 decimal places
+    pub fn new(value: Decimal) -> Result<Self, AmountError> {
+        Self::with_max_scale(value, 4)
+    }
+
+    /// Validate an amount, which must be non-negative and have at most `max_scale` decimal places
+    pub fn with_max_scale(value: Decimal, max_scale: u32) -> Result<Self, AmountError> {
+        if value.is_sign_negative() && !value.is_zero() {
+            return Err(AmountError::Negative(value));
+        }
+        if value.scale() > max_scale {
+            return Err(AmountError::ExcessPrecision { value, max_scale });
+        }
+        Ok(Self(value))
+    }
+
+    /// Get the validated value
+    pub fn value(self) -> Decimal {
+        self.0
+    }
+}
+
+impl From<Amount> for Decimal {
+    fn from(amount: Amount) -> Self {
+        amount.0
+    }
+}
+
+/// Validate an amount, which must be non-negative, of any scale a decimal can have
+impl TryFrom<Decimal> for Amount {
+    type Error = AmountError;
+
+    fn try_from(value: Decimal) -> Result<Self, Self::Error> {
+        Self::with_max_scale(value, Decimal::MAX_SCALE)
+    }
+}
+
+impl std::fmt::Display for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 /// Transaction type
-#[derive(Debug, Clone, Copy)]
+///
+/// New types may be added, so matches outside of this crate need a catch-all arm:
+///
+/// ```
+/// use coding_test::TransactionType;
+///
+/// fn describe(tx_type: TransactionType) -> &'static str {
+///     match tx_type {
+///         TransactionType::Deposit(_) => "credit",
+///         TransactionType::Withdrawal(_) => "debit",
+///         _ => "other",
+///     }
+/// }
+/// assert_eq!(describe(TransactionType::Dispute), "other");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[non_exhaustive]
 pub enum TransactionType {
-    Deposit(Decimal),
-    Withdrawal(Decimal),
+    Deposit(Amount),
+    Withdrawal(Amount),
     Dispute,
+    /// Dispute of only the given portion of the referenced transaction's amount
+    PartialDispute(Decimal),
     Resolve,
+    /// Resolve of only the given portion of the amount held by the referenced transaction's dispute
+    PartialResolve(Decimal),
     Chargeback,
+    /// Transfer of funds from the transaction's client to another client
+    Transfer {
+        to: ClientId,
+        amount: Decimal,
+    },
+}
+
+/// Parse a transaction type from its keyword, case-insensitively
+///
+/// Deposits, withdrawals, partial disputes and partial resolves carry their amount after a colon,
+/// e.g. `deposit:1.50` or `partial_dispute:0.75`, while disputes, resolves and chargebacks are bare
+/// keywords, e.g. `dispute`. Transfers cannot be parsed, as they
+/// also need a recipient.
+impl std::str::FromStr for TransactionType {
+    type Err = ParseTransactionTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (keyword, amount) = match s.split_once(':') {
+            Some((keyword, amount)) => (keyword, Some(amount)),
+            None => (s, None),
+        };
+        let parse_amount = || {
+            let amount = amount.ok_or(ParseTransactionTypeError::MissingAmount)?;
+            amount
+                .parse()
+                .map_err(|_| ParseTransactionTypeError::InvalidAmount(amount.to_string()))
+        };
+        // deposits and withdrawals cannot carry a negative amount
+        let parse_valid_amount = || {
+            let value: Decimal = parse_amount()?;
+            Amount::try_from(value)
+                .map_err(|_| ParseTransactionTypeError::InvalidAmount(value.to_string()))
+        };
+        let tx_type = match keyword.to_ascii_lowercase().as_str() {
+            "deposit" => return Ok(Self::Deposit(parse_valid_amount()?)),
+            "withdrawal" => return Ok(Self::Withdrawal(parse_valid_amount()?)),
+            "dispute" => Self::Dispute,
+            "partial_dispute" => return Ok(Self::PartialDispute(parse_amount()?)),
+            "resolve" => Self::Resolve,
+            "partial_resolve" => return Ok(Self::PartialResolve(parse_amount()?)),
+            "chargeback" => Self::Chargeback,
+            _ => {
+                return Err(ParseTransactionTypeError::UnknownKeyword(
+                    keyword.to_string(),
+                ))
+            }
+        };
+        match amount {
+            Some(_) => Err(ParseTransactionTypeError::UnexpectedAmount),
+            None => Ok(tx_type),
+        }
+    }
 }
 
 /// Transaction on an account
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Transaction {
     pub tx_type: TransactionType,
     pub client_id: ClientId,
     pub txid: Txid,
+    /// Time the transaction happened at as a Unix timestamp in seconds, if known
+    pub timestamp: Option<u64>,
+}
+
+impl TransactionType {
+    /// Get the amount moved by a deposit, withdrawal or transfer, or zero for any other type
+    ///
+    /// The portion carried by a partial dispute or resolve is not a monetary amount of its own, so
+    /// it is zero too.
+    pub fn amount(&self) -> Decimal {
+        match *self {
+            TransactionType::Deposit(amount) | TransactionType::Withdrawal(amount) => {
+                amount.value()
+            }
+            TransactionType::Transfer { amount, .. } => amount,
+            TransactionType::Dispute
+            | TransactionType::PartialDispute(_)
+            | TransactionType::Resolve
+            | TransactionType::PartialResolve(_)
+            | TransactionType::Chargeback => Decimal::ZERO,
+        }
+    }
 }
 
 impl Transaction {
-    fn is_generative_tx(&self) -> bool {
-        match self.tx_type {
-            TransactionType::Deposit(_) | TransactionType::Withdrawal(_) => true,
-            _ => false,
+    /// Get the amount moved by the transaction, following [`TransactionType::amount`]
+    pub fn amount(&self) -> Decimal {
+        self.tx_type.amount()
+    }
+
+    /// Check a deposit, withdrawal or transfer for structural problems before submitting it,
+    /// i.e. a negative transfer or an amount with more decimal places than `config` allows
+    ///
+    /// Other transaction types, and whatever depends on the state of the accounts, such as
+    /// sufficient funds, always pass.
+    pub fn validate(&self, config: &ValidationConfig) -> Result<(), ValidationError> {
+        if !self.is_generative_tx() {
+            return Ok(());
+        }
+        let amount = self.amount();
+        if amount.is_sign_negative() && !amount.is_zero() {
+            return Err(ValidationError::NegativeAmount(amount));
         }
+        config.validate_amount(Some(amount)).map(|_| ())
     }
+
+    /// Check whether the transaction consumes its txid, i.e. is a deposit, withdrawal or transfer
+    ///
+    /// Deposits and withdrawals are also recorded on the account so they can be disputed later,
+    /// while transfers are not.
+    fn is_generative_tx(&self) -> bool {
+        matches!(
+            self.tx_type,
+            TransactionType::Deposit(_)
+                | TransactionType::Withdrawal(_)
+                | TransactionType::Transfer { .. }
+        )
+    }
+}
+
+/// Validate `value` as an amount of any scale, panicking if it is negative
+#[cfg(test)]
+pub(crate) fn amount(value: Decimal) -> Amount {
+    Amount::try_from(value).expect("test amounts are non-negative")
 }
 
 mod account;
 mod accounts_handler;
+#[cfg(feature = "binary")]
+mod binary_reader;
+#[cfg(feature = "arrow")]
+mod columns;
+mod config;
+mod csv_reader;
+mod diff;
+mod error;
+mod ledger;
+mod multi_currency;
+mod outcome;
+mod raw;
+mod stats;
+#[cfg(feature = "async")]
+mod stream;
+mod sync_handler;
+mod txid_allocator;
+mod validation;
 
 pub(crate) use account::Account;
 
-pub use account::AccountSnapshot;
-pub use accounts_handler::AccountsHandler;
+pub use account::{
+    AccountSnapshot, AccountStatus, AppliedEvent, StringSnapshot, TxKind, DEFAULT_OUTPUT_SCALE,
+};
+pub use accounts_handler::{AccountsHandler, FinalizeReport, HandlerState};
+#[cfg(feature = "binary")]
+pub use binary_reader::{
+    read_binary_transactions, write_binary_transaction, BinaryTransaction, MAX_FRAME_LEN,
+};
+#[cfg(feature = "arrow")]
+pub use columns::DECIMAL128_PRECISION;
+pub use config::{
+    DisputePolicy, HandlerConfig, LockedPolicy, NegativeAmountPolicy, RepeatDepositPolicy,
+    RoundingStrategy, ScalePolicy, UniquenessScope, WithdrawalMode,
+};
+pub use csv_reader::{read_csv_transactions, CsvOptions};
+pub use diff::{diff_snapshots, DeltaKind, SnapshotDelta};
+pub use error::{
+    AmountError, InvariantError, ParseTransactionTypeError, TransactionError, ValidationError,
+};
+pub use ledger::Ledger;
+pub use multi_currency::{CurrencySnapshot, MultiCurrencyHandler};
+pub use outcome::{IgnoreReason, ProcessOutcome};
+pub use raw::{RawAmount, RawTransaction, RawTransactionType};
+pub use stats::IngestionStats;
+pub use sync_handler::SyncAccountsHandler;
+pub use txid_allocator::TxidAllocator;
+pub use validation::{Currency, ValidationConfig};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_display_as_integers() {
+        assert_eq!(ClientId::from(5).to_string(), "5");
+        assert_eq!(Txid::from(70000u32).to_string(), "70000");
+    }
+
+    #[test]
+    fn transaction_type_from_str_works() {
+        use rust_decimal_macros::dec;
+        assert_eq!(
+            "deposit:1.50".parse(),
+            Ok(TransactionType::Deposit(amount(dec!(1.50))))
+        );
+        assert_eq!(
+            "Withdrawal:2".parse(),
+            Ok(TransactionType::Withdrawal(amount(dec!(2))))
+        );
+        assert_eq!("dispute".parse(), Ok(TransactionType::Dispute));
+        assert_eq!(
+            "partial_dispute:0.75".parse(),
+            Ok(TransactionType::PartialDispute(dec!(0.75)))
+        );
+        assert_eq!("RESOLVE".parse(), Ok(TransactionType::Resolve));
+        assert_eq!(
+            "partial_resolve:0.25".parse(),
+            Ok(TransactionType::PartialResolve(dec!(0.25)))
+        );
+        assert_eq!("chargeback".parse(), Ok(TransactionType::Chargeback));
+    }
+
+    #[test]
+    fn transaction_type_from_str_fails() {
+        assert_eq!(
+            "refund".parse::<TransactionType>(),
+            Err(ParseTransactionTypeError::UnknownKeyword(
+                "refund".to_string()
+            ))
+        );
+        assert_eq!(
+            "deposit".parse::<TransactionType>(),
+            Err(ParseTransactionTypeError::MissingAmount)
+        );
+        assert_eq!(
+            "deposit:abc".parse::<TransactionType>(),
+            Err(ParseTransactionTypeError::InvalidAmount("abc".to_string()))
+        );
+        assert_eq!(
+            "dispute:1".parse::<TransactionType>(),
+            Err(ParseTransactionTypeError::UnexpectedAmount)
+        );
+    }
+
+    #[test]
+    fn amount_works() {
+        use rust_decimal_macros::dec;
+        for (tx_type, expected) in [
+            (TransactionType::Deposit(amount(dec!(1.50))), dec!(1.50)),
+            (TransactionType::Withdrawal(amount(dec!(2))), dec!(2)),
+            (TransactionType::Dispute, Decimal::ZERO),
+            (TransactionType::PartialDispute(dec!(0.75)), Decimal::ZERO),
+            (TransactionType::Resolve, Decimal::ZERO),
+            (TransactionType::PartialResolve(dec!(0.25)), Decimal::ZERO),
+            (TransactionType::Chargeback, Decimal::ZERO),
+            (
+                TransactionType::Transfer {
+                    to: ClientId(2),
+                    amount: dec!(3),
+                },
+                dec!(3),
+            ),
+        ] {
+            assert_eq!(tx_type.amount(), expected);
+            let tx = Transaction {
+                tx_type,
+                client_id: ClientId(1),
+                txid: Txid(1),
+                timestamp: None,
+            };
+            assert_eq!(tx.amount(), expected);
+        }
+    }
+
+    #[test]
+    fn ids_sort_numerically() {
+        let mut client_ids = vec![ClientId(10), ClientId(2), ClientId(1)];
+        client_ids.sort();
+        assert_eq!(client_ids, vec![ClientId(1), ClientId(2), ClientId(10)]);
+        assert!(Txid(2) < Txid(10));
+    }
+
+    #[test]
+    fn amount_new_works() {
+        use rust_decimal_macros::dec;
+        assert_eq!(Amount::new(dec!(1.5)).map(Amount::value), Ok(dec!(1.5)));
+        assert_eq!(Amount::new(dec!(0)).map(Amount::value), Ok(dec!(0)));
+        assert_eq!(
+            Amount::try_from(dec!(1.00001)).map(Amount::value),
+            Ok(dec!(1.00001))
+        );
+    }
+
+    #[test]
+    fn amount_new_fails() {
+        use rust_decimal_macros::dec;
+        assert_eq!(
+            Amount::new(dec!(-0.0001)),
+            Err(AmountError::Negative(dec!(-0.0001)))
+        );
+        assert_eq!(
+            Amount::new(dec!(1.00001)),
+            Err(AmountError::ExcessPrecision {
+                value: dec!(1.00001),
+                max_scale: 4
+            })
+        );
+        assert_eq!(
+            Amount::with_max_scale(dec!(1.5), 0),
+            Err(AmountError::ExcessPrecision {
+                value: dec!(1.5),
+                max_scale: 0
+            })
+        );
+        assert!(Amount::with_max_scale(dec!(1.00001), 8).is_ok());
+        assert_eq!(
+            Amount::try_from(dec!(-1)),
+            Err(AmountError::Negative(dec!(-1)))
+        );
+    }
+
+    #[test]
+    fn amount_serializes_as_decimal() {
+        use rust_decimal_macros::dec;
+        let amount = Amount::new(dec!(1.5)).unwrap();
+        let json = serde_json::to_string(&amount).unwrap();
+        assert_eq!(json, serde_json::to_string(&dec!(1.5)).unwrap());
+        assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), amount);
+        assert!(serde_json::from_str::<Amount>(r#""-1""#).is_err());
+    }
+
+    #[test]
+    fn transaction_validate_works() {
+        use rust_decimal_macros::dec;
+        let tx = |tx_type| Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        let config = ValidationConfig::default();
+        for tx_type in [
+            TransactionType::Deposit(amount(dec!(1.2345))),
+            TransactionType::Withdrawal(amount(dec!(0))),
+            TransactionType::Transfer {
+                to: ClientId(2),
+                amount: dec!(1),
+            },
+            TransactionType::Dispute,
+            TransactionType::PartialDispute(dec!(1.00001)),
+        ] {
+            assert_eq!(tx(tx_type).validate(&config), Ok(()));
+        }
+        assert_eq!(
+            tx(TransactionType::Transfer {
+                to: ClientId(2),
+                amount: dec!(-1)
+            })
+            .validate(&config),
+            Err(ValidationError::NegativeAmount(dec!(-1)))
+        );
+        assert_eq!(
+            tx(TransactionType::Deposit(amount(dec!(1.00001)))).validate(&config),
+            Err(ValidationError::ExcessPrecision {
+                amount: dec!(1.00001),
+                max_scale: 4
+            })
+        );
+        assert_eq!(
+            tx(TransactionType::Transfer {
+                to: ClientId(2),
+                amount: dec!(1.5)
+            })
+            .validate(&ValidationConfig {
+                max_scale: 0,
+                ..ValidationConfig::default()
+            }),
+            Err(ValidationError::ExcessPrecision {
+                amount: dec!(1.5),
+                max_scale: 0
+            })
+        );
+    }
+
+    #[test]
+    fn public_types_are_send_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<AccountsHandler>();
+        assert_send_sync::<SyncAccountsHandler>();
+        assert_send_sync::<MultiCurrencyHandler>();
+        assert_send_sync::<Ledger>();
+        assert_send_sync::<HandlerState>();
+        assert_send_sync::<FinalizeReport>();
+        assert_send_sync::<AccountSnapshot>();
+        assert_send_sync::<Transaction>();
+        assert_send_sync::<ProcessOutcome>();
+        assert_send_sync::<TransactionError>();
+        assert_send_sync::<ValidationError>();
+        assert_send_sync::<InvariantError>();
+    }
+}
@@ -1,120 +1,815 @@
-use std::{io, path::PathBuf};
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use anyhow::{anyhow, Context, Result};
-use clap::Parser;
-use csv::ReaderBuilder;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use csv::Position;
+use flate2::read::MultiGzDecoder;
 use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
 
-use serde::Deserialize;
-
-use coding_test::{AccountsHandler, ClientId, Transaction, TransactionType, Txid};
-
-/// Transaction type represented by the CSV field `type`
-#[derive(Deserialize)]
-#[serde(rename_all = "lowercase")]
-enum CsvTransactionType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
-}
-
-/// Transaction represented by a CSV row
-#[derive(Deserialize)]
-struct CsvTransaction {
-    #[serde(rename = "type")]
-    tx_type: CsvTransactionType,
-    client: u16,
-    #[serde(rename = "tx")]
-    txid: u32,
-    amount: Option<Decimal>,
-}
-
-// This abstraction of the two separate transaction types is necessary because of a limitation of
-// `csv::Deserialize` which does not allow to deserialize a field into an enum with heterogenous
-// variants. The best that can be done is
-// https://stackoverflow.com/questions/69417454/serialize-deserialize-csv-with-nested-enum-struct-with-serde-in-rust
-// but this only works for serializing and not for deserializing, as documented here:
-// github.com/BurntSushi/rust-csv/issues/211
-// Thus, we have to deserialize into a separate struct for each variant and then convert it into the
-// desired enum variant. This also allows us to do some basic validation of the CSV data, including
-// checking decimal precision of `amount` to 4 places.
-impl TryFrom<CsvTransaction> for Transaction {
-    type Error = anyhow::Error;
-
-    fn try_from(tx: CsvTransaction) -> Result<Self> {
-        use CsvTransactionType::*;
-        let validate_amount = |amount: Option<Decimal>| {
-            if let Some(amount) = amount {
-                if amount.scale() > 4 {
-                    return Err(anyhow!("amount {} has more than 4 decimal places", amount));
+use coding_test::{
+    read_csv_transactions, AccountSnapshot, AccountsHandler, ClientId, CsvOptions, Currency,
+    HandlerConfig, IgnoreReason, LockedPolicy, ProcessOutcome, RepeatDepositPolicy, Transaction,
+    TransactionError, Txid, ValidationConfig, DEFAULT_OUTPUT_SCALE,
+};
+
+/// Format of the transactions files
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InputFormat {
+    Csv,
+    /// Length-prefixed `bincode` frames
+    #[cfg(feature = "binary")]
+    Bincode,
+}
+
+/// Handling of a deposit reusing the txid of an earlier deposit of the same client
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OnRepeatDeposit {
+    /// Reject it as a duplicate, following `--on-duplicate`
+    Reject,
+    /// Replace the earlier deposit, adjusting the available balance by the difference
+    Overwrite,
+    /// Add it to the earlier deposit
+    Sum,
+}
+
+impl From<OnRepeatDeposit> for RepeatDepositPolicy {
+    fn from(on_repeat_deposit: OnRepeatDeposit) -> Self {
+        match on_repeat_deposit {
+            OnRepeatDeposit::Reject => Self::Reject,
+            OnRepeatDeposit::Overwrite => Self::Overwrite,
+            OnRepeatDeposit::Sum => Self::Sum,
+        }
+    }
+}
+
+/// Handling of a transaction reusing the txid of an earlier one
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OnDuplicate {
+    /// Reject it like any other error, reporting it with `--verbose` and aborting with
+    /// `--fail-fast`
+    Error,
+    /// Drop it silently
+    Skip,
+    /// Drop it, reporting it to stderr
+    Warn,
+}
+
+/// Output format of the account snapshots
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    Json,
+}
+
+/// Names of the columns of the accounts CSV, in order
+const DEFAULT_HEADER: [&str; 6] = [
+    "client",
+    "available",
+    "held",
+    "total",
+    "locked",
+    "disputed_count",
+];
+
+/// Style of the header of the accounts CSV
+#[derive(Clone, Debug)]
+enum HeaderStyle {
+    /// Names of the fields of the accounts, e.g. `client`
+    Default,
+    /// Default names with the client column qualified as `client_id`
+    Snake,
+    /// Given names of every column, in order
+    Custom(Vec<String>),
+}
+
+impl FromStr for HeaderStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Self::Default),
+            "snake" => Ok(Self::Snake),
+            _ => {
+                let names = s
+                    .strip_prefix("custom:")
+                    .ok_or_else(|| format!("invalid header style: {}", s))?;
+                let names: Vec<_> = names.split(',').map(str::to_string).collect();
+                if names.len() != DEFAULT_HEADER.len() {
+                    return Err(format!(
+                        "custom header needs {} names, got {}",
+                        DEFAULT_HEADER.len(),
+                        names.len()
+                    ));
                 }
-                return Ok(amount);
+                Ok(Self::Custom(names))
             }
-            Err(anyhow!(
-                "amount is required for deposit/withdraw transactions"
-            ))
-        };
-        let tx_type = match tx.tx_type {
-            Deposit => TransactionType::Deposit(validate_amount(tx.amount)?),
-            Withdrawal => TransactionType::Withdrawal(validate_amount(tx.amount)?),
-            Dispute => TransactionType::Dispute,
-            Resolve => TransactionType::Resolve,
-            Chargeback => TransactionType::Chargeback,
-        };
+        }
+    }
+}
 
-        Ok(Self {
-            tx_type,
-            client_id: ClientId(tx.client),
-            txid: Txid(tx.txid),
-        })
+impl HeaderStyle {
+    /// Get the names of the columns of the accounts CSV, in order
+    fn names(&self) -> Vec<&str> {
+        match self {
+            Self::Default => DEFAULT_HEADER.to_vec(),
+            Self::Snake => DEFAULT_HEADER
+                .iter()
+                .map(|&name| if name == "client" { "client_id" } else { name })
+                .collect(),
+            Self::Custom(names) => names.iter().map(String::as_str).collect(),
+        }
     }
 }
 
+/// Amount held by the dispute of a client's transaction, output with `--audit`
+#[derive(Serialize)]
+struct DisputeRow {
+    /// Client's ID
+    client: ClientId,
+    /// Disputed transaction's ID
+    tx: Txid,
+    /// Amount held by the dispute
+    held: Decimal,
+}
+
+/// Accounts along with their disputes, output as JSON with `--audit`
+#[derive(Serialize)]
+struct AuditOutput {
+    /// Every account
+    accounts: Vec<AccountSnapshot>,
+    /// Every dispute of every account
+    disputes: Vec<DisputeRow>,
+}
+
 /// Transaction payments engine
+///
+/// Without a subcommand, transactions are processed as with `process`.
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    args: Args,
+}
+
+/// Subcommand of the CLI
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Process the transactions and output the accounts
+    Process(Args),
+    /// Only validate the rows of the transactions files, the same as `process --check`
+    Validate(Args),
+}
+
+/// Options shared by every subcommand
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Transactions filename
+    /// Transactions filenames, processed in order, or `-` to read from stdin (the default)
     #[clap(value_parser, value_name = "TRANSACTIONS_FILE", value_hint = clap::ValueHint::FilePath)]
-    transactions_filename: PathBuf,
+    transactions_filenames: Vec<PathBuf>,
+    /// File of disputes, resolves and chargebacks delivered separately, processed after the
+    /// transactions files so that they can reference their transactions
+    #[clap(long, value_parser, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    disputes_file: Option<PathBuf>,
+    /// Report skipped rows to stderr
+    #[clap(short, long)]
+    verbose: bool,
+    /// Output format
+    #[clap(long, value_enum, default_value = "csv")]
+    format: Format,
+    /// Format of the transactions files
+    #[clap(long, value_enum, default_value = "csv")]
+    input_format: InputFormat,
+    /// Maximum number of decimal places accepted in amounts
+    #[clap(long, default_value_t = ValidationConfig::default().max_scale)]
+    max_scale: u32,
+    /// Currency every amount must be suffixed with, e.g. `USD` for `1.50 USD`, rather than being a
+    /// plain decimal
+    #[clap(long, value_parser)]
+    currency: Option<Currency>,
+    /// Number of decimal places balances are output with, rounding half to even [default: the
+    /// larger of 4 and --max-scale]
+    #[clap(long)]
+    precision: Option<u32>,
+    /// Read transactions files without a header row, with columns in the fixed order `type`,
+    /// `client`, `tx`, `amount`
+    #[clap(long)]
+    no_header: bool,
+    /// Header of the accounts CSV: `default`, `snake` for `client_id` rather than `client`, or
+    /// `custom:` followed by the comma-separated names of every column
+    #[clap(long, value_parser, default_value = "default")]
+    header_style: HeaderStyle,
+    /// Skip lines of the transactions files starting with this ASCII character, e.g. `#`
+    #[clap(long, value_name = "CHAR", value_parser = parse_comment_char)]
+    comment_char: Option<u8>,
+    /// Separate the fields of the transactions files with this ASCII character, e.g. `\t` or `|`
+    #[clap(long, value_name = "CHAR", value_parser = parse_delimiter, default_value = ",")]
+    delimiter: u8,
+    /// Strip commas from amounts as thousands separators, e.g. `"1,000.50"`, which must be quoted
+    /// if commas also separate the fields
+    #[clap(long)]
+    strip_separators: bool,
+    /// Write the accounts to this file, truncating it, instead of stdout
+    #[clap(short, long, value_parser, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+    /// Reject dispute, resolve and chargeback rows carrying an amount, rather than ignoring it
+    #[clap(long)]
+    strict: bool,
+    /// Abort with an error on any transaction other than a resolve or chargeback for a locked
+    /// account, rather than ignoring it
+    #[clap(long)]
+    strict_locked: bool,
+    /// Abort with an error on the first row that cannot be parsed, validated or submitted, rather
+    /// than skipping it
+    #[clap(long)]
+    fail_fast: bool,
+    /// Handling of a transaction reusing the txid of an earlier one, e.g. when merging files that
+    /// overlap
+    #[clap(long, value_enum, default_value = "error")]
+    on_duplicate: OnDuplicate,
+    /// Handling of a deposit reusing the txid of an earlier deposit of the same client, which
+    /// feeds may send to amend it. An amended deposit is disputed for its amended amount, and one
+    /// under dispute, charged back or reversed cannot be amended
+    #[clap(long, value_enum, default_value = "reject")]
+    on_repeat_deposit: OnRepeatDeposit,
+    /// Buffer disputes, resolves and chargebacks referencing a txid not seen yet, replaying them
+    /// once the transaction arrives, rather than ignoring them
+    #[clap(long)]
+    buffer_disputes: bool,
+    /// Treat a deposit or withdrawal identical to an earlier one with the same txid as a no-op
+    /// rather than a duplicate, e.g. when re-running overlapping files
+    #[clap(long)]
+    idempotent: bool,
+    /// Also output the amount held by each dispute of every account, as a second CSV table after
+    /// a blank line, or alongside the accounts in JSON
+    #[clap(long)]
+    audit: bool,
+    /// Report the disputes still open at the end of the inputs to stderr
+    #[clap(long)]
+    open_disputes: bool,
+    /// Output a checkpoint of the accounts after every N submitted transactions, tagged with the
+    /// number of transactions submitted so far
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_every: Option<u64>,
+    /// Write the checkpoints to this file, truncating it, instead of stderr
+    #[clap(long, value_parser, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    checkpoint_output: Option<PathBuf>,
+    /// Abort with an error on reading more than N rows across all inputs, e.g. to guard against a
+    /// runaway file
+    #[clap(long, value_name = "N")]
+    max_rows: Option<u64>,
+    /// Abort with an error on reading a row starting more than N bytes into an input
+    #[clap(long, value_name = "N")]
+    max_bytes: Option<u64>,
+    /// Only process the transactions of the first N distinct clients with an account, skipping
+    /// those of later clients, e.g. to debug a large file on a sample of it
+    #[clap(long, value_name = "N")]
+    limit_clients: Option<usize>,
+    /// Also output zeroed accounts for clients without one, i.e. those referenced only by rows
+    /// that were rejected and those listed with `--roster`
+    #[clap(long)]
+    include_empty: bool,
+    /// File listing known client ids, one per line, to output with `--include-empty`
+    #[clap(long, value_parser, value_name = "PATH", value_hint = clap::ValueHint::FilePath, requires = "include-empty")]
+    roster: Option<PathBuf>,
+    /// Append a comment to the accounts CSV summarizing the number of clients, of submitted
+    /// transactions and of locked accounts
+    #[clap(long)]
+    summary: bool,
+    /// Only validate the rows of the transactions files, summarizing the result to stderr
+    /// without processing them or outputting the accounts
+    #[clap(long)]
+    check: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
-
-    // create csv reader
-    let mut reader = ReaderBuilder::new()
-        .flexible(true)
-        .from_path(&args.transactions_filename)
-        .with_context(|| {
-            format!(
-                "Failed to read transactions from {}",
-                args.transactions_filename.display()
-            )
+impl Args {
+    /// Iterate over the inputs in the order they are processed, the transactions files followed
+    /// by the disputes file, if any
+    fn inputs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.transactions_filenames
+            .iter()
+            .chain(&self.disputes_file)
+    }
+
+    /// Get the number of decimal places balances are output with, by default enough for every
+    /// accepted amount and at least `DEFAULT_OUTPUT_SCALE`
+    fn precision(&self) -> u32 {
+        self.precision
+            .unwrap_or_else(|| self.max_scale.max(DEFAULT_OUTPUT_SCALE))
+    }
+
+    /// Get the validation policy selected by the arguments
+    fn validation_config(&self) -> ValidationConfig {
+        ValidationConfig {
+            max_scale: self.max_scale,
+            currency: self.currency,
+            strict: self.strict,
+        }
+    }
+}
+
+/// Parse the character starting comment lines, which must be a single ASCII character
+fn parse_comment_char(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        &[c] if c.is_ascii() => Ok(c),
+        _ => Err(format!(
+            "comment character must be a single ASCII character: {}",
+            s
+        )),
+    }
+}
+
+/// Parse the character separating fields, which must be a single ASCII character or `\t` for a tab
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        &[c] if c.is_ascii() => Ok(c),
+        _ => Err(format!("delimiter must be a single ASCII character: {}", s)),
+    }
+}
+
+/// Maximum number of invalid rows detailed by `--check`
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// Describe the location of a CSV row read from `source`
+fn row_location(source: &Path, position: Option<&Position>) -> String {
+    match position {
+        Some(position) => format!(
+            "{}: line {} (byte {})",
+            source.display(),
+            position.line(),
+            position.byte()
+        ),
+        None => source.display().to_string(),
+    }
+}
+
+/// Report a skipped CSV row to stderr
+fn report_skipped(source: &Path, position: Option<&Position>, reason: impl fmt::Display) {
+    eprintln!(
+        "{}: skipped row: {}",
+        row_location(source, position),
+        reason
+    );
+}
+
+/// Open a transactions file, where `-` denotes stdin and a `.gz` extension denotes a
+/// gzip-compressed file
+fn open_input(path: &Path) -> Result<Box<dyn io::Read>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let file = File::open(path)
+        .with_context(|| format!("Failed to read transactions from {}", path.display()))?;
+    if path.extension() == Some(OsStr::new("gz")) {
+        return Ok(Box::new(MultiGzDecoder::new(io::BufReader::new(file))));
+    }
+    Ok(Box::new(file))
+}
+
+/// Read every row of `input` from `source` in the input format, passing its position along with
+/// either the transaction it converts into or the reason it cannot be converted to `f`
+fn read_transactions(
+    input: impl io::Read,
+    source: &Path,
+    args: &Args,
+    f: impl FnMut(Option<&Position>, Result<Transaction, String>),
+) -> Result<()> {
+    match args.input_format {
+        InputFormat::Csv => {
+            let options = CsvOptions {
+                has_headers: !args.no_header,
+                comment: args.comment_char,
+                delimiter: args.delimiter,
+                strip_separators: args.strip_separators,
+            };
+            read_csv_transactions(input, &args.validation_config(), options, f)
+                .with_context(|| format!("Failed to read CSV from {}", source.display()))
+        }
+        #[cfg(feature = "binary")]
+        InputFormat::Bincode => {
+            coding_test::read_binary_transactions(input, &args.validation_config(), f)
+                .with_context(|| format!("Failed to read frames from {}", source.display()))
+        }
+    }
+}
+
+/// Destination of the checkpoints output with `--checkpoint-every`
+struct Checkpoints {
+    /// Number of submitted transactions between checkpoints
+    every: u64,
+    /// Output the checkpoints are written to
+    output: Box<dyn Write>,
+}
+
+impl Checkpoints {
+    /// Open the destination of the checkpoints selected by the arguments, if any
+    fn open(args: &Args) -> Result<Option<Self>> {
+        let Some(every) = args.checkpoint_every else {
+            return Ok(None);
+        };
+        let output: Box<dyn Write> = match &args.checkpoint_output {
+            Some(path) => Box::new(io::BufWriter::new(File::create(path).with_context(
+                || format!("Failed to create checkpoint file {}", path.display()),
+            )?)),
+            None => Box::new(io::stderr()),
+        };
+        Ok(Some(Self { every, output }))
+    }
+
+    /// Write a checkpoint of the accounts if a multiple of the interval has been submitted
+    fn write_if_due(&mut self, accounts: &AccountsHandler, args: &Args) -> Result<()> {
+        let sequence = accounts.sequence();
+        if !sequence.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        writeln!(self.output, "checkpoint after transaction {}", sequence)?;
+        // checkpoints are output at the same precision as the final balances
+        let snapshots = accounts.snapshots_sorted_iter_with_scale(args.precision());
+        write_snapshots(&mut self.output, snapshots, None, None, args)
+            .context("Failed to write checkpoint")
+    }
+}
+
+/// Process every transaction in CSV `input` read from `source`, writing due checkpoints along the
+/// way
+fn process_transactions(
+    input: impl io::Read,
+    source: &Path,
+    accounts: &mut AccountsHandler,
+    checkpoints: &mut Option<Checkpoints>,
+    rows_read: &mut u64,
+    clients: &mut BTreeSet<ClientId>,
+    args: &Args,
+) -> Result<()> {
+    let mut aborted = None;
+    read_transactions(input, source, args, |position, transaction| {
+        // stop processing once aborted
+        if aborted.is_some() {
+            return;
+        }
+        // abort on rows beyond the limits, if any
+        *rows_read += 1;
+        let limit = if let Some(max_rows) = args.max_rows.filter(|&max_rows| *rows_read > max_rows)
+        {
+            Some(format!("{} rows set by --max-rows", max_rows))
+        } else {
+            args.max_bytes
+                .filter(|&max_bytes| position.is_some_and(|position| position.byte() > max_bytes))
+                .map(|max_bytes| format!("{} bytes set by --max-bytes", max_bytes))
+        };
+        if let Some(limit) = limit {
+            aborted = Some(format!(
+                "{}: exceeded the limit of {}",
+                row_location(source, position),
+                limit
+            ));
+            return;
+        }
+        let skip = |reason: &dyn fmt::Display| {
+            if args.verbose {
+                report_skipped(source, position, reason);
+            }
+        };
+        // skip invalid rows, unless failing fast
+        let transaction = match transaction {
+            Ok(transaction) => transaction,
+            Err(e) if args.fail_fast => {
+                aborted = Some(format!("{}: {}", row_location(source, position), e));
+                return;
+            }
+            Err(e) => {
+                skip(&e);
+                return;
+            }
+        };
+        // skip the transactions of clients beyond the sample, if any
+        if args.limit_clients.is_some_and(|limit| {
+            accounts.account_count() >= limit && !accounts.contains(transaction.client_id)
+        }) {
+            skip(&"client beyond the limit set by --limit-clients");
+            return;
+        }
+        if args.include_empty {
+            clients.insert(transaction.client_id);
+        }
+        // ignore duplicate txid error and ignored transactions, but abort on any error when
+        // failing fast and on a locked account under --strict-locked
+        match accounts.submit_transaction(transaction) {
+            Ok(
+                ProcessOutcome::Applied
+                | ProcessOutcome::PartiallyApplied(_)
+                | ProcessOutcome::Buffered,
+            ) => {}
+            Ok(ProcessOutcome::Idempotent) => skip(&"identical to an earlier transaction"),
+            Ok(ProcessOutcome::Ignored(reason)) => skip(&format!("ignored: {}", reason)),
+            // outcomes added to the library later are not reported
+            Ok(_) => {}
+            Err(TransactionError::DuplicateTxid(_)) if args.on_duplicate == OnDuplicate::Skip => {}
+            Err(TransactionError::DuplicateTxid(txid))
+                if args.on_duplicate == OnDuplicate::Warn =>
+            {
+                eprintln!(
+                    "{}: warning: dropped duplicate txid {} for client {}",
+                    row_location(source, position),
+                    txid,
+                    transaction.client_id
+                );
+            }
+            Err(e) if args.fail_fast || matches!(e, TransactionError::AccountLocked(_)) => {
+                aborted = Some(format!("{}: {}", row_location(source, position), e));
+            }
+            Err(e) => skip(&e),
+        }
+        // no checkpoint follows the transaction that aborted processing
+        if let (None, Some(checkpoints)) = (&aborted, checkpoints.as_mut()) {
+            if let Err(e) = checkpoints.write_if_due(accounts, args) {
+                aborted = Some(format!("{:#}", e));
+            }
+        }
+    })?;
+    match aborted {
+        Some(reason) => bail!(reason),
+        None => Ok(()),
+    }
+}
+
+/// Read the client ids listed one per line in the roster file at `path`, skipping blank lines
+fn read_roster(path: &Path) -> Result<BTreeSet<ClientId>> {
+    let roster = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read roster file {}", path.display()))?;
+    roster
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            line.trim().parse().map(ClientId).with_context(|| {
+                format!(
+                    "{}:{}: invalid client id {:?}",
+                    path.display(),
+                    index + 1,
+                    line.trim()
+                )
+            })
+        })
+        .collect()
+}
+
+/// Validate every row of the inputs without processing them, summarizing the result to stderr
+fn check_transactions(args: &Args) -> Result<()> {
+    let mut valid = 0;
+    let mut invalid = 0;
+    let mut errors = Vec::new();
+    for path in args.inputs() {
+        let input = open_input(path)?;
+        read_transactions(input, path, args, |position, transaction| {
+            if let Err(e) = transaction {
+                invalid += 1;
+                if errors.len() < MAX_REPORTED_ERRORS {
+                    errors.push(format!("{}: {}", row_location(path, position), e));
+                }
+            } else {
+                valid += 1;
+            }
         })?;
+    }
 
-    let mut accounts = AccountsHandler::new();
+    eprintln!("{} valid rows, {} invalid rows", valid, invalid);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+    if invalid > 0 {
+        bail!("{} invalid rows", invalid);
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut args = match cli.command {
+        None => cli.args,
+        Some(Command::Process(args)) => args,
+        Some(Command::Validate(args)) => Args {
+            check: true,
+            ..args
+        },
+    };
+
+    // fall back to stdin if no file is given
+    if args.transactions_filenames.is_empty() {
+        args.transactions_filenames.push(PathBuf::from("-"));
+    }
 
-    // process transactions
-    for transaction in reader.deserialize() {
-        let transaction: CsvTransaction =
-            transaction.with_context(|| "Failed to parse transaction from CSV row")?;
-        // process transaction
-        // ignore validation errors, including precision and missing amount
-        if let Ok(transaction) = Transaction::try_from(transaction) {
-            // ignore duplicate txid error
-            let _ = accounts.submit_transaction(transaction);
+    if args.check {
+        return check_transactions(&args);
+    }
+    if args.summary && !matches!(args.format, Format::Csv) {
+        bail!("--summary is only supported with --format csv");
+    }
+
+    let mut accounts = AccountsHandler::with_config(HandlerConfig {
+        max_scale: args.max_scale,
+        locked_policy: if args.strict_locked {
+            LockedPolicy::Reject
+        } else {
+            LockedPolicy::Ignore
+        },
+        buffer_disputes: args.buffer_disputes,
+        idempotent_resubmits: args.idempotent,
+        repeat_deposit_policy: args.on_repeat_deposit.into(),
+        ..HandlerConfig::default()
+    });
+
+    // process transactions from each input in order, sharing the accounts (and thus txids), so
+    // that the disputes file can reference transactions of the transactions files
+    let mut checkpoints = Checkpoints::open(&args)?;
+    let mut rows_read = 0;
+    let mut clients = match &args.roster {
+        Some(path) => read_roster(path)?,
+        None => BTreeSet::new(),
+    };
+    for path in args.inputs() {
+        let input = open_input(path)?;
+        process_transactions(
+            input,
+            path,
+            &mut accounts,
+            &mut checkpoints,
+            &mut rows_read,
+            &mut clients,
+            &args,
+        )?;
+    }
+    if let Some(checkpoints) = &mut checkpoints {
+        checkpoints.output.flush()?;
+    }
+
+    // drop buffered transactions whose transaction never arrived
+    let report = accounts.finalize();
+    for tx in &report.dropped {
+        if args.verbose {
+            eprintln!(
+                "skipped buffered transaction for client {} referencing txid {}: ignored: {}",
+                tx.client_id,
+                tx.txid,
+                IgnoreReason::UnknownTxid
+            );
+        }
+    }
+
+    if args.open_disputes {
+        for dispute in dispute_rows(&accounts, args.precision()) {
+            eprintln!(
+                "open dispute for client {} of txid {} holding {}",
+                dispute.client, dispute.tx, dispute.held
+            );
+        }
+    }
+
+    if args.verbose {
+        eprintln!("{}", report.stats);
+    }
+    report.invariants?;
+
+    // open zeroed accounts for the known clients without one
+    for client_id in clients {
+        match accounts.seed_account(client_id, Decimal::ZERO, Decimal::ZERO, false) {
+            Ok(()) | Err(TransactionError::AccountExists(_)) => {}
+            Err(e) => bail!(e),
+        }
+    }
+
+    let snapshots = accounts.snapshots_sorted_iter_with_scale(args.precision());
+    let disputes = args
+        .audit
+        .then(|| dispute_rows(&accounts, args.precision()));
+    let transactions = args.summary.then(|| accounts.stats().processed());
+    match &args.output {
+        Some(path) => {
+            let output = File::create(path)
+                .with_context(|| format!("Failed to create output file {}", path.display()))?;
+            write_snapshots(output, snapshots, transactions, disputes, &args)
+                .with_context(|| format!("Failed to write accounts to {}", path.display()))
         }
+        // a reader closing early, as `head` does, ends the output as it would for any Unix tool
+        None => match write_snapshots(
+            io::stdout().lock(),
+            snapshots,
+            transactions,
+            disputes,
+            &args,
+        ) {
+            Err(e) if is_broken_pipe(&e) => Ok(()),
+            result => result,
+        },
     }
+}
+
+/// Check whether `error` was caused by writing to a pipe whose reader was closed
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        let kind = if let Some(e) = cause.downcast_ref::<io::Error>() {
+            Some(e.kind())
+        } else if let Some(e) = cause.downcast_ref::<csv::Error>() {
+            match e.kind() {
+                csv::ErrorKind::Io(e) => Some(e.kind()),
+                _ => None,
+            }
+        } else if let Some(e) = cause.downcast_ref::<serde_json::Error>() {
+            e.io_error_kind()
+        } else {
+            None
+        };
+        kind == Some(io::ErrorKind::BrokenPipe)
+    })
+}
 
-    // serialize client accounts as csv
-    let mut writer = csv::Writer::from_writer(io::stdout());
-    for snapshot in accounts.snapshot_accounts() {
-        writer.serialize(snapshot)?;
+/// List the disputes of every account in ascending order of client id then txid, with held
+/// amounts rounded half to even to `scale` decimal places
+fn dispute_rows(accounts: &AccountsHandler, scale: u32) -> Vec<DisputeRow> {
+    accounts
+        .open_disputes()
+        .into_iter()
+        .map(|(client, tx, held)| {
+            let mut held = held.round_dp(scale);
+            held.rescale(scale);
+            DisputeRow { client, tx, held }
+        })
+        .collect()
+}
+
+/// Serialize client accounts, followed by a summary comment counting `transactions` if given and
+/// their disputes if given, to `output` in the format selected by the arguments
+fn write_snapshots(
+    output: impl Write,
+    snapshots: impl Iterator<Item = AccountSnapshot>,
+    transactions: Option<usize>,
+    disputes: Option<Vec<DisputeRow>>,
+    args: &Args,
+) -> Result<()> {
+    match args.format {
+        Format::Csv => {
+            let mut output = io::BufWriter::new(output);
+            // the header is written by hand so that its names can be customized
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(&mut output);
+            let mut snapshots = snapshots.peekable();
+            if snapshots.peek().is_some() {
+                writer.write_record(args.header_style.names())?;
+            }
+            let (mut clients, mut locked) = (0, 0);
+            for snapshot in snapshots {
+                clients += 1;
+                locked += usize::from(snapshot.locked);
+                writer.serialize(snapshot)?;
+            }
+            writer.flush()?;
+            drop(writer);
+            // the summary is a comment so that parsers honoring comments skip it
+            if let Some(transactions) = transactions {
+                writeln!(
+                    output,
+                    "# {} clients, {} transactions, {} locked",
+                    clients, transactions, locked
+                )?;
+            }
+            // the disputes form a second table with its own header
+            if let Some(disputes) = disputes {
+                writeln!(output)?;
+                let mut writer = csv::Writer::from_writer(&mut output);
+                for dispute in disputes {
+                    writer.serialize(dispute)?;
+                }
+                writer.flush()?;
+            }
+            output.flush()?;
+        }
+        Format::Json => {
+            let mut writer = io::BufWriter::new(output);
+            let mut serializer = serde_json::Serializer::pretty(&mut writer);
+            match disputes {
+                Some(disputes) => AuditOutput {
+                    accounts: snapshots.collect(),
+                    disputes,
+                }
+                .serialize(&mut serializer)?,
+                None => serializer.collect_seq(snapshots)?,
+            }
+            writeln!(writer)?;
+            writer.flush()?;
+        }
     }
-    writer.flush()?;
 
     Ok(())
 }
@@ -0,0 +1,231 @@
+//! # Multi Currency
+//!
+//! `multi_currency` is a module providing an accounts handler for multi-currency ledgers, keeping
+//! an independent account per client and currency.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    AccountSnapshot, AccountsHandler, ClientId, Currency, HandlerConfig, ProcessOutcome,
+    Transaction, TransactionError,
+};
+
+/// Snapshot of a client's account in a single currency
+#[derive(Debug, PartialEq, Eq)]
+pub struct CurrencySnapshot {
+    /// Currency of the account's balances
+    pub currency: Currency,
+    /// Snapshot of the account
+    pub snapshot: AccountSnapshot,
+}
+
+/// Accounts handler keeping a separate account per client and currency
+///
+/// Transactions are routed to the accounts handler of their currency, so a client's balances in
+/// different currencies are independent, and a transfer only moves funds within its currency.
+/// Txids stay unique across currencies, and a dispute, resolve or chargeback referencing a
+/// transaction in another currency is rejected.
+///
+/// ```
+/// use coding_test::{
+///     Amount, ClientId, Currency, MultiCurrencyHandler, ProcessOutcome, Transaction,
+///     TransactionType, Txid,
+/// };
+/// use rust_decimal::Decimal;
+///
+/// let usd: Currency = "USD".parse().unwrap();
+/// let mut handler = MultiCurrencyHandler::new();
+/// let tx = Transaction {
+///     tx_type: TransactionType::Deposit(Amount::new(Decimal::ONE).unwrap()),
+///     client_id: ClientId(1),
+///     txid: Txid(1),
+///     timestamp: None,
+/// };
+/// assert_eq!(handler.submit_transaction(usd, tx), Ok(ProcessOutcome::Applied));
+/// assert_eq!(handler.snapshot(ClientId(1), usd).unwrap().available, Decimal::ONE);
+/// ```
+#[derive(Default)]
+pub struct MultiCurrencyHandler {
+    /// Policy for handling transactions, shared by the handlers of every currency
+    config: HandlerConfig,
+    /// Map of currency to the handler of the accounts in it
+    handlers: BTreeMap<Currency, AccountsHandler>,
+}
+
+impl MultiCurrencyHandler {
+    /// Create a new multi-currency handler with the default configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new multi-currency handler following the given policy in every currency
+    pub fn with_config(config: HandlerConfig) -> Self {
+        Self {
+            config,
+            handlers: BTreeMap::new(),
+        }
+    }
+
+    /// Submit a transaction in `currency` to the client's account in it
+    ///
+    /// A deposit, withdrawal or transfer whose txid was consumed in another currency is rejected
+    /// as a duplicate, and a dispute, resolve or chargeback referencing it as in another currency.
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn submit_transaction(
+        &mut self,
+        currency: Currency,
+        tx: Transaction,
+    ) -> Result<ProcessOutcome, TransactionError> {
+        if let Some(owner) = self.txid_currency(&tx, currency) {
+            return Err(if tx.is_generative_tx() {
+                TransactionError::DuplicateTxid(tx.txid)
+            } else {
+                TransactionError::CurrencyMismatch {
+                    txid: tx.txid,
+                    owner,
+                    got: currency,
+                }
+            });
+        }
+        let config = self.config;
+        self.handlers
+            .entry(currency)
+            .or_insert_with(|| AccountsHandler::with_config(config))
+            .submit_transaction(tx)
+    }
+
+    /// Find the currency other than `currency` in which the txid of `tx` was consumed, if any
+    fn txid_currency(&self, tx: &Transaction, currency: Currency) -> Option<Currency> {
+        self.handlers
+            .iter()
+            .find(|(&other, handler)| other != currency && handler.has_txid(tx.txid))
+            .map(|(&other, _)| other)
+    }
+
+    /// Get the handler of the accounts in `currency`, if any transaction was submitted in it
+    pub fn handler(&self, currency: Currency) -> Option<&AccountsHandler> {
+        self.handlers.get(&currency)
+    }
+
+    /// Get the currencies transactions were submitted in, in ascending order
+    pub fn currencies(&self) -> Vec<Currency> {
+        self.handlers.keys().copied().collect()
+    }
+
+    /// Get a snapshot of the client's account in `currency`
+    pub fn snapshot(&self, client_id: ClientId, currency: Currency) -> Option<AccountSnapshot> {
+        self.handler(currency)?.snapshot(client_id)
+    }
+
+    /// Get snapshots of all accounts, sorted in ascending order of client id, then of currency
+    pub fn snapshot_accounts_sorted(&self) -> Vec<CurrencySnapshot> {
+        let mut snapshots: Vec<_> = self
+            .handlers
+            .iter()
+            .flat_map(|(&currency, handler)| {
+                handler
+                    .snapshots_iter()
+                    .map(move |snapshot| CurrencySnapshot { currency, snapshot })
+            })
+            .collect();
+        snapshots.sort_unstable_by_key(|snapshot| (snapshot.snapshot.id, snapshot.currency));
+        snapshots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{IgnoreReason, TransactionType, Txid};
+    use rust_decimal::Decimal;
+
+    fn tx(tx_type: TransactionType, txid: u64) -> Transaction {
+        Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn currencies_are_independent() {
+        let usd: Currency = "USD".parse().unwrap();
+        let eur: Currency = "EUR".parse().unwrap();
+        let mut handler = MultiCurrencyHandler::new();
+        for (currency, tx) in [
+            (
+                usd,
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+            ),
+            (
+                eur,
+                tx(TransactionType::Deposit(amount(Decimal::new(5, 0))), 2),
+            ),
+            (usd, tx(TransactionType::Dispute, 1)),
+            (
+                eur,
+                tx(TransactionType::Withdrawal(amount(Decimal::new(2, 0))), 3),
+            ),
+        ] {
+            assert_eq!(
+                handler.submit_transaction(currency, tx),
+                Ok(ProcessOutcome::Applied)
+            );
+        }
+        // withdrawals cannot draw on the balance of another currency
+        assert_eq!(
+            handler.submit_transaction(
+                eur,
+                tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 4)
+            ),
+            Ok(ProcessOutcome::Ignored(IgnoreReason::InsufficientFunds))
+        );
+
+        let snapshots = handler.snapshot_accounts_sorted();
+        assert_eq!(
+            snapshots
+                .iter()
+                .map(|snapshot| (
+                    snapshot.currency,
+                    snapshot.snapshot.available,
+                    snapshot.snapshot.held
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (eur, Decimal::new(3, 0), Decimal::ZERO),
+                (usd, Decimal::ZERO, Decimal::new(10, 0)),
+            ]
+        );
+        assert_eq!(handler.currencies(), vec![eur, usd]);
+    }
+
+    #[test]
+    fn cross_currency_rejected() {
+        let usd: Currency = "USD".parse().unwrap();
+        let eur: Currency = "EUR".parse().unwrap();
+        let mut handler = MultiCurrencyHandler::new();
+        assert_eq!(
+            handler.submit_transaction(usd, tx(TransactionType::Deposit(amount(Decimal::ONE)), 1)),
+            Ok(ProcessOutcome::Applied)
+        );
+        assert_eq!(
+            handler.submit_transaction(eur, tx(TransactionType::Deposit(amount(Decimal::ONE)), 1)),
+            Err(TransactionError::DuplicateTxid(Txid(1)))
+        );
+        assert_eq!(
+            handler.submit_transaction(eur, tx(TransactionType::Dispute, 1)),
+            Err(TransactionError::CurrencyMismatch {
+                txid: Txid(1),
+                owner: usd,
+                got: eur,
+            })
+        );
+        assert_eq!(handler.snapshot(ClientId(1), eur), None);
+        assert_eq!(
+            handler.snapshot(ClientId(1), usd).unwrap().held,
+            Decimal::ZERO
+        );
+    }
+}
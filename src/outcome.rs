@@ -0,0 +1,90 @@
+//! # Outcome
+//!
+//! `outcome` is a module describing the result of processing a transaction against an account.
+
+use std::fmt;
+
+use rust_decimal::Decimal;
+
+/// Outcome of processing a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProcessOutcome {
+    /// Transaction was applied to the account
+    Applied,
+    /// Transaction was ignored and left the account untouched
+    Ignored(IgnoreReason),
+    /// Transaction references a txid not seen yet and was buffered until it arrives
+    Buffered,
+    /// Transaction is identical to an earlier one with the same txid and was a no-op
+    Idempotent,
+    /// Withdrawal exceeding the available balance was applied for only the given amount, under
+    /// [`crate::WithdrawalMode::Saturating`]
+    PartiallyApplied(Decimal),
+}
+
+impl ProcessOutcome {
+    /// Check whether the transaction was applied to the account, even if only in part
+    pub fn is_applied(&self) -> bool {
+        matches!(self, Self::Applied | Self::PartiallyApplied(_))
+    }
+}
+
+/// Reason a transaction was ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum IgnoreReason {
+    /// Amount is zero or negative
+    NonPositiveAmount,
+    /// Amount has more decimal places than allowed
+    ExcessPrecision,
+    /// Available balance is too low to cover the transaction
+    InsufficientFunds,
+    /// Referenced txid was never used by any deposit, withdrawal or transfer
+    UnknownTxid,
+    /// Referenced txid is not a deposit or withdrawal on the account, e.g. it belongs to a transfer
+    /// or to an ignored transaction
+    NotADeposit,
+    /// Referenced transaction is already under dispute
+    AlreadyDisputed,
+    /// Referenced transaction is not under dispute
+    NotDisputed,
+    /// Referenced transaction was charged back and cannot be disputed again
+    ChargedBack,
+    /// Partially disputed amount exceeds the amount of the referenced transaction
+    ExceedsTransaction,
+    /// Partially resolved amount exceeds the amount held by the dispute of the referenced
+    /// transaction
+    ExceedsHeld,
+    /// Referenced transaction was reversed by an operator and no longer stands
+    Reversed,
+    /// Held balance is too low to cover the disputed amount
+    HeldInsufficient,
+    /// Dispute would hold more than was ever credited to the account
+    HeldWouldExceedDeposits,
+    /// Account is locked
+    LockedAccount,
+}
+
+impl fmt::Display for IgnoreReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use IgnoreReason::*;
+        let reason = match self {
+            NonPositiveAmount => "amount is not positive",
+            ExcessPrecision => "amount has too many decimal places",
+            InsufficientFunds => "insufficient available funds",
+            UnknownTxid => "no transaction with this txid",
+            NotADeposit => "no deposit or withdrawal with this txid on the account",
+            AlreadyDisputed => "transaction is already disputed",
+            NotDisputed => "transaction is not disputed",
+            ChargedBack => "transaction was charged back",
+            ExceedsTransaction => "disputed amount exceeds the transaction amount",
+            ExceedsHeld => "resolved amount exceeds the amount held by the dispute",
+            Reversed => "transaction was reversed",
+            HeldInsufficient => "insufficient held funds",
+            HeldWouldExceedDeposits => "held funds would exceed the funds ever deposited",
+            LockedAccount => "account is locked",
+        };
+        f.write_str(reason)
+    }
+}
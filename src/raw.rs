@@ -0,0 +1,440 @@
+//! # Raw
+//!
+//! `raw` is a module providing the serde representation of transactions read from external input,
+//! e.g. CSV or JSON.
+
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::{
+    Amount, ClientId, Currency, Transaction, TransactionType, Txid, ValidationConfig,
+    ValidationError,
+};
+
+/// Amount represented by the field `amount`, either a plain decimal or a decimal suffixed with a
+/// currency, e.g. `1.50 USD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "AmountRepr")]
+pub struct RawAmount {
+    pub value: Decimal,
+    pub currency: Option<Currency>,
+}
+
+/// Deserializable representation of the field `amount`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AmountRepr {
+    Plain(Decimal),
+    Suffixed(String),
+    /// Float that is not a decimal, e.g. `NaN` or `inf`, which CSV fields are inferred to be
+    NonFinite(f64),
+}
+
+impl TryFrom<AmountRepr> for RawAmount {
+    type Error = String;
+
+    fn try_from(repr: AmountRepr) -> Result<Self, Self::Error> {
+        match repr {
+            AmountRepr::Plain(value) => Ok(Self {
+                value,
+                currency: None,
+            }),
+            AmountRepr::Suffixed(s) => {
+                let (value, currency) = s
+                    .split_once(' ')
+                    .ok_or_else(|| format!("invalid amount: {}", s))?;
+                Ok(Self {
+                    value: value
+                        .parse()
+                        .map_err(|_| format!("invalid amount: {}", s))?,
+                    currency: Some(currency.trim().parse()?),
+                })
+            }
+            AmountRepr::NonFinite(value) => Err(format!("invalid amount: {}", value)),
+        }
+    }
+}
+
+/// Transaction type represented by the field `type`
+///
+/// In a record, the type is parsed from its keyword with [`RawTransactionType::from_keyword`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawTransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    #[serde(rename = "partial_dispute")]
+    PartialDispute,
+    Resolve,
+    #[serde(rename = "partial_resolve")]
+    PartialResolve,
+    Chargeback,
+}
+
+impl RawTransactionType {
+    /// Keywords of the transaction types, in declaration order
+    const KEYWORDS: &'static [&'static str] = &[
+        "deposit",
+        "withdrawal",
+        "dispute",
+        "partial_dispute",
+        "resolve",
+        "partial_resolve",
+        "chargeback",
+    ];
+
+    /// Parse a transaction type from its keyword, case-insensitively
+    ///
+    /// Besides the keywords of the types, `credit` is accepted for a deposit and `debit` and
+    /// `withdraw` for a withdrawal, as used by some feeds.
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        use RawTransactionType::*;
+        // compared without lowercasing, which would allocate for every row
+        [
+            ("deposit", Deposit),
+            ("credit", Deposit),
+            ("withdrawal", Withdrawal),
+            ("withdraw", Withdrawal),
+            ("debit", Withdrawal),
+            ("dispute", Dispute),
+            ("partial_dispute", PartialDispute),
+            ("resolve", Resolve),
+            ("partial_resolve", PartialResolve),
+            ("chargeback", Chargeback),
+        ]
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(keyword))
+        .map(|(_, tx_type)| tx_type)
+    }
+}
+
+/// Transaction represented by a record with the fields `type`, `client`, `tx` and `amount`, and
+/// optionally `timestamp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct RawTransaction {
+    #[serde(rename = "type", deserialize_with = "deserialize_tx_type")]
+    pub tx_type: RawTransactionType,
+    #[serde(deserialize_with = "deserialize_client")]
+    pub client: u16,
+    #[serde(rename = "tx")]
+    pub txid: u64,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub amount: Option<RawAmount>,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+/// Deserialize the field `type` from its keyword
+fn deserialize_tx_type<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<RawTransactionType, D::Error> {
+    let keyword = String::deserialize(deserializer)?;
+    RawTransactionType::from_keyword(&keyword)
+        .ok_or_else(|| de::Error::unknown_variant(&keyword, RawTransactionType::KEYWORDS))
+}
+
+/// Deserialize the field `amount`, treating an empty or blank string like a missing amount
+fn deserialize_amount<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RawAmount>, D::Error> {
+    match Option::<AmountRepr>::deserialize(deserializer)? {
+        Some(AmountRepr::Suffixed(s)) if s.trim().is_empty() => Ok(None),
+        Some(repr) => RawAmount::try_from(repr)
+            .map(Some)
+            .map_err(de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Deserialize an id described by `name`, reporting ids out of the range of `T` by name
+fn deserialize_id<'de, D, T>(deserializer: D, name: &str) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    let id = u64::deserialize(deserializer)?;
+    T::try_from(id).map_err(|_| {
+        de::Error::custom(format!(
+            "{} {} exceeds {} range",
+            name,
+            id,
+            std::any::type_name::<T>()
+        ))
+    })
+}
+
+/// Deserialize the field `client`
+fn deserialize_client<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    deserialize_id(deserializer, "client id")
+}
+
+// This abstraction of the two separate transaction types is necessary because of a limitation of
+// `csv::Deserialize` which does not allow to deserialize a field into an enum with heterogenous
+// variants. The best that can be done is
+// https://stackoverflow.com/questions/69417454/serialize-deserialize-csv-with-nested-enum-struct-with-serde-in-rust
+// but this only works for serializing and not for deserializing, as documented here:
+// github.com/BurntSushi/rust-csv/issues/211
+// Thus, we have to deserialize into a separate struct for each variant and then convert it into the
+// desired enum variant. This also allows us to do some basic validation of the data, including
+// checking decimal precision of `amount` against the configured maximum scale.
+impl RawTransaction {
+    /// Convert into a transaction, validating it against `config`
+    pub fn into_transaction(
+        self,
+        config: &ValidationConfig,
+    ) -> Result<Transaction, ValidationError> {
+        use RawTransactionType::*;
+        // a stray amount is ignored unless validation is strict
+        if config.strict
+            && self.amount.is_some()
+            && matches!(self.tx_type, Dispute | Resolve | Chargeback)
+        {
+            return Err(ValidationError::UnexpectedAmount);
+        }
+        let tx_type = match self.tx_type {
+            Deposit => TransactionType::Deposit(self.validate_generative_amount(config)?),
+            Withdrawal => TransactionType::Withdrawal(self.validate_generative_amount(config)?),
+            Dispute => TransactionType::Dispute,
+            PartialDispute => TransactionType::PartialDispute(self.validate_amount(config)?),
+            Resolve => TransactionType::Resolve,
+            PartialResolve => TransactionType::PartialResolve(self.validate_amount(config)?),
+            Chargeback => TransactionType::Chargeback,
+        };
+
+        Ok(Transaction {
+            tx_type,
+            client_id: ClientId(self.client),
+            txid: Txid(self.txid),
+            timestamp: self.timestamp,
+        })
+    }
+}
+
+impl RawTransaction {
+    /// Validate the amount of a deposit, withdrawal or partial dispute, including its currency
+    fn validate_amount(&self, config: &ValidationConfig) -> Result<Decimal, ValidationError> {
+        let value = config.validate_amount(self.amount.map(|amount| amount.value))?;
+        config.validate_currency(self.amount.and_then(|amount| amount.currency))?;
+        Ok(value)
+    }
+
+    /// Validate the amount of a deposit or withdrawal like [`RawTransaction::validate_amount`],
+    /// also rejecting a negative amount
+    fn validate_generative_amount(
+        &self,
+        config: &ValidationConfig,
+    ) -> Result<Amount, ValidationError> {
+        let value = self.validate_amount(config)?;
+        Ok(Amount::with_max_scale(value, config.max_scale)?)
+    }
+}
+
+impl TryFrom<RawTransaction> for Transaction {
+    type Error = ValidationError;
+
+    /// Convert into a transaction, validating it against the default [`ValidationConfig`]
+    fn try_from(raw: RawTransaction) -> Result<Self, Self::Error> {
+        raw.into_transaction(&ValidationConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+
+    /// Deserialize a raw transaction from `value` and convert it with the default validation
+    fn convert(value: serde_json::Value) -> Result<Transaction, ValidationError> {
+        serde_json::from_value::<RawTransaction>(value)
+            .unwrap()
+            .try_into()
+    }
+
+    #[test]
+    fn deserialize_each_type_works() {
+        for (tx_type, value, expected) in [
+            (
+                "deposit",
+                json!("1.5"),
+                TransactionType::Deposit(amount(dec!(1.5))),
+            ),
+            (
+                "withdrawal",
+                json!("2"),
+                TransactionType::Withdrawal(amount(dec!(2))),
+            ),
+            ("dispute", json!(null), TransactionType::Dispute),
+            (
+                "partial_dispute",
+                json!("0.5"),
+                TransactionType::PartialDispute(dec!(0.5)),
+            ),
+            ("resolve", json!(null), TransactionType::Resolve),
+            (
+                "partial_resolve",
+                json!("0.25"),
+                TransactionType::PartialResolve(dec!(0.25)),
+            ),
+            ("chargeback", json!(null), TransactionType::Chargeback),
+        ] {
+            let tx =
+                convert(json!({"type": tx_type, "client": 1, "tx": 2, "amount": value})).unwrap();
+            assert_eq!(tx.tx_type, expected);
+            assert_eq!(tx.client_id, ClientId(1));
+            assert_eq!(tx.txid, Txid(2));
+        }
+    }
+
+    #[test]
+    fn deserialize_missing_amount_fails() {
+        assert_eq!(
+            convert(json!({"type": "deposit", "client": 1, "tx": 2})).unwrap_err(),
+            ValidationError::MissingAmount
+        );
+        for amount in ["", "  "] {
+            assert_eq!(
+                convert(json!({"type": "deposit", "client": 1, "tx": 2, "amount": amount}))
+                    .unwrap_err(),
+                ValidationError::MissingAmount
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_excess_precision_fails() {
+        assert_eq!(
+            convert(json!({"type": "withdrawal", "client": 1, "tx": 2, "amount": "1.00001"}))
+                .unwrap_err(),
+            ValidationError::ExcessPrecision {
+                amount: dec!(1.00001),
+                max_scale: 4
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_negative_amount_fails() {
+        for tx_type in ["deposit", "withdrawal"] {
+            assert_eq!(
+                convert(json!({"type": tx_type, "client": 1, "tx": 2, "amount": "-1.5"}))
+                    .unwrap_err(),
+                ValidationError::NegativeAmount(dec!(-1.5))
+            );
+        }
+        // partial disputes are left to the handler's checks
+        assert_eq!(
+            convert(json!({"type": "partial_dispute", "client": 1, "tx": 2, "amount": "-1.5"}))
+                .unwrap()
+                .tx_type,
+            TransactionType::PartialDispute(dec!(-1.5))
+        );
+    }
+
+    #[test]
+    fn deserialize_currency_suffix_works() {
+        let usd = ValidationConfig {
+            currency: Some("USD".parse().unwrap()),
+            ..ValidationConfig::default()
+        };
+        let raw = |amount| {
+            serde_json::from_value::<RawTransaction>(
+                json!({"type": "deposit", "client": 1, "tx": 2, "amount": amount}),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            raw("1.50 USD").into_transaction(&usd).unwrap().tx_type,
+            TransactionType::Deposit(amount(dec!(1.50)))
+        );
+        assert_eq!(
+            raw("1.50 EUR").into_transaction(&usd).unwrap_err(),
+            ValidationError::CurrencyMismatch {
+                expected: "USD".parse().unwrap(),
+                got: Some("EUR".parse().unwrap())
+            }
+        );
+        assert_eq!(
+            Transaction::try_from(raw("1.50 USD")).unwrap_err(),
+            ValidationError::UnexpectedCurrency("USD".parse().unwrap())
+        );
+        assert!(serde_json::from_value::<RawTransaction>(
+            json!({"type": "deposit", "client": 1, "tx": 2, "amount": "1.50 usd"})
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn stray_amount_rejected_when_strict() {
+        let strict = ValidationConfig {
+            strict: true,
+            ..ValidationConfig::default()
+        };
+        let raw = serde_json::from_value::<RawTransaction>(
+            json!({"type": "dispute", "client": 1, "tx": 2, "amount": "1.0"}),
+        )
+        .unwrap();
+        assert_eq!(
+            Transaction::try_from(raw).unwrap().tx_type,
+            TransactionType::Dispute
+        );
+        assert_eq!(
+            raw.into_transaction(&strict).unwrap_err(),
+            ValidationError::UnexpectedAmount
+        );
+    }
+
+    #[test]
+    fn deserialize_out_of_range_ids_fails() {
+        let err = serde_json::from_value::<RawTransaction>(
+            json!({"type": "deposit", "client": 70000, "tx": 1, "amount": "1.0"}),
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "client id 70000 exceeds u16 range");
+    }
+
+    #[test]
+    fn deserialize_wide_txid_works() {
+        let tx =
+            convert(json!({"type": "deposit", "client": 1, "tx": 5000000000u64, "amount": "1.0"}));
+        assert_eq!(tx.unwrap().txid, Txid(5_000_000_000));
+    }
+
+    #[test]
+    fn from_keyword_works() {
+        use RawTransactionType::*;
+        for (keyword, expected) in [
+            ("deposit", Some(Deposit)),
+            ("DEPOSIT", Some(Deposit)),
+            ("Credit", Some(Deposit)),
+            ("withdraw", Some(Withdrawal)),
+            ("debit", Some(Withdrawal)),
+            ("Partial_Dispute", Some(PartialDispute)),
+            ("chargeback", Some(Chargeback)),
+            ("transfer", None),
+        ] {
+            assert_eq!(RawTransactionType::from_keyword(keyword), expected);
+        }
+    }
+
+    #[test]
+    fn from_keyword_matches_serde() {
+        for keyword in RawTransactionType::KEYWORDS {
+            let expected: RawTransactionType = serde_json::from_value(json!(keyword)).unwrap();
+            for keyword in [keyword.to_string(), keyword.to_ascii_uppercase()] {
+                assert_eq!(RawTransactionType::from_keyword(&keyword), Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_timestamp_works() {
+        let tx = convert(json!({"type": "deposit", "client": 1, "tx": 2, "amount": "1"})).unwrap();
+        assert_eq!(tx.timestamp, None);
+        let tx = convert(json!({"type": "deposit", "client": 1, "tx": 2, "amount": "1", "timestamp": 1700000000}))
+            .unwrap();
+        assert_eq!(tx.timestamp, Some(1700000000));
+    }
+}
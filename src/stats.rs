@@ -0,0 +1,176 @@
+//! # Stats
+//!
+//! `stats` is a module providing counters of the outcomes of submitted transactions.
+
+use std::{collections::BTreeMap, fmt};
+
+use crate::{IgnoreReason, ProcessOutcome, Transaction, TransactionError, TransactionType};
+
+/// Counts of the outcomes of submitted transactions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestionStats {
+    /// Applied deposits
+    pub deposits: usize,
+    /// Applied withdrawals
+    pub withdrawals: usize,
+    /// Applied transfers
+    pub transfers: usize,
+    /// Applied disputes
+    pub disputes: usize,
+    /// Applied resolves
+    pub resolves: usize,
+    /// Applied chargebacks
+    pub chargebacks: usize,
+    /// Ignored transactions by reason
+    pub ignored: BTreeMap<IgnoreReason, usize>,
+    /// Disputes, resolves and chargebacks buffered until the transaction they reference arrives
+    pub buffered: usize,
+    /// Identical resubmissions of earlier transactions, treated as no-ops
+    pub resubmissions: usize,
+    /// Transactions rejected for a duplicate txid
+    pub duplicate_txids: usize,
+    /// Transactions rejected for any other error
+    pub errors: usize,
+}
+
+impl IngestionStats {
+    /// Count the result of submitting `tx`
+    pub(crate) fn record(
+        &mut self,
+        tx: &Transaction,
+        result: &Result<ProcessOutcome, TransactionError>,
+    ) {
+        match result {
+            Ok(ProcessOutcome::Applied | ProcessOutcome::PartiallyApplied(_)) => {
+                use TransactionType::*;
+                let counter = match tx.tx_type {
+                    Deposit(_) => &mut self.deposits,
+                    Withdrawal(_) => &mut self.withdrawals,
+                    Transfer { .. } => &mut self.transfers,
+                    Dispute | PartialDispute(_) => &mut self.disputes,
+                    Resolve | PartialResolve(_) => &mut self.resolves,
+                    Chargeback => &mut self.chargebacks,
+                };
+                *counter += 1;
+            }
+            Ok(ProcessOutcome::Ignored(reason)) => *self.ignored.entry(*reason).or_default() += 1,
+            Ok(ProcessOutcome::Buffered) => self.buffered += 1,
+            Ok(ProcessOutcome::Idempotent) => self.resubmissions += 1,
+            Err(TransactionError::DuplicateTxid(_)) => self.duplicate_txids += 1,
+            Err(_) => self.errors += 1,
+        }
+    }
+
+    /// Get the number of transactions that were applied or ignored, leaving out those rejected,
+    /// buffered or resubmitted
+    pub fn processed(&self) -> usize {
+        self.deposits
+            + self.withdrawals
+            + self.transfers
+            + self.disputes
+            + self.resolves
+            + self.chargebacks
+            + self.ignored.values().sum::<usize>()
+    }
+
+    /// Add the counts of `other`
+    pub(crate) fn merge(&mut self, other: &Self) {
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.transfers += other.transfers;
+        self.disputes += other.disputes;
+        self.resolves += other.resolves;
+        self.chargebacks += other.chargebacks;
+        for (reason, count) in &other.ignored {
+            *self.ignored.entry(*reason).or_default() += count;
+        }
+        self.buffered += other.buffered;
+        self.resubmissions += other.resubmissions;
+        self.duplicate_txids += other.duplicate_txids;
+        self.errors += other.errors;
+    }
+}
+
+impl fmt::Display for IngestionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "applied: {} deposits, {} withdrawals, {} transfers, {} disputes, {} resolves, {} chargebacks",
+            self.deposits,
+            self.withdrawals,
+            self.transfers,
+            self.disputes,
+            self.resolves,
+            self.chargebacks
+        )?;
+        for (reason, count) in &self.ignored {
+            writeln!(f, "ignored: {} ({})", count, reason)?;
+        }
+        if self.buffered > 0 {
+            writeln!(f, "buffered: {}", self.buffered)?;
+        }
+        if self.resubmissions > 0 {
+            writeln!(
+                f,
+                "resubmitted: {} identical transactions",
+                self.resubmissions
+            )?;
+        }
+        write!(
+            f,
+            "rejected: {} duplicate txids, {} other errors",
+            self.duplicate_txids, self.errors
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{ClientId, Txid};
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn display_lists_every_counter() {
+        let mut stats = IngestionStats::default();
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        stats.record(&tx, &Ok(ProcessOutcome::Applied));
+        stats.record(
+            &tx,
+            &Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount)),
+        );
+        stats.record(&tx, &Err(TransactionError::DuplicateTxid(Txid(1))));
+        assert_eq!(
+            stats.to_string(),
+            "applied: 1 deposits, 0 withdrawals, 0 transfers, 0 disputes, 0 resolves, 0 chargebacks
+ignored: 1 (account is locked)
+rejected: 1 duplicate txids, 0 other errors"
+        );
+    }
+
+    #[test]
+    fn processed_counts_applied_and_ignored() {
+        let mut stats = IngestionStats::default();
+        let tx = Transaction {
+            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        };
+        stats.record(&tx, &Ok(ProcessOutcome::Applied));
+        stats.record(
+            &tx,
+            &Ok(ProcessOutcome::Ignored(IgnoreReason::LockedAccount)),
+        );
+        stats.record(&tx, &Ok(ProcessOutcome::Idempotent));
+        stats.record(&tx, &Err(TransactionError::DuplicateTxid(Txid(1))));
+        stats.record(&tx, &Err(TransactionError::SelfTransfer(ClientId(1))));
+        assert_eq!(stats.processed(), 2);
+    }
+}
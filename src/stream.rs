@@ -0,0 +1,93 @@
+//! # Stream
+//!
+//! `stream` is a module providing the ingestion of transactions from an asynchronous stream, e.g.
+//! in a Tokio-based service, built with the `async` feature.
+//!
+//! Transactions are submitted one at a time as they arrive, so only awaiting them is
+//! asynchronous. Any [`Stream`] of transactions can be ingested, e.g. one built with
+//! `futures::stream::iter` or a Tokio channel wrapped as a stream.
+
+use std::{future::poll_fn, pin::Pin};
+
+use futures_core::Stream;
+
+use crate::{AccountsHandler, IngestionStats, Transaction};
+
+impl AccountsHandler {
+    /// Submit the transactions of `stream` as they arrive, until it is exhausted, counting their
+    /// outcomes
+    ///
+    /// The handler is borrowed mutably until the stream is exhausted, so it cannot be shared
+    /// between tasks in the meantime. Tasks submitting concurrently should share a
+    /// [`crate::SyncAccountsHandler`] instead.
+    pub async fn ingest_stream<S: Stream<Item = Transaction> + Unpin>(
+        &mut self,
+        mut stream: S,
+    ) -> IngestionStats {
+        let mut stats = IngestionStats::default();
+        while let Some(tx) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+            let result = self.submit_transaction(tx);
+            stats.record(&tx, &result);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{ClientId, TransactionType, Txid};
+    use futures::{channel::mpsc, stream, SinkExt};
+    use rust_decimal::Decimal;
+
+    fn tx(tx_type: TransactionType, txid: u64) -> Transaction {
+        Transaction {
+            tx_type,
+            client_id: ClientId(1),
+            txid: Txid(txid),
+            timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn ingest_stream_submits_transactions() {
+        let mut handler = AccountsHandler::new();
+        let stats = handler
+            .ingest_stream(stream::iter([
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(3, 0))), 2),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(3, 0))), 2),
+            ]))
+            .await;
+        assert_eq!(
+            (stats.deposits, stats.withdrawals, stats.duplicate_txids),
+            (1, 1, 1)
+        );
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(7, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn ingest_stream_awaits_pending_transactions() {
+        let mut handler = AccountsHandler::new();
+        let (mut sender, receiver) = mpsc::channel(1);
+        let feed = tokio::spawn(async move {
+            for tx in [
+                tx(TransactionType::Deposit(amount(Decimal::new(10, 0))), 1),
+                tx(TransactionType::Withdrawal(amount(Decimal::new(4, 0))), 2),
+            ] {
+                sender.send(tx).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+        handler.ingest_stream(receiver).await;
+        feed.await.unwrap();
+        assert_eq!(
+            handler.snapshot(ClientId(1)).unwrap().available,
+            Decimal::new(6, 0)
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! # Sync Handler
+//!
+//! `sync_handler` is a module providing an accounts handler that can be shared between threads
+//! without external synchronization.
+
+use std::sync::{PoisonError, RwLock};
+
+use crate::{AccountSnapshot, AccountsHandler, ProcessOutcome, Transaction, TransactionError};
+
+/// Accounts handler behind a read-write lock, so that threads sharing it, e.g. through an `Arc`,
+/// can submit transactions and take snapshots through a shared reference
+///
+/// The lock covers the whole handler: submitting a transaction locks out every other submission
+/// and snapshot, while snapshots only lock out submissions. For higher throughput, partition the
+/// transactions by client and process each partition with its own handler, as
+/// [`AccountsHandler::process_parallel`] does, merging the handlers once done.
+///
+/// A thread panicking while holding the lock does not poison the handler, which the other threads
+/// keep using as that thread left it.
+#[derive(Default)]
+pub struct SyncAccountsHandler {
+    inner: RwLock<AccountsHandler>,
+}
+
+impl From<AccountsHandler> for SyncAccountsHandler {
+    fn from(handler: AccountsHandler) -> Self {
+        Self::new(handler)
+    }
+}
+
+impl SyncAccountsHandler {
+    /// Wrap `handler` for sharing between threads
+    pub fn new(handler: AccountsHandler) -> Self {
+        Self {
+            inner: RwLock::new(handler),
+        }
+    }
+
+    /// Submit a transaction like [`AccountsHandler::submit_transaction`], under the write lock
+    #[must_use = "a transaction may be rejected with an error, which should be handled"]
+    pub fn submit_transaction(&self, tx: Transaction) -> Result<ProcessOutcome, TransactionError> {
+        self.inner
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .submit_transaction(tx)
+    }
+
+    /// Get snapshots of all accounts like [`AccountsHandler::snapshot_accounts`], under the read
+    /// lock
+    pub fn snapshot_accounts(&self) -> Vec<AccountSnapshot> {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .snapshot_accounts()
+    }
+
+    /// Get snapshots of all accounts sorted in ascending order of client id, under the read lock
+    pub fn snapshot_accounts_sorted(&self) -> Vec<AccountSnapshot> {
+        self.inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .snapshot_accounts_sorted()
+    }
+
+    /// Unwrap the handler, e.g. once every thread is done with it
+    pub fn into_inner(self) -> AccountsHandler {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount;
+    use crate::{ClientId, TransactionType, Txid};
+    use rust_decimal::Decimal;
+    use std::thread;
+
+    #[test]
+    fn concurrent_submissions_work() {
+        let handler = SyncAccountsHandler::default();
+        thread::scope(|scope| {
+            for client_id in 1..=4u16 {
+                let handler = &handler;
+                scope.spawn(move || {
+                    for i in 0..100u64 {
+                        let tx = Transaction {
+                            tx_type: TransactionType::Deposit(amount(Decimal::ONE)),
+                            client_id: ClientId(client_id),
+                            // disjoint txids per client
+                            txid: Txid(u64::from(client_id) * 1000 + i),
+                            timestamp: None,
+                        };
+                        assert_eq!(handler.submit_transaction(tx), Ok(ProcessOutcome::Applied));
+                    }
+                    handler.snapshot_accounts();
+                });
+            }
+        });
+
+        let snapshots = handler.snapshot_accounts_sorted();
+        assert_eq!(snapshots.len(), 4);
+        for (snapshot, client_id) in snapshots.iter().zip(1..) {
+            assert_eq!(snapshot.id, ClientId(client_id));
+            assert_eq!(snapshot.total, Decimal::new(100, 0));
+        }
+        assert_eq!(handler.into_inner().sequence(), 400);
+    }
+}
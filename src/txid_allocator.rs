@@ -0,0 +1,66 @@
+//! # Txid Allocator
+//!
+//! `txid_allocator` is a module providing the allocation of txids to transactions constructed by
+//! the library consumer rather than read from a file.
+
+use crate::Txid;
+
+/// Allocator handing out monotonically increasing txids, starting at 1 by default
+///
+/// The allocator does not know which txids were consumed, so mixing allocated txids with those
+/// assigned by hand may still collide. [`crate::AccountsHandler::deposit`] and its siblings skip
+/// the allocated txids already consumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TxidAllocator {
+    /// Next txid to allocate
+    next: u64,
+}
+
+impl Default for TxidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxidAllocator {
+    /// Create a new allocator starting at txid 1
+    pub fn new() -> Self {
+        Self::starting_at(Txid(1))
+    }
+
+    /// Create a new allocator starting at `txid`, e.g. past the txids of an earlier feed
+    pub fn starting_at(txid: Txid) -> Self {
+        Self { next: txid.0 }
+    }
+
+    /// Allocate the next txid
+    ///
+    /// Panics if every txid was allocated.
+    pub fn allocate(&mut self) -> Txid {
+        let txid = Txid(self.next);
+        self.next = self.next.checked_add(1).expect("txids are not exhausted");
+        txid
+    }
+
+    /// Get the next txid to allocate, without allocating it
+    pub fn peek(&self) -> Txid {
+        Txid(self.next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocate_increases_monotonically() {
+        let mut allocator = TxidAllocator::new();
+        assert_eq!(allocator.allocate(), Txid(1));
+        assert_eq!(allocator.allocate(), Txid(2));
+        assert_eq!(allocator.peek(), Txid(3));
+
+        let mut allocator = TxidAllocator::starting_at(Txid(100));
+        assert_eq!(allocator.allocate(), Txid(100));
+        assert_eq!(allocator.peek(), Txid(101));
+    }
+}
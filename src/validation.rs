@@ -0,0 +1,181 @@
+//! # Validation
+//!
+//! `validation` is a module providing the policy for validating transactions read from external
+//! input.
+
+use std::{fmt, str::FromStr};
+
+use rust_decimal::Decimal;
+
+use crate::ValidationError;
+
+/// Three-letter currency code, e.g. `USD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency([u8; 3]);
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[a, b, c] if s.bytes().all(|b| b.is_ascii_uppercase()) => Ok(Self([a, b, c])),
+            _ => Err(format!("invalid currency code: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // only ever constructed from ASCII uppercase letters
+        f.write_str(std::str::from_utf8(&self.0).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// Policy for validating transactions read from external input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// Maximum number of decimal places accepted in amounts
+    pub max_scale: u32,
+    /// Currency every amount must be suffixed with, e.g. `1.50 USD`, or `None` if amounts must be
+    /// plain decimals
+    pub currency: Option<Currency>,
+    /// Reject disputes, resolves and chargebacks carrying an amount, rather than ignoring it
+    pub strict: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            max_scale: 4,
+            currency: None,
+            strict: false,
+        }
+    }
+}
+
+impl ValidationConfig {
+    /// Validate the amount of a deposit or withdrawal, which must be present and must not exceed
+    /// the maximum scale
+    pub fn validate_amount(&self, amount: Option<Decimal>) -> Result<Decimal, ValidationError> {
+        let amount = amount.ok_or(ValidationError::MissingAmount)?;
+        if amount.scale() > self.max_scale {
+            return Err(ValidationError::ExcessPrecision {
+                amount,
+                max_scale: self.max_scale,
+            });
+        }
+        Ok(amount)
+    }
+
+    /// Validate the currency an amount is suffixed with, which must match the configured currency
+    pub fn validate_currency(&self, currency: Option<Currency>) -> Result<(), ValidationError> {
+        match (self.currency, currency) {
+            (None, None) => Ok(()),
+            (None, Some(got)) => Err(ValidationError::UnexpectedCurrency(got)),
+            (Some(expected), Some(got)) if expected == got => Ok(()),
+            (Some(expected), got) => Err(ValidationError::CurrencyMismatch { expected, got }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn default_max_scale_is_four() {
+        let config = ValidationConfig::default();
+        assert_eq!(config.validate_amount(Some(dec!(1.2345))), Ok(dec!(1.2345)));
+        assert_eq!(
+            config.validate_amount(Some(dec!(1.23456))),
+            Err(ValidationError::ExcessPrecision {
+                amount: dec!(1.23456),
+                max_scale: 4
+            })
+        );
+    }
+
+    #[test]
+    fn max_scale_two_works() {
+        let config = ValidationConfig {
+            max_scale: 2,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(config.validate_amount(Some(dec!(1.23))), Ok(dec!(1.23)));
+        assert_eq!(
+            config.validate_amount(Some(dec!(1.234))),
+            Err(ValidationError::ExcessPrecision {
+                amount: dec!(1.234),
+                max_scale: 2
+            })
+        );
+    }
+
+    #[test]
+    fn max_scale_six_works() {
+        let config = ValidationConfig {
+            max_scale: 6,
+            ..ValidationConfig::default()
+        };
+        assert_eq!(
+            config.validate_amount(Some(dec!(1.234567))),
+            Ok(dec!(1.234567))
+        );
+        assert_eq!(
+            config.validate_amount(Some(dec!(1.2345678))),
+            Err(ValidationError::ExcessPrecision {
+                amount: dec!(1.2345678),
+                max_scale: 6
+            })
+        );
+    }
+
+    #[test]
+    fn missing_amount_fails() {
+        let config = ValidationConfig::default();
+        assert_eq!(
+            config.validate_amount(None),
+            Err(ValidationError::MissingAmount)
+        );
+    }
+
+    #[test]
+    fn currency_validated() {
+        let usd: Currency = "USD".parse().unwrap();
+        let eur: Currency = "EUR".parse().unwrap();
+        let config = ValidationConfig::default();
+        assert_eq!(config.validate_currency(None), Ok(()));
+        assert_eq!(
+            config.validate_currency(Some(usd)),
+            Err(ValidationError::UnexpectedCurrency(usd))
+        );
+        let config = ValidationConfig {
+            currency: Some(usd),
+            ..ValidationConfig::default()
+        };
+        assert_eq!(config.validate_currency(Some(usd)), Ok(()));
+        assert_eq!(
+            config.validate_currency(Some(eur)),
+            Err(ValidationError::CurrencyMismatch {
+                expected: usd,
+                got: Some(eur)
+            })
+        );
+        assert_eq!(
+            config.validate_currency(None),
+            Err(ValidationError::CurrencyMismatch {
+                expected: usd,
+                got: None
+            })
+        );
+    }
+
+    #[test]
+    fn currency_parsing_works() {
+        assert_eq!("USD".parse::<Currency>().unwrap().to_string(), "USD");
+        assert!("usd".parse::<Currency>().is_err());
+        assert!("US".parse::<Currency>().is_err());
+        assert!("USDT".parse::<Currency>().is_err());
+    }
+}
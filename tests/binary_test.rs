@@ -0,0 +1,53 @@
+#![cfg(feature = "binary")]
+
+use coding_test::{
+    read_binary_transactions, write_binary_transaction, AccountsHandler, BinaryTransaction,
+    RawTransactionType, ValidationConfig,
+};
+use rust_decimal_macros::dec;
+
+const TRANSACTIONS: &str = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0
+dispute,1,3,
+chargeback,1,3,
+";
+
+#[test]
+fn binary_ingestion_matches_csv() {
+    let mut stream = Vec::new();
+    for (tx_type, client, txid, amount) in [
+        (RawTransactionType::Deposit, 1, 1, Some(dec!(1.0))),
+        (RawTransactionType::Deposit, 2, 2, Some(dec!(2.0))),
+        (RawTransactionType::Deposit, 1, 3, Some(dec!(2.0))),
+        (RawTransactionType::Withdrawal, 1, 4, Some(dec!(1.5))),
+        (RawTransactionType::Withdrawal, 2, 5, Some(dec!(3.0))),
+        (RawTransactionType::Dispute, 1, 3, None),
+        (RawTransactionType::Chargeback, 1, 3, None),
+    ] {
+        let tx = BinaryTransaction {
+            tx_type,
+            client,
+            txid,
+            amount,
+        };
+        write_binary_transaction(&mut stream, &tx).unwrap();
+    }
+
+    let mut binary = AccountsHandler::new();
+    read_binary_transactions(&stream[..], &ValidationConfig::default(), |_, tx| {
+        binary.submit_transaction(tx.unwrap()).ok();
+    })
+    .unwrap();
+    let mut csv = AccountsHandler::new();
+    csv.ingest_csv(TRANSACTIONS.as_bytes()).unwrap();
+
+    assert_eq!(
+        binary.snapshot_accounts_sorted(),
+        csv.snapshot_accounts_sorted()
+    );
+    assert_eq!(binary.stats(), csv.stats());
+}
@@ -0,0 +1,1267 @@
+use std::{
+    ffi::OsStr,
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use tempfile::NamedTempFile;
+
+/// Write `contents` to a temporary transactions file
+fn transactions_file(contents: &str) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(contents.as_bytes()).unwrap();
+    file
+}
+
+/// Run the CLI with the given arguments, returning its output
+fn run_output<I, S>(args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(env!("CARGO_BIN_EXE_coding-test"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Run the CLI with the given arguments, returning its stdout
+fn run<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = run_output(args);
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Run the CLI with the given arguments, feeding `stdin` to it and returning its stdout
+fn run_with_stdin<I, S>(args: I, stdin: &str) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut child = Command::new(env!("CARGO_BIN_EXE_coding-test"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+const TRANSACTIONS: &str = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0
+";
+
+const ACCOUNTS: &str = "client,available,held,total,locked,disputed_count
+1,1.5000,0.0000,1.5000,false,0
+2,2.0000,0.0000,2.0000,false,0
+";
+
+#[test]
+fn file_input_works() {
+    let file = transactions_file(TRANSACTIONS);
+    assert_eq!(run([file.path()]), ACCOUNTS);
+}
+
+#[test]
+fn stdin_input_works() {
+    assert_eq!(run_with_stdin(None::<&OsStr>, TRANSACTIONS), ACCOUNTS);
+    assert_eq!(run_with_stdin(["-"], TRANSACTIONS), ACCOUNTS);
+}
+
+#[test]
+fn output_rounded_to_four_places() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.00005
+deposit,1,2,0.3333
+deposit,1,3,0.3333
+deposit,1,4,0.3333
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,0.9999,0.0000,0.9999,false,0
+"
+    );
+}
+
+#[test]
+fn precision_configurable() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,0.3333
+deposit,1,2,0.3333
+deposit,1,3,0.3333
+deposit,2,4,0.125
+",
+    );
+    assert_eq!(
+        run([
+            OsStr::new("--precision"),
+            OsStr::new("2"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,1.00,0.00,1.00,false,0
+2,0.12,0.00,0.12,false,0
+"
+    );
+}
+
+#[test]
+fn verbose_reports_skipped_rows() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2
+deposit,1,3,2.0
+",
+    );
+    let output = run_output([OsStr::new("--verbose"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{}: line 3 (byte 38): skipped row: {}\n{}\n{}\n",
+            file.path().display(),
+            "amount is required for deposit/withdraw transactions",
+            "applied: 2 deposits, 0 withdrawals, 0 transfers, 0 disputes, 0 resolves, 0 chargebacks",
+            "rejected: 0 duplicate txids, 0 other errors"
+        )
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,3.0000,0.0000,3.0000,false,0
+"
+    );
+
+    let output = run_output([file.path()]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn whitespace_padded_fields_work() {
+    let file = transactions_file(
+        "type, client, tx, amount
+deposit, 1, 1, 1.0
+  deposit ,2 , 2 ,  2.0
+deposit, 1, 3, 2.0
+withdrawal, 1, 4, 1.5
+withdrawal, 2, 5, 3.0
+",
+    );
+    assert_eq!(run([file.path()]), ACCOUNTS);
+}
+
+#[test]
+fn headerless_input_works() {
+    let file = transactions_file(
+        "deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0
+dispute,1,3
+dispute,2,2,
+",
+    );
+    let with_header = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,2.0
+withdrawal,1,4,1.5
+withdrawal,2,5,3.0
+dispute,1,3
+dispute,2,2,
+",
+    );
+    assert_eq!(
+        run([OsStr::new("--no-header"), file.path().as_os_str()]),
+        run([with_header.path()])
+    );
+    assert_eq!(
+        run([with_header.path()]),
+        "client,available,held,total,locked,disputed_count
+1,1.5000,0.0000,1.5000,false,0
+2,0.0000,2.0000,2.0000,false,1
+"
+    );
+}
+
+#[test]
+fn stray_amount_on_dispute_ignored_unless_strict() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+dispute,1,1,abc
+deposit,2,2,2.0
+dispute,2,2,2.0
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,0.0000,1.0000,1.0000,false,1
+2,0.0000,2.0000,2.0000,false,1
+"
+    );
+    assert_eq!(
+        run([OsStr::new("--strict"), file.path().as_os_str()]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+2,2.0000,0.0000,2.0000,false,0
+"
+    );
+
+    let output = run_output([
+        OsStr::new("--strict"),
+        OsStr::new("--check"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 5 (byte 70): amount is not allowed for dispute/resolve/chargeback transactions",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn out_of_range_client_reported() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,70000,1,1.0
+",
+    );
+    let output = run_output([OsStr::new("--check"), file.path().as_os_str()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 2 (byte 22): client id 70000 exceeds u16 range\n",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn strict_locked_aborts_on_locked_account() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,2.0
+dispute,1,1,
+chargeback,1,1,
+withdrawal,1,3,1.0
+deposit,2,4,1.0
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,2.0000,0.0000,2.0000,true,0
+2,1.0000,0.0000,1.0000,false,0
+"
+    );
+
+    let output = run_output([OsStr::new("--strict-locked"), file.path().as_os_str()]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 6 (byte 83): account 1 is locked",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn buffer_disputes_replays_early_disputes() {
+    let file = transactions_file(
+        "type,client,tx,amount
+dispute,1,1,
+deposit,1,1,1.0
+dispute,2,2,
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+2,0.0000,0.0000,0.0000,false,0
+"
+    );
+    let output = run_output([
+        OsStr::new("--buffer-disputes"),
+        OsStr::new("--verbose"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,0.0000,1.0000,1.0000,false,1
+2,0.0000,0.0000,0.0000,false,0
+"
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with(
+        "skipped buffered transaction for client 2 referencing txid 2: ignored: no transaction with this txid\n"
+    ));
+}
+
+#[test]
+fn multiple_files_share_accounts() {
+    let first = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+",
+    );
+    let second = transactions_file(
+        "type,client,tx,amount
+withdrawal,1,3,0.5
+deposit,2,1,5.0
+dispute,2,2,
+",
+    );
+    assert_eq!(
+        run([first.path(), second.path()]),
+        "client,available,held,total,locked,disputed_count
+1,0.5000,0.0000,0.5000,false,0
+2,0.0000,2.0000,2.0000,false,1
+"
+    );
+}
+
+#[test]
+fn output_file_works() {
+    let file = transactions_file(TRANSACTIONS);
+    let output = NamedTempFile::new().unwrap();
+    std::fs::write(
+        output.path(),
+        "stale contents that are longer than the accounts output\n".repeat(10),
+    )
+    .unwrap();
+    for format in ["csv", "json"] {
+        let stdout = run([
+            OsStr::new("--output"),
+            output.path().as_os_str(),
+            OsStr::new("--format"),
+            OsStr::new(format),
+            file.path().as_os_str(),
+        ]);
+        assert!(stdout.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(output.path()).unwrap(),
+            run([
+                OsStr::new("--format"),
+                OsStr::new(format),
+                file.path().as_os_str()
+            ])
+        );
+    }
+
+    let output = run_output([
+        OsStr::new("--output"),
+        OsStr::new("does-not-exist/accounts.csv"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Failed to create output file does-not-exist/accounts.csv"));
+}
+
+#[test]
+fn missing_file_reported() {
+    let file = transactions_file(TRANSACTIONS);
+    let output = run_output([file.path(), Path::new("does-not-exist.csv")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Failed to read transactions from does-not-exist.csv"));
+}
+
+#[test]
+fn json_format_works() {
+    let file = transactions_file(TRANSACTIONS);
+    let output = run([
+        OsStr::new("--format"),
+        OsStr::new("json"),
+        file.path().as_os_str(),
+    ]);
+    let snapshots: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(
+        snapshots,
+        serde_json::json!([
+            {
+                "client": 1,
+                "available": "1.5000",
+                "held": "0.0000",
+                "total": "1.5000",
+                "locked": false,
+                "disputed_count": 0
+            },
+            {
+                "client": 2,
+                "available": "2.0000",
+                "held": "0.0000",
+                "total": "2.0000",
+                "locked": false,
+                "disputed_count": 0
+            }
+        ])
+    );
+}
+
+#[test]
+fn max_scale_configurable() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.25
+deposit,1,2,1.125
+",
+    );
+    assert_eq!(
+        run([
+            OsStr::new("--max-scale"),
+            OsStr::new("2"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,1.2500,0.0000,1.2500,false,0
+"
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,2.3750,0.0000,2.3750,false,0
+"
+    );
+}
+
+#[test]
+fn currency_suffix_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.50 USD
+deposit,1,2,2.00 EUR
+deposit,1,3,0.25
+",
+    );
+    assert_eq!(
+        run([
+            OsStr::new("--currency"),
+            OsStr::new("USD"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,1.5000,0.0000,1.5000,false,0
+"
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,0.2500,0.0000,0.2500,false,0
+"
+    );
+}
+
+#[test]
+fn gzip_input_works() {
+    let mut file = tempfile::Builder::new()
+        .suffix(".csv.gz")
+        .tempfile()
+        .unwrap();
+    let mut encoder = GzEncoder::new(file.as_file_mut(), Compression::default());
+    encoder.write_all(TRANSACTIONS.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+    assert_eq!(run([file.path()]), ACCOUNTS);
+}
+
+#[test]
+fn check_validates_without_output() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2
+withdrawal,1,3,0.5
+transfer,1,4,1.0
+deposit,1,5,1.00001
+",
+    );
+    let output = run_output([OsStr::new("--check"), file.path().as_os_str()]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let path = file.path().display();
+    assert!(stderr.starts_with(&format!(
+        "2 valid rows, 3 invalid rows
+{path}: line 3 (byte 38): amount is required for deposit/withdraw transactions
+{path}: line 5 (byte 69): unknown variant `transfer`"
+    )));
+    assert!(stderr.contains(&format!(
+        "{path}: line 6 (byte 86): amount 1.00001 has more than 4 decimal places\n"
+    )));
+
+    let file = transactions_file(TRANSACTIONS);
+    let output = run_output([OsStr::new("--check"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "5 valid rows, 0 invalid rows\n"
+    );
+}
+
+#[test]
+fn audit_lists_disputes() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+deposit,1,3,3.0
+dispute,1,3,
+dispute,2,2,
+",
+    );
+    let output = run([OsStr::new("--audit"), file.path().as_os_str()]);
+    assert_eq!(
+        output,
+        "client,available,held,total,locked,disputed_count
+1,1.0000,3.0000,4.0000,false,1
+2,0.0000,2.0000,2.0000,false,1
+
+client,tx,held
+1,3,3.0000
+2,2,2.0000
+"
+    );
+
+    let output = run([
+        OsStr::new("--audit"),
+        OsStr::new("--format"),
+        OsStr::new("json"),
+        file.path().as_os_str(),
+    ]);
+    let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(
+        output["disputes"],
+        serde_json::json!([
+            { "client": 1, "tx": 3, "held": "3.0000" },
+            { "client": 2, "tx": 2, "held": "2.0000" }
+        ])
+    );
+    assert_eq!(output["accounts"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn eight_decimal_places_work() {
+    let mut contents = String::from("type,client,tx,amount\n");
+    for txid in 1..=8 {
+        contents.push_str(&format!("deposit,1,{},0.00000001\n", txid));
+    }
+    let file = transactions_file(&contents);
+    assert_eq!(
+        run([
+            OsStr::new("--max-scale"),
+            OsStr::new("8"),
+            OsStr::new("--precision"),
+            OsStr::new("8"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,0.00000008,0.00000000,0.00000008,false,0
+"
+    );
+    // the precision defaults to the maximum scale
+    assert_eq!(
+        run([
+            OsStr::new("--max-scale"),
+            OsStr::new("8"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,0.00000008,0.00000000,0.00000008,false,0
+"
+    );
+    // but no lower than 4 places
+    let file = transactions_file("type,client,tx,amount\ndeposit,1,1,0.01\n");
+    assert_eq!(
+        run([
+            OsStr::new("--max-scale"),
+            OsStr::new("2"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,0.0100,0.0000,0.0100,false,0
+"
+    );
+}
+
+#[test]
+fn fail_fast_aborts_on_first_invalid_row() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,2.0
+deposit,1,3,abc
+withdrawal,1,4,
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,3.0000,0.0000,3.0000,false,0
+"
+    );
+
+    let output = run_output([
+        OsStr::new("--fail-fast"),
+        OsStr::new("--verbose"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains(&format!("{}: line 4 (byte 54): ", file.path().display())));
+    // rows after the invalid one are not processed
+    assert!(!stderr.contains("line 5"));
+}
+
+#[test]
+fn header_style_works() {
+    let file = transactions_file(TRANSACTIONS);
+    let output = run([
+        OsStr::new("--header-style"),
+        OsStr::new("snake"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.starts_with("client_id,available,held,total,locked,disputed_count\n1,"));
+
+    let output = run([
+        OsStr::new("--header-style"),
+        OsStr::new("custom:id,free,frozen,sum,locked,disputes"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.starts_with("id,free,frozen,sum,locked,disputes\n1,"));
+
+    let output = run_output([
+        OsStr::new("--header-style"),
+        OsStr::new("custom:id"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn open_disputes_reported() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,2.0
+dispute,1,1,
+dispute,1,2,
+resolve,1,1,
+",
+    );
+    let output = run_output([OsStr::new("--open-disputes"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "open dispute for client 1 of txid 2 holding 2.0000\n"
+    );
+}
+
+#[test]
+fn empty_amount_reported_as_missing() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,
+deposit,1,2,1.0
+",
+    );
+    let output = run_output([OsStr::new("--verbose"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+"
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 2 (byte 22): skipped row: amount is required for deposit/withdraw transactions",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn non_numeric_amount_reported() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,abc
+deposit,1,2,NaN
+deposit,1,3,1.0
+",
+    );
+    let output = run_output([OsStr::new("--verbose"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let path = file.path().display();
+    assert!(stderr.contains(&format!(
+        "{path}: line 2 (byte 22): skipped row: invalid amount: abc\n"
+    )));
+    assert!(stderr.contains(&format!(
+        "{path}: line 3 (byte 38): skipped row: invalid amount: NaN\n"
+    )));
+}
+
+#[test]
+fn checkpoint_every_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+deposit,1,3,1.0
+deposit,1,4,1.0
+deposit,1,5,1.0
+",
+    );
+    let checkpoints = NamedTempFile::new().unwrap();
+    let output = run([
+        OsStr::new("--checkpoint-every"),
+        OsStr::new("2"),
+        OsStr::new("--checkpoint-output"),
+        checkpoints.path().as_os_str(),
+        file.path().as_os_str(),
+    ]);
+    assert_eq!(
+        output,
+        "client,available,held,total,locked,disputed_count
+1,5.0000,0.0000,5.0000,false,0
+"
+    );
+    assert_eq!(
+        std::fs::read_to_string(checkpoints.path()).unwrap(),
+        "checkpoint after transaction 2
+client,available,held,total,locked,disputed_count
+1,2.0000,0.0000,2.0000,false,0
+checkpoint after transaction 4
+client,available,held,total,locked,disputed_count
+1,4.0000,0.0000,4.0000,false,0
+"
+    );
+}
+
+#[test]
+fn comment_and_blank_lines_skipped() {
+    let file = transactions_file(
+        "type,client,tx,amount
+# opening deposits
+deposit,1,1,1.0
+
+deposit,1,2,2.0
+   
+# closing withdrawal
+withdrawal,1,3,0.5
+",
+    );
+    let output = run_output([
+        OsStr::new("--comment-char"),
+        OsStr::new("#"),
+        OsStr::new("--verbose"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,2.5000,0.0000,2.5000,false,0
+"
+    );
+    assert!(!String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("skipped row"));
+}
+
+#[cfg(feature = "binary")]
+#[test]
+fn bincode_input_format_works() {
+    use coding_test::{write_binary_transaction, BinaryTransaction, RawTransactionType};
+    use rust_decimal_macros::dec;
+
+    let mut file = NamedTempFile::new().unwrap();
+    for (tx_type, client, txid, amount) in [
+        (RawTransactionType::Deposit, 1, 1, dec!(1.0)),
+        (RawTransactionType::Deposit, 2, 2, dec!(2.0)),
+        (RawTransactionType::Deposit, 1, 3, dec!(2.0)),
+        (RawTransactionType::Withdrawal, 1, 4, dec!(1.5)),
+        (RawTransactionType::Withdrawal, 2, 5, dec!(3.0)),
+    ] {
+        let tx = BinaryTransaction {
+            tx_type,
+            client,
+            txid,
+            amount: Some(amount),
+        };
+        write_binary_transaction(&mut file, &tx).unwrap();
+    }
+    assert_eq!(
+        run([
+            OsStr::new("--input-format"),
+            OsStr::new("bincode"),
+            file.path().as_os_str()
+        ]),
+        ACCOUNTS
+    );
+}
+
+/// Transactions repeating txid 1 on line 3 (byte 38)
+const DUPLICATE_TRANSACTIONS: &str = "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,1,5.0
+deposit,2,2,2.0
+";
+
+#[test]
+fn on_duplicate_error_works() {
+    let file = transactions_file(DUPLICATE_TRANSACTIONS);
+    let output = run_output([
+        OsStr::new("--on-duplicate"),
+        OsStr::new("error"),
+        OsStr::new("--verbose"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 3 (byte 38): skipped row: duplicate txid: 1",
+        file.path().display()
+    )));
+
+    let output = run_output([
+        OsStr::new("--on-duplicate"),
+        OsStr::new("error"),
+        OsStr::new("--fail-fast"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn on_duplicate_skip_works() {
+    let file = transactions_file(DUPLICATE_TRANSACTIONS);
+    let output = run_output([
+        OsStr::new("--on-duplicate"),
+        OsStr::new("skip"),
+        OsStr::new("--verbose"),
+        OsStr::new("--fail-fast"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+2,2.0000,0.0000,2.0000,false,0
+"
+    );
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("line 3"));
+}
+
+#[test]
+fn on_duplicate_warn_works() {
+    let file = transactions_file(DUPLICATE_TRANSACTIONS);
+    let output = run_output([
+        OsStr::new("--on-duplicate"),
+        OsStr::new("warn"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{}: line 3 (byte 38): warning: dropped duplicate txid 1 for client 2\n",
+            file.path().display()
+        )
+    );
+}
+
+#[test]
+fn process_subcommand_works() {
+    let file = transactions_file(TRANSACTIONS);
+    assert_eq!(
+        run([OsStr::new("process"), file.path().as_os_str()]),
+        ACCOUNTS
+    );
+    // without a subcommand, transactions are processed too
+    assert_eq!(run([file.path()]), ACCOUNTS);
+}
+
+#[test]
+fn validate_subcommand_works() {
+    let file = transactions_file(TRANSACTIONS);
+    let output = run_output([OsStr::new("validate"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "5 valid rows, 0 invalid rows\n"
+    );
+
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,abc
+",
+    );
+    let output = run_output([OsStr::new("validate"), file.path().as_os_str()]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn max_rows_aborts() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+deposit,1,3,1.0
+deposit,1,4,1.0
+deposit,1,5,1.0
+",
+    );
+    let output = run_output([
+        OsStr::new("--max-rows"),
+        OsStr::new("3"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with(&format!(
+            "Error: {}: line 5 (byte 70): exceeded the limit of 3 rows set by --max-rows\n",
+            file.path().display()
+        )));
+
+    // the limit itself may be reached
+    let output = run_output([
+        OsStr::new("--max-rows"),
+        OsStr::new("5"),
+        file.path().as_os_str(),
+    ]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn max_bytes_aborts() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+deposit,1,3,1.0
+",
+    );
+    let output = run_output([
+        OsStr::new("--max-bytes"),
+        OsStr::new("40"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 4 (byte 54): exceeded the limit of 40 bytes set by --max-bytes",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn delimiter_works() {
+    let file = transactions_file(&TRANSACTIONS.replace(',', "\t"));
+    assert_eq!(
+        run([
+            OsStr::new("--delimiter"),
+            OsStr::new("\\t"),
+            file.path().as_os_str()
+        ]),
+        ACCOUNTS
+    );
+
+    let output = run_output([
+        OsStr::new("--delimiter"),
+        OsStr::new("::"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn include_empty_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,1,2.0
+",
+    );
+    let roster = transactions_file("3\n\n1\n");
+    let output = run([
+        OsStr::new("--include-empty"),
+        OsStr::new("--roster"),
+        roster.path().as_os_str(),
+        file.path().as_os_str(),
+    ]);
+    // client 2 is referenced only by a duplicate txid, and client 3 only by the roster
+    assert_eq!(
+        output,
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+2,0.0000,0.0000,0.0000,false,0
+3,0.0000,0.0000,0.0000,false,0
+"
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+"
+    );
+
+    let roster = transactions_file("x\n");
+    let output = run_output([
+        OsStr::new("--include-empty"),
+        OsStr::new("--roster"),
+        roster.path().as_os_str(),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains(":1: invalid client id \"x\""));
+}
+
+#[test]
+fn disputes_file_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,2.0
+",
+    );
+    let disputes = transactions_file(
+        "type,client,tx,amount
+dispute,1,2,
+",
+    );
+    assert_eq!(
+        run([
+            OsStr::new("--disputes-file"),
+            disputes.path().as_os_str(),
+            file.path().as_os_str(),
+        ]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,2.0000,3.0000,false,1
+"
+    );
+}
+
+#[test]
+fn wide_txid_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,5000000000,1.0
+deposit,1,5000000001,2.0
+dispute,1,5000000001,
+",
+    );
+    assert_eq!(
+        run([file.path()]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,2.0000,3.0000,false,1
+"
+    );
+}
+
+#[test]
+fn summary_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,2,2,2.0
+dispute,2,2,
+chargeback,2,2,
+withdrawal,1,3,5.0
+deposit,1,1,1.0
+",
+    );
+    // the rejected duplicate deposit is not counted
+    assert_eq!(
+        run([OsStr::new("--summary"), file.path().as_os_str()]),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+2,0.0000,0.0000,0.0000,true,0
+# 2 clients, 5 transactions, 1 locked
+"
+    );
+    let output = run_output([
+        OsStr::new("--summary"),
+        OsStr::new("--format"),
+        OsStr::new("json"),
+        file.path().as_os_str(),
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn on_repeat_deposit_works() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,2.0
+deposit,1,1,5.0
+",
+    );
+    let output = |mode| {
+        let output = run_output([
+            OsStr::new("--on-repeat-deposit"),
+            OsStr::new(mode),
+            file.path().as_os_str(),
+        ]);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let row = |total| {
+        format!(
+            "client,available,held,total,locked,disputed_count\n1,{0},0.0000,{0},false,0\n",
+            total
+        )
+    };
+    assert_eq!(output("reject"), row("3.0000"));
+    assert_eq!(output("overwrite"), row("7.0000"));
+    assert_eq!(output("sum"), row("8.0000"));
+}
+
+#[test]
+fn broken_pipe_exits_cleanly() {
+    // enough accounts for the output to overflow the pipe buffer
+    let mut contents = String::from("type,client,tx,amount\n");
+    for client in 1..=10_000 {
+        contents.push_str(&format!("deposit,{0},{0},1.0\n", client));
+    }
+    let file = transactions_file(&contents);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_coding-test"))
+        .arg(file.path())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut header = [0; 6];
+    stdout.read_exact(&mut header).unwrap();
+    assert_eq!(&header, b"client");
+    drop(stdout);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn limit_clients_samples_first_clients() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,2,1,1.0
+deposit,3,2,2.0
+deposit,1,3,3.0
+deposit,2,4,1.0
+withdrawal,3,5,0.5
+",
+    );
+    assert_eq!(
+        run([
+            OsStr::new("--limit-clients"),
+            OsStr::new("2"),
+            file.path().as_os_str()
+        ]),
+        "client,available,held,total,locked,disputed_count
+2,2.0000,0.0000,2.0000,false,0
+3,1.5000,0.0000,1.5000,false,0
+"
+    );
+}
+
+#[test]
+fn negative_amount_reported() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,-1.0
+deposit,1,1,1.0
+",
+    );
+    let output = run_output([OsStr::new("--verbose"), file.path().as_os_str()]);
+    assert!(output.status.success());
+    // the rejected row leaves its txid unused
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "client,available,held,total,locked,disputed_count
+1,1.0000,0.0000,1.0000,false,0
+"
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "{}: line 2 (byte 22): skipped row: amount -1 is negative",
+        file.path().display()
+    )));
+}
+
+#[test]
+fn checkpoint_every_uses_precision() {
+    let file = transactions_file(
+        "type,client,tx,amount
+deposit,1,1,1.0
+deposit,1,2,1.0
+",
+    );
+    let checkpoints = NamedTempFile::new().unwrap();
+    let output = run([
+        OsStr::new("--checkpoint-every"),
+        OsStr::new("1"),
+        OsStr::new("--checkpoint-output"),
+        checkpoints.path().as_os_str(),
+        OsStr::new("--precision"),
+        OsStr::new("8"),
+        file.path().as_os_str(),
+    ]);
+    assert_eq!(
+        output,
+        "client,available,held,total,locked,disputed_count
+1,2.00000000,0.00000000,2.00000000,false,0
+"
+    );
+    assert_eq!(
+        std::fs::read_to_string(checkpoints.path()).unwrap(),
+        "checkpoint after transaction 1
+client,available,held,total,locked,disputed_count
+1,1.00000000,0.00000000,1.00000000,false,0
+checkpoint after transaction 2
+client,available,held,total,locked,disputed_count
+1,2.00000000,0.00000000,2.00000000,false,0
+"
+    );
+}
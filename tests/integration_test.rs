@@ -1,35 +1,49 @@
+use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use std::collections::HashSet;
+use std::{collections::HashSet, fs::File, io::Write};
 
-use coding_test::{AccountSnapshot, AccountsHandler, ClientId, Transaction, TransactionType, Txid};
+use coding_test::{
+    read_csv_transactions, AccountSnapshot, AccountsHandler, Amount, ClientId, CsvOptions,
+    IgnoreReason, ProcessOutcome, Transaction, TransactionType, Txid, ValidationConfig,
+};
+
+/// Validate `value` as an amount of any scale, panicking if it is negative
+fn amount(value: Decimal) -> Amount {
+    Amount::try_from(value).expect("test amounts are non-negative")
+}
 
 #[test]
 fn document_test_passes() {
     let transactions = [
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(1.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(1.0))),
             client_id: ClientId(1),
             txid: Txid(1),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(2.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(2.0))),
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(2.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(2.0))),
             client_id: ClientId(1),
             txid: Txid(3),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Withdrawal(dec!(1.5)),
+            tx_type: TransactionType::Withdrawal(amount(dec!(1.5))),
             client_id: ClientId(1),
             txid: Txid(4),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Withdrawal(dec!(3.0)),
+            tx_type: TransactionType::Withdrawal(amount(dec!(3.0))),
             client_id: ClientId(2),
             txid: Txid(5),
+            timestamp: None,
         },
     ];
 
@@ -46,6 +60,7 @@ fn document_test_passes() {
             held: dec!(0),
             total: dec!(1.5),
             locked: false,
+            disputed_count: 0,
         },
         AccountSnapshot {
             id: ClientId(2),
@@ -53,6 +68,7 @@ fn document_test_passes() {
             held: dec!(0),
             total: dec!(2),
             locked: false,
+            disputed_count: 0,
         },
     ]);
     assert_eq!(
@@ -68,84 +84,100 @@ fn document_test_passes() {
 fn comprehensive_test_passes() {
     let transactions = [
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(1.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(1.0))),
             client_id: ClientId(1),
             txid: Txid(1),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(2.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(2.0))),
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(3.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(3.0))),
             client_id: ClientId(2),
             txid: Txid(7),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(2.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(2.0))),
             client_id: ClientId(1),
             txid: Txid(3),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(5.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(5.0))),
             client_id: ClientId(3),
             txid: Txid(8),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Withdrawal(dec!(1.5)),
+            tx_type: TransactionType::Withdrawal(amount(dec!(1.5))),
             client_id: ClientId(1),
             txid: Txid(4),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Deposit(dec!(3.0)),
+            tx_type: TransactionType::Deposit(amount(dec!(3.0))),
             client_id: ClientId(1),
             txid: Txid(9),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Withdrawal(dec!(3.0)),
+            tx_type: TransactionType::Withdrawal(amount(dec!(3.0))),
             client_id: ClientId(2),
             txid: Txid(5),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Dispute,
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Resolve,
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Dispute,
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Chargeback,
             client_id: ClientId(1),
             txid: Txid(1),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Chargeback,
             client_id: ClientId(2),
             txid: Txid(2),
+            timestamp: None,
         },
         Transaction {
-            tx_type: TransactionType::Withdrawal(dec!(1.0)),
+            tx_type: TransactionType::Withdrawal(amount(dec!(1.0))),
             client_id: ClientId(2),
             txid: Txid(6),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Dispute,
             client_id: ClientId(3),
             txid: Txid(8),
+            timestamp: None,
         },
         Transaction {
             tx_type: TransactionType::Dispute,
             client_id: ClientId(1),
             txid: Txid(3),
+            timestamp: None,
         },
     ];
 
@@ -155,6 +187,18 @@ fn comprehensive_test_passes() {
         let _ = accounts.submit_transaction(transaction);
     }
 
+    let collected: AccountsHandler = transactions.into_iter().collect();
+    assert_eq!(
+        collected.snapshot_accounts_sorted(),
+        accounts.snapshot_accounts_sorted()
+    );
+    let mut extended = AccountsHandler::new();
+    extended.extend(transactions);
+    assert_eq!(
+        extended.snapshot_accounts_sorted(),
+        accounts.snapshot_accounts_sorted()
+    );
+
     let snapshots = HashSet::from([
         AccountSnapshot {
             id: ClientId(1),
@@ -162,6 +206,7 @@ fn comprehensive_test_passes() {
             held: dec!(2.0),
             total: dec!(4.5),
             locked: false,
+            disputed_count: 1,
         },
         AccountSnapshot {
             id: ClientId(2),
@@ -169,6 +214,7 @@ fn comprehensive_test_passes() {
             held: dec!(0),
             total: dec!(0),
             locked: true,
+            disputed_count: 0,
         },
         AccountSnapshot {
             id: ClientId(3),
@@ -176,6 +222,7 @@ fn comprehensive_test_passes() {
             held: dec!(5.0),
             total: dec!(5.0),
             locked: false,
+            disputed_count: 1,
         },
     ]);
     assert_eq!(
@@ -186,3 +233,139 @@ fn comprehensive_test_passes() {
         snapshots
     );
 }
+
+#[test]
+fn excess_precision_ignored() {
+    let mut accounts = AccountsHandler::new();
+    assert_eq!(
+        accounts.submit_transaction(Transaction {
+            tx_type: TransactionType::Deposit(amount(dec!(1.23456))),
+            client_id: ClientId(1),
+            txid: Txid(1),
+            timestamp: None,
+        }),
+        Ok(ProcessOutcome::Ignored(IgnoreReason::ExcessPrecision))
+    );
+    assert_eq!(accounts.available(ClientId(1)), Some(dec!(0)));
+}
+
+#[test]
+fn type_casing_and_aliases_parse() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        file,
+        "type,client,tx,amount
+Deposit,1,1,5.0
+WITHDRAWAL,1,2,1.5
+credit,1,3,2.0
+Debit,1,4,0.5
+Dispute,1,3,
+"
+    )
+    .unwrap();
+
+    let mut accounts = AccountsHandler::new();
+    read_csv_transactions(
+        File::open(file.path()).unwrap(),
+        &ValidationConfig::default(),
+        CsvOptions::default(),
+        |_, tx| {
+            accounts.submit_transaction(tx.unwrap()).unwrap();
+        },
+    )
+    .unwrap();
+    assert_eq!(accounts.available(ClientId(1)), Some(dec!(3.0)));
+    assert_eq!(accounts.held(ClientId(1)), Some(dec!(2.0)));
+}
+
+#[test]
+fn tab_delimited_matches_comma_delimited() {
+    let csv = "type,client,tx,amount
+deposit,1,1,5.0
+withdrawal,1,2,1.5
+deposit,2,3,2.0
+dispute,2,3,
+";
+    let process = |input: String, delimiter| {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "{}", input).unwrap();
+        let mut accounts = AccountsHandler::new();
+        read_csv_transactions(
+            File::open(file.path()).unwrap(),
+            &ValidationConfig::default(),
+            CsvOptions {
+                delimiter,
+                ..CsvOptions::default()
+            },
+            |_, tx| {
+                accounts.submit_transaction(tx.unwrap()).unwrap();
+            },
+        )
+        .unwrap();
+        accounts.snapshot_accounts_sorted()
+    };
+    let snapshots = process(csv.to_string(), b',');
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(process(csv.replace(',', "\t"), b'\t'), snapshots);
+    assert_eq!(process(csv.replace(',', "|"), b'|'), snapshots);
+}
+
+#[test]
+fn strip_separators_works() {
+    let csv = "type,client,tx,amount
+deposit,1,1,\"1,000.50\"
+";
+    let mut accounts = AccountsHandler::new();
+    read_csv_transactions(
+        csv.as_bytes(),
+        &ValidationConfig::default(),
+        CsvOptions {
+            strip_separators: true,
+            ..CsvOptions::default()
+        },
+        |_, tx| {
+            accounts.submit_transaction(tx.unwrap()).unwrap();
+        },
+    )
+    .unwrap();
+    assert_eq!(accounts.available(ClientId(1)), Some(dec!(1000.50)));
+
+    let mut reasons = Vec::new();
+    read_csv_transactions(
+        csv.as_bytes(),
+        &ValidationConfig::default(),
+        CsvOptions::default(),
+        |_, tx| reasons.push(tx.unwrap_err()),
+    )
+    .unwrap();
+    assert_eq!(reasons, vec!["invalid amount: 1,000.50"]);
+}
+
+#[test]
+fn extra_columns_and_repeated_headers_are_skipped() {
+    let csv = "type,client,tx,amount,notes
+deposit,1,1,2.0,first deposit
+withdrawal,1,2,0.5,
+type,client,tx,amount,notes
+deposit,2,3,1.0,after a repeated header
+dispute,2,3,,chargeback pending
+";
+    let mut accounts = AccountsHandler::new();
+    let mut errors = Vec::new();
+    read_csv_transactions(
+        csv.as_bytes(),
+        &ValidationConfig::default(),
+        CsvOptions::default(),
+        |_, tx| match tx {
+            Ok(tx) => {
+                accounts.submit_transaction(tx).unwrap();
+            }
+            Err(e) => errors.push(e),
+        },
+    )
+    .unwrap();
+    assert!(errors.is_empty());
+    assert_eq!(accounts.available(ClientId(1)), Some(dec!(1.5)));
+    assert_eq!(accounts.available(ClientId(2)), Some(dec!(0)));
+    assert_eq!(accounts.held(ClientId(2)), Some(dec!(1.0)));
+}
@@ -0,0 +1,55 @@
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+
+use coding_test::{AccountsHandler, Amount, ClientId, Transaction, TransactionType, Txid};
+
+/// Generate a transaction for one of a few clients, referencing one of a few txids so that
+/// disputes, resolves and chargebacks often hit an earlier deposit or withdrawal
+fn transaction() -> impl Strategy<Value = Transaction> {
+    // amounts up to 1000 at 4 decimal places
+    let amount = (1..=10_000_000i64).prop_map(|units| Amount::new(Decimal::new(units, 4)).unwrap());
+    let tx_type = prop_oneof![
+        3 => amount.clone().prop_map(TransactionType::Deposit),
+        2 => amount.prop_map(TransactionType::Withdrawal),
+        2 => Just(TransactionType::Dispute),
+        1 => Just(TransactionType::Resolve),
+        1 => Just(TransactionType::Chargeback),
+    ];
+    (tx_type, 1..=3u16, 1..=20u64).prop_map(|(tx_type, client_id, txid)| Transaction {
+        tx_type,
+        client_id: ClientId(client_id),
+        txid: Txid(txid),
+        timestamp: None,
+    })
+}
+
+proptest! {
+    #[test]
+    fn invariants_hold_after_every_transaction(
+        txs in prop::collection::vec(transaction(), 1..100)
+    ) {
+        let mut accounts = AccountsHandler::new();
+        for tx in txs {
+            let _ = accounts.submit_transaction(tx);
+            prop_assert_eq!(accounts.validate_invariants(), Ok(()));
+
+            let client_id = tx.client_id;
+            let (Some(available), Some(held)) =
+                (accounts.available(client_id), accounts.held(client_id))
+            else {
+                continue;
+            };
+            prop_assert_eq!(accounts.total(client_id), Some(available + held));
+            // the held balance is exactly what the open disputes hold
+            let disputed: Decimal = accounts
+                .disputed_breakdown(client_id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(_, held)| held)
+                .sum();
+            prop_assert_eq!(held, disputed);
+            // disputes may not drive the total negative under the default policy
+            prop_assert!(available + held >= Decimal::ZERO);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+/// Dropping the result of a mutating call fails to compile under `#![deny(unused_must_use)]`
+#[test]
+fn dropped_results_warn() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
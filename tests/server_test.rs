@@ -0,0 +1,102 @@
+#![cfg(feature = "server")]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+};
+
+/// Server process, killed once dropped
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    /// Start the server on a free port, waiting until it listens
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_server"))
+            .arg("127.0.0.1:0")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        BufReader::new(child.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("listening on ")
+            .unwrap()
+            .to_string();
+        Self { child, addr }
+    }
+
+    /// Send a request, returning the status line and body of the response
+    fn request(&self, method: &str, path: &str, body: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            self.addr,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn posted_transactions_are_read_back() {
+    let server = Server::start();
+    let (status, body) = server.request(
+        "POST",
+        "/transactions",
+        r#"[
+            {"type": "deposit", "client": 2, "tx": 1, "amount": "2.0"},
+            {"type": "deposit", "client": 1, "tx": 2, "amount": "1.5"},
+            {"type": "dispute", "client": 2, "tx": 1},
+            {"type": "deposit", "client": 1, "tx": 2, "amount": "1.0"}
+        ]"#,
+    );
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["submitted"], 3);
+    assert_eq!(body["errors"].as_array().unwrap().len(), 1);
+    assert!(body["errors"][0].as_str().unwrap().starts_with("3: "));
+
+    let (status, body) = server.request("GET", "/accounts", "");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let accounts: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        accounts,
+        serde_json::json!([
+            {"client": 1, "available": "1.5000", "held": "0.0000", "total": "1.5000", "locked": false, "disputed_count": 0},
+            {"client": 2, "available": "0.0000", "held": "2.0000", "total": "2.0000", "locked": false, "disputed_count": 1}
+        ])
+    );
+}
+
+#[test]
+fn malformed_requests_fail() {
+    let server = Server::start();
+    let (status, _) = server.request("POST", "/transactions", "not json");
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    let (status, _) = server.request("DELETE", "/accounts", "");
+    assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+    let (status, _) = server.request("GET", "/", "");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+}
@@ -0,0 +1,18 @@
+#![deny(unused_must_use)]
+
+use coding_test::{AccountsHandler, Amount, ClientId, Transaction, TransactionType, Txid};
+use rust_decimal::Decimal;
+
+fn main() {
+    let mut accounts = AccountsHandler::new();
+    let tx = Transaction {
+        tx_type: TransactionType::Deposit(Amount::new(Decimal::ONE).unwrap()),
+        client_id: ClientId(1),
+        txid: Txid(1),
+        timestamp: None,
+    };
+    accounts.submit_transaction(tx);
+    accounts.submit_batch([tx]);
+    accounts.deposit(ClientId(1), Amount::new(Decimal::ONE).unwrap());
+    accounts.freeze_account(ClientId(1));
+}